@@ -1,12 +1,22 @@
-use crate::{accounts::CrossMarginAccount, CrossMarginError};
+use crate::{
+    accounts::{
+        CrossMarginAccount, CrossMarginAccountChange, CrossMarginAccountEvent,
+        CrossMarginAccountEventsBroadcaster, CrossMarginAccountTradingState,
+        ACCOUNT_EVENTS_CAPACITY,
+    },
+    CrossMarginError,
+};
 
 use super::AccountsStore;
 
+// Raw account mutations, they are not deduplicated by process id. Retried commands go through
+// CrossMarginCaches, which remembers their results.
 pub struct AccountsCache<T>
 where
     T: CrossMarginAccount + Clone,
 {
     pub accounts_store: AccountsStore<T>,
+    pub events: CrossMarginAccountEventsBroadcaster<T>,
 }
 
 impl<T> AccountsCache<T>
@@ -16,6 +26,7 @@ where
     pub fn new(accounts: Vec<T>) -> Self {
         AccountsCache {
             accounts_store: AccountsStore::new(accounts),
+            events: CrossMarginAccountEventsBroadcaster::new(ACCOUNT_EVENTS_CAPACITY),
         }
     }
 
@@ -30,6 +41,15 @@ where
         return self.accounts_store.add_account(account);
     }
 
+    // Account subscribers are closed together with the account.
+    pub fn remove_account(&mut self, account_id: &str) -> Option<T> {
+        let account = self.accounts_store.remove_account(account_id)?;
+        self.events.remove_account(account_id);
+        metrics::gauge!("accounts_in_cache").decrement(1);
+
+        return Some(account);
+    }
+
     pub async fn get_accounts(&self, account: &[&str]) -> Vec<T> {
         let mut result = vec![];
 
@@ -63,20 +83,29 @@ where
         delta: f64,
         process_id: &str,
         allow_negative_balance: bool,
+        expected_version: Option<u64>,
     ) -> Result<T, CrossMarginError> {
+        let previous = self.get_previous(account_id);
         let result = self
             .accounts_store
-            .update_account(account_id, process_id, |account| {
-                if account.get_balance() + delta < 0.0 && allow_negative_balance {
-                    return Some(Err(CrossMarginError::NotEnoughBalance));
+            .update_account(account_id, process_id, expected_version, |account| {
+                if account.get_balance() + delta < 0.0 && !allow_negative_balance {
+                    return None;
                 }
 
                 account.update_balance(delta);
 
-                return Some(Ok(account.clone()));
+                return Some(account.clone());
             })
             .await?
-            .ok_or(CrossMarginError::AccountNotFound)??;
+            .ok_or(CrossMarginError::NotEnoughBalance)?;
+
+        self.publish(
+            previous,
+            &result,
+            process_id,
+            CrossMarginAccountChange::Balance { delta },
+        );
 
         return Ok(result);
     }
@@ -86,10 +115,12 @@ where
         account_id: &str,
         trading_disabled: bool,
         process_id: &str,
+        expected_version: Option<u64>,
     ) -> Result<T, CrossMarginError> {
+        let previous = self.get_previous(account_id);
         let result = self
             .accounts_store
-            .update_account(account_id, process_id, |account| {
+            .update_account(account_id, process_id, expected_version, |account| {
                 account.set_trading_disabled(trading_disabled);
 
                 return Some(account.clone());
@@ -97,6 +128,41 @@ where
             .await?
             .ok_or(CrossMarginError::AccountNotFound)?;
 
+        self.publish(
+            previous,
+            &result,
+            process_id,
+            CrossMarginAccountChange::TradingDisabled,
+        );
+
+        return Ok(result);
+    }
+
+    pub async fn update_trading_state(
+        &mut self,
+        account_id: &str,
+        trading_state: CrossMarginAccountTradingState,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<T, CrossMarginError> {
+        let previous = self.get_previous(account_id);
+        let result = self
+            .accounts_store
+            .update_account(account_id, process_id, expected_version, |account| {
+                account.set_trading_state(trading_state);
+
+                return Some(account.clone());
+            })
+            .await?
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        self.publish(
+            previous,
+            &result,
+            process_id,
+            CrossMarginAccountChange::TradingState,
+        );
+
         return Ok(result);
     }
 
@@ -105,10 +171,12 @@ where
         account_id: &str,
         trading_group: &str,
         process_id: &str,
+        expected_version: Option<u64>,
     ) -> Result<T, CrossMarginError> {
+        let previous = self.get_previous(account_id);
         let result = self
             .accounts_store
-            .update_account(account_id, process_id, |account| {
+            .update_account(account_id, process_id, expected_version, |account| {
                 account.update_trading_group(trading_group.to_string());
 
                 return Some(account.clone());
@@ -116,18 +184,27 @@ where
             .await?
             .ok_or(CrossMarginError::AccountNotFound)?;
 
+        self.publish(
+            previous,
+            &result,
+            process_id,
+            CrossMarginAccountChange::TradingGroup,
+        );
+
         return Ok(result);
     }
 
     pub async fn update_leverage(
         &mut self,
         account_id: &str,
-        leverage: f64,
+        leverage: Option<f64>,
         process_id: &str,
+        expected_version: Option<u64>,
     ) -> Result<T, CrossMarginError> {
+        let previous = self.get_previous(account_id);
         let result = self
             .accounts_store
-            .update_account(account_id, process_id, |account| {
+            .update_account(account_id, process_id, expected_version, |account| {
                 account.update_leverage(leverage);
 
                 return Some(account.clone());
@@ -135,18 +212,190 @@ where
             .await?
             .ok_or(CrossMarginError::AccountNotFound)?;
 
+        self.publish(
+            previous,
+            &result,
+            process_id,
+            CrossMarginAccountChange::Leverage,
+        );
+
         return Ok(result);
     }
 
+    // Previous value is cloned only when somebody listens to the account.
+    fn get_previous(&self, account_id: &str) -> Option<T> {
+        if !self.events.has_subscribers(account_id) {
+            return None;
+        }
+
+        return self.get_account(account_id).cloned();
+    }
+
+    fn publish(
+        &mut self,
+        previous: Option<T>,
+        current: &T,
+        process_id: &str,
+        change: CrossMarginAccountChange,
+    ) {
+        let Some(previous) = previous else {
+            return;
+        };
+
+        self.events.publish(CrossMarginAccountEvent {
+            account_id: current.get_id().to_string(),
+            process_id: process_id.to_string(),
+            change,
+            previous,
+            current: current.clone(),
+        });
+    }
+
     pub async fn update_accounts<F>(
         &mut self,
         ids: &[&str],
         process_id: &str,
         update_command: impl Fn(&mut T) -> Option<F>,
     ) -> Vec<F> {
-        return self
+        let previous: Vec<T> = ids.iter().filter_map(|id| self.get_previous(id)).collect();
+
+        let result = self
             .accounts_store
             .update_accounts(ids, process_id, update_command)
             .await;
+
+        for previous in previous {
+            let Some(current) = self.get_account(previous.get_id()).cloned() else {
+                continue;
+            };
+
+            // declined commands restore the version
+            if current.get_version() != previous.get_version() {
+                self.publish(
+                    Some(previous),
+                    &current,
+                    process_id,
+                    CrossMarginAccountChange::Bulk,
+                );
+            }
+        }
+
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        accounts::{CrossMarginAccount, CrossMarginAccountChange},
+        test_utils::TestAccount,
+        CrossMarginError,
+    };
+
+    use super::AccountsCache;
+
+    #[tokio::test]
+    async fn test_balance_update_is_published() {
+        let mut cache = AccountsCache::new(vec![TestAccount::new("acc", 1000.0, 100.0)]);
+        let mut subscriber = cache.events.subscribe_account("acc");
+
+        cache
+            .update_balance("acc", -100.0, "withdraw", false, None)
+            .await
+            .unwrap();
+
+        let event = subscriber.recv().await.unwrap();
+        assert_eq!(event.process_id, "withdraw");
+        assert_eq!(
+            event.change,
+            CrossMarginAccountChange::Balance { delta: -100.0 }
+        );
+        assert_eq!(event.previous.get_balance(), 1000.0);
+        assert_eq!(event.current.get_balance(), 900.0);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_update_is_published() {
+        let mut cache = AccountsCache::new(vec![
+            TestAccount::new("acc", 1000.0, 100.0),
+            TestAccount::new("other", 1000.0, 100.0),
+        ]);
+        let mut subscriber = cache.events.subscribe_all();
+
+        cache
+            .update_accounts(&["acc", "other"], "bulk", |account| {
+                if account.get_id() != "acc" {
+                    return None;
+                }
+
+                account.update_balance(50.0);
+                return Some(());
+            })
+            .await;
+
+        let event = subscriber.recv().await.unwrap();
+        assert_eq!(event.account_id, "acc");
+        assert_eq!(event.change, CrossMarginAccountChange::Bulk);
+        assert_eq!(event.current.get_balance(), 1050.0);
+    }
+
+    #[tokio::test]
+    async fn test_removed_account_closes_subscribers() {
+        let mut cache = AccountsCache::new(vec![TestAccount::new("acc", 1000.0, 100.0)]);
+        let mut subscriber = cache.events.subscribe_account("acc");
+
+        assert!(cache.remove_account("acc").is_some());
+        assert!(cache.get_account("acc").is_none());
+        assert!(cache.get_trader_accounts("trader-acc").await.is_none());
+        assert!(subscriber.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_negative_balance_requires_permission() {
+        let mut cache = AccountsCache::new(vec![TestAccount::new("acc", 1000.0, 100.0)]);
+
+        let result = cache
+            .update_balance("acc", -1500.0, "withdraw", false, None)
+            .await;
+        assert!(matches!(result, Err(CrossMarginError::NotEnoughBalance)));
+        assert_eq!(cache.get_account("acc").unwrap().get_balance(), 1000.0);
+
+        let account = cache
+            .update_balance("acc", -1500.0, "loss", true, None)
+            .await
+            .unwrap();
+        assert_eq!(account.get_balance(), -500.0);
+    }
+
+    #[tokio::test]
+    async fn test_stale_version_is_rejected() {
+        let mut cache = AccountsCache::new(vec![TestAccount::new("acc", 1000.0, 100.0)]);
+
+        let account = cache
+            .update_leverage("acc", Some(200.0), "first", Some(0))
+            .await
+            .unwrap();
+        assert_eq!(account.get_version(), 1);
+
+        let result = cache
+            .update_leverage("acc", Some(300.0), "second", Some(0))
+            .await;
+        assert!(matches!(
+            result,
+            Err(CrossMarginError::VersionMismatch {
+                expected: 0,
+                actual: 1
+            })
+        ));
+        assert_eq!(
+            cache.get_account("acc").unwrap().get_leverage(),
+            Some(200.0)
+        );
+
+        let result = cache
+            .update_balance("acc", -2000.0, "withdraw", false, None)
+            .await;
+        assert!(matches!(result, Err(CrossMarginError::NotEnoughBalance)));
+        assert_eq!(cache.get_account("acc").unwrap().get_version(), 1);
     }
 }
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{accounts::CrossMarginAccount, flows::AccountCalculationResult};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginInstrumentMarginState {
+    pub margin: f64,
+    pub pl: f64,
+    pub swaps: f64,
+}
+
+// Account values the margin states depend on, states calculated with other values are stale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossMarginAccountMarginKey {
+    pub trading_group: String,
+    pub leverage: Option<f64>,
+    pub instruments_leverages: HashMap<String, f64>,
+}
+
+impl CrossMarginAccountMarginKey {
+    pub fn new(account: &impl CrossMarginAccount) -> Self {
+        Self {
+            trading_group: account.get_trading_group().to_string(),
+            leverage: account.get_leverage(),
+            instruments_leverages: account.get_instruments_leverages().clone(),
+        }
+    }
+
+    pub fn matches(&self, account: &impl CrossMarginAccount) -> bool {
+        return self.trading_group == account.get_trading_group()
+            && self.leverage == account.get_leverage()
+            && &self.instruments_leverages == account.get_instruments_leverages();
+    }
+}
+
+struct CrossMarginAccountMarginStates {
+    key: CrossMarginAccountMarginKey,
+    instruments: HashMap<String, CrossMarginInstrumentMarginState>,
+}
+
+// Per account, per instrument margin and PnL. Only instruments with changed positions are recalculated,
+// states are dropped when the margin configuration revision of instruments or trading groups changes.
+// Account changes are detected on read, so accounts updated directly in the accounts cache are not served stale.
+pub struct CrossMarginAccountsMarginCache {
+    // ACCOUNT_ID - STATES
+    states: HashMap<String, CrossMarginAccountMarginStates>,
+    // (INSTRUMENTS, TRADING_GROUPS)
+    revision: (u64, u64),
+}
+
+impl CrossMarginAccountsMarginCache {
+    pub fn new() -> Self {
+        Self {
+            states: HashMap::new(),
+            revision: (0, 0),
+        }
+    }
+
+    pub fn ensure_revision(&mut self, instruments_revision: u64, trading_groups_revision: u64) {
+        let revision = (instruments_revision, trading_groups_revision);
+
+        if self.revision != revision {
+            self.states.clear();
+            self.revision = revision;
+        }
+    }
+
+    pub fn get_revision(&self) -> (u64, u64) {
+        return self.revision;
+    }
+
+    // Cached states are only valid for the account values they were calculated with.
+    pub fn has_account(&self, account: &impl CrossMarginAccount) -> bool {
+        return self
+            .states
+            .get(account.get_id())
+            .is_some_and(|x| x.key.matches(account));
+    }
+
+    pub fn set_account(
+        &mut self,
+        account: &impl CrossMarginAccount,
+        states: HashMap<String, CrossMarginInstrumentMarginState>,
+    ) {
+        self.states.insert(
+            account.get_id().to_string(),
+            CrossMarginAccountMarginStates {
+                key: CrossMarginAccountMarginKey::new(account),
+                instruments: states,
+            },
+        );
+    }
+
+    // None removes the instrument, accounts which are not cached yet are skipped.
+    pub fn update_instrument(
+        &mut self,
+        account_id: &str,
+        instrument_id: &str,
+        state: Option<CrossMarginInstrumentMarginState>,
+    ) {
+        let Some(account_states) = self.states.get_mut(account_id) else {
+            return;
+        };
+
+        match state {
+            Some(state) => {
+                account_states
+                    .instruments
+                    .insert(instrument_id.to_string(), state);
+            }
+            None => {
+                account_states.instruments.remove(instrument_id);
+            }
+        }
+    }
+
+    pub fn invalidate_account(&mut self, account_id: &str) {
+        self.states.remove(account_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+
+    pub fn get_instruments(
+        &self,
+        account: &impl CrossMarginAccount,
+    ) -> Option<&HashMap<String, CrossMarginInstrumentMarginState>> {
+        let states = self.states.get(account.get_id())?;
+
+        if !states.key.matches(account) {
+            return None;
+        }
+
+        return Some(&states.instruments);
+    }
+
+    pub fn get_summary(
+        &self,
+        account: &impl CrossMarginAccount,
+        reserved_margin: f64,
+    ) -> Option<AccountCalculationResult> {
+        let states = self.get_instruments(account)?.values();
+
+        return Some(AccountCalculationResult::new(
+            account.get_balance(),
+            states.clone().map(|x| x.margin).sum(),
+            states.map(|x| x.pl + x.swaps).sum(),
+            reserved_margin,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        flows::calculate_account_data,
+        test_utils::{bid_ask, create_caches, eurusd, TestAccount, TestActivePosition, TestCaches},
+        CrossMarginPositionSide, CrossMarginPositionsCacheQueryBuilder, CrossMarginTradingGroup,
+    };
+
+    fn assert_summary_matches(caches: &TestCaches, account_id: &str) {
+        let cached = caches.get_account_summary(account_id).unwrap();
+
+        let account = caches.accounts_cache.get_account(account_id).unwrap();
+        let positions = caches
+            .active_positions_cache
+            .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id));
+        let expected = calculate_account_data(
+            account,
+            &positions,
+            &caches.instruments_cache,
+            &caches.trading_groups_cache,
+        )
+        .unwrap();
+
+        assert!((cached.margin - expected.margin).abs() < 1e-9);
+        assert!((cached.equity - expected.equity).abs() < 1e-9);
+        assert!((cached.margin_level - expected.margin_level).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_cached_summary_matches_full_calculation() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("a", 10_000.0, 100.0)]).await;
+
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "1",
+                    "a",
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    100_000.0,
+                    1.1002,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+        assert_summary_matches(&caches, "a");
+
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "2",
+                    "a",
+                    &instrument,
+                    CrossMarginPositionSide::Sell,
+                    50_000.0,
+                    1.1,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+        caches
+            .handle_bid_ask(bid_ask(&instrument, 1.101, 1.1012), "tick")
+            .await;
+        assert_summary_matches(&caches, "a");
+
+        caches
+            .remove_active_position("1", "close", None)
+            .await
+            .unwrap();
+        assert_summary_matches(&caches, "a");
+
+        let mut group = CrossMarginTradingGroup::new("vip");
+        group.leverage = 10.0;
+        caches.trading_groups_cache.update_group(group);
+        caches
+            .update_account_trading_group("a", "vip", "group", None)
+            .await
+            .unwrap();
+        assert_summary_matches(&caches, "a");
+
+        caches
+            .update_account_leverage("a", Some(20.0), "leverage", None)
+            .await
+            .unwrap();
+        assert_summary_matches(&caches, "a");
+    }
+
+    #[tokio::test]
+    async fn test_direct_account_change_is_not_served_stale() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("a", 10_000.0, 100.0)]).await;
+
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "1",
+                    "a",
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    100_000.0,
+                    1.1002,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+        assert_eq!(caches.get_account_summary("a").unwrap().margin, 1000.0);
+
+        caches
+            .accounts_cache
+            .update_leverage("a", Some(50.0), "leverage", None)
+            .await
+            .unwrap();
+        assert_eq!(caches.get_account_summary("a").unwrap().margin, 2000.0);
+        assert_summary_matches(&caches, "a");
+
+        // next position change recalculates the whole account with the new leverage
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "2",
+                    "a",
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    100_000.0,
+                    1.1002,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+        let account = caches.accounts_cache.get_account("a").unwrap();
+        assert!(caches.accounts_margin_cache.has_account(account));
+        assert_eq!(caches.get_account_summary("a").unwrap().margin, 4000.0);
+    }
+}
@@ -66,6 +66,20 @@ where
         return account;
     }
 
+    pub fn remove_account(&mut self, account_id: &str) -> Option<T> {
+        let account = self.id_account_index.remove(account_id)?;
+
+        if let Some(trader_accounts) = self.trader_index.get_mut(account.get_trader_id()) {
+            trader_accounts.retain(|x| x != account_id);
+
+            if trader_accounts.is_empty() {
+                self.trader_index.remove(account.get_trader_id());
+            }
+        }
+
+        return Some(account);
+    }
+
     pub async fn update_accounts<F>(
         &mut self,
         ids: &[&str],
@@ -79,8 +93,13 @@ where
             let account = self.id_account_index.get_mut(id.to_owned());
 
             if let Some(account) = account {
-                if let Some(value) = update_command(account) {
-                    result.push(value);
+                // version is bumped upfront so the command result sees it
+                let version = account.get_version();
+                account.set_version(version + 1);
+
+                match update_command(account) {
+                    Some(value) => result.push(value),
+                    None => account.set_version(version),
                 }
                 account.track_update(process_id, update_date);
             }
@@ -93,6 +112,7 @@ where
         &mut self,
         id: &str,
         process_id: &str,
+        expected_version: Option<u64>,
         update_command: impl Fn(&mut T) -> Option<F>,
     ) -> Result<Option<F>, CrossMarginError> {
         let update_date = DateTimeAsMicroseconds::now();
@@ -100,7 +120,15 @@ where
         let account = self.id_account_index.get_mut(id);
 
         if let Some(account) = account {
+            check_version(account.get_version(), expected_version)?;
+
+            let version = account.get_version();
+            account.set_version(version + 1);
+
             let result = update_command(account);
+            if result.is_none() {
+                account.set_version(version);
+            }
             account.track_update(process_id, update_date);
 
             return Ok(result);
@@ -113,3 +141,13 @@ where
         self.id_account_index.values().collect()
     }
 }
+
+// None skips the check.
+pub fn check_version(actual: u64, expected: Option<u64>) -> Result<(), CrossMarginError> {
+    match expected {
+        Some(expected) if expected != actual => {
+            return Err(CrossMarginError::VersionMismatch { expected, actual })
+        }
+        _ => return Ok(()),
+    }
+}
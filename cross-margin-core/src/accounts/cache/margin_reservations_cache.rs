@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginMarginReservation {
+    pub order_id: String,
+    pub account_id: String,
+    pub margin: f64,
+    // Source instruments of the margin rate, the reservation is re-priced when they tick.
+    pub sources: Vec<String>,
+}
+
+// Margin held by pending orders, it lowers the account free margin until the order is executed or cancelled.
+pub struct CrossMarginMarginReservationsCache {
+    // ORDER_ID - RESERVATION
+    reservations: HashMap<String, CrossMarginMarginReservation>,
+    // ACCOUNT_ID - [ORDER_ID]
+    accounts: HashMap<String, HashSet<String>>,
+    // SOURCE_ID - [ORDER_ID]
+    sources: HashMap<String, HashSet<String>>,
+}
+
+impl CrossMarginMarginReservationsCache {
+    pub fn new() -> Self {
+        Self {
+            reservations: HashMap::new(),
+            accounts: HashMap::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    pub fn reserve(&mut self, reservation: CrossMarginMarginReservation) {
+        self.release(&reservation.order_id);
+
+        self.accounts
+            .entry(reservation.account_id.clone())
+            .or_default()
+            .insert(reservation.order_id.clone());
+
+        for source in reservation.sources.iter() {
+            self.sources
+                .entry(source.clone())
+                .or_default()
+                .insert(reservation.order_id.clone());
+        }
+
+        self.reservations
+            .insert(reservation.order_id.clone(), reservation);
+    }
+
+    pub fn release(&mut self, order_id: &str) -> Option<CrossMarginMarginReservation> {
+        let reservation = self.reservations.remove(order_id)?;
+
+        if let Some(orders) = self.accounts.get_mut(&reservation.account_id) {
+            orders.remove(order_id);
+
+            if orders.is_empty() {
+                self.accounts.remove(&reservation.account_id);
+            }
+        }
+
+        for source in reservation.sources.iter() {
+            if let Some(orders) = self.sources.get_mut(source) {
+                orders.remove(order_id);
+
+                if orders.is_empty() {
+                    self.sources.remove(source);
+                }
+            }
+        }
+
+        return Some(reservation);
+    }
+
+    pub fn get_reservation(&self, order_id: &str) -> Option<&CrossMarginMarginReservation> {
+        return self.reservations.get(order_id);
+    }
+
+    pub fn get_account_reservations(&self, account_id: &str) -> Vec<&CrossMarginMarginReservation> {
+        let Some(orders) = self.accounts.get(account_id) else {
+            return vec![];
+        };
+
+        return orders
+            .iter()
+            .filter_map(|x| self.reservations.get(x))
+            .collect();
+    }
+
+    // Summed from the account reservations, so no drift accumulates from float subtraction.
+    pub fn get_reserved_margin(&self, account_id: &str) -> f64 {
+        return self
+            .get_account_reservations(account_id)
+            .iter()
+            .map(|x| x.margin)
+            .sum();
+    }
+
+    pub fn get_source_orders(&self, source_id: &str) -> Vec<String> {
+        return self
+            .sources
+            .get(source_id)
+            .map(|x| x.iter().cloned().collect())
+            .unwrap_or_default();
+    }
+
+    pub fn get_all(&self) -> Vec<&CrossMarginMarginReservation> {
+        return self.reservations.values().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{bid_ask, create_caches, eurusd, TestAccount, TestPendingPosition},
+        CrossMarginError, CrossMarginPendingPositionType,
+    };
+
+    use super::{CrossMarginMarginReservation, CrossMarginMarginReservationsCache};
+
+    #[test]
+    fn test_release_updates_account_and_source_indexes() {
+        let mut cache = CrossMarginMarginReservationsCache::new();
+        for (order_id, account_id, margin) in [("1", "a", 10.0), ("2", "a", 20.0), ("3", "b", 5.0)]
+        {
+            cache.reserve(CrossMarginMarginReservation {
+                order_id: order_id.to_string(),
+                account_id: account_id.to_string(),
+                margin,
+                sources: vec!["EURUSD".to_string()],
+            });
+        }
+        assert_eq!(cache.get_reserved_margin("a"), 30.0);
+        assert_eq!(cache.get_source_orders("EURUSD").len(), 3);
+
+        cache.release("1");
+        assert_eq!(cache.get_reserved_margin("a"), 20.0);
+        assert_eq!(cache.get_source_orders("EURUSD").len(), 2);
+
+        cache.release("2");
+        cache.release("3");
+        assert_eq!(cache.get_reserved_margin("a"), 0.0);
+        assert!(cache.get_source_orders("EURUSD").is_empty());
+        assert!(cache.release("3").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pending_orders_reserve_margin() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.reserve_pending_margin = true;
+
+        let order = |id: &str| {
+            TestPendingPosition::new(
+                id,
+                "acc",
+                &instrument,
+                CrossMarginPendingPositionType::BuyLimit,
+                50_000.0,
+                1.05,
+            )
+        };
+
+        caches
+            .add_pending_position(order("1"), "place")
+            .await
+            .unwrap();
+        let reserved = caches.margin_reservations.get_reserved_margin("acc");
+        assert!((reserved - 550.1).abs() < 1e-9);
+
+        let summary = caches.get_account_summary("acc").unwrap();
+        assert!((summary.free_margin - (1000.0 - reserved)).abs() < 1e-9);
+
+        let result = caches.add_pending_position(order("2"), "place").await;
+        assert!(matches!(result, Err(CrossMarginError::NotEnoughBalance)));
+
+        caches.remove_pending_position("1", "cancel").await.unwrap();
+        assert_eq!(caches.margin_reservations.get_reserved_margin("acc"), 0.0);
+
+        caches
+            .add_pending_position(order("2"), "place")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reservations_follow_price_leverage_and_removed_orders() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 10_000.0, 100.0)]).await;
+        caches.settings.reserve_pending_margin = true;
+
+        caches
+            .add_pending_position(
+                TestPendingPosition::new(
+                    "1",
+                    "acc",
+                    &instrument,
+                    CrossMarginPendingPositionType::BuyLimit,
+                    50_000.0,
+                    1.05,
+                ),
+                "place",
+            )
+            .await
+            .unwrap();
+        assert!((caches.get_reserved_margin("acc") - 550.1).abs() < 1e-9);
+
+        // 50_000 * 1.2002 / 100
+        caches
+            .handle_bid_ask(bid_ask(&instrument, 1.2, 1.2002), "tick")
+            .await;
+        assert!((caches.get_reserved_margin("acc") - 600.1).abs() < 1e-9);
+
+        caches
+            .update_account_leverage("acc", Some(50.0), "leverage", None)
+            .await
+            .unwrap();
+        assert!((caches.get_reserved_margin("acc") - 1200.2).abs() < 1e-9);
+
+        // removed behind the engine back, the reservation no longer counts and is dropped on the next tick
+        caches.pending_positions_cache.remove_position("1");
+        assert_eq!(caches.get_reserved_margin("acc"), 0.0);
+
+        caches
+            .handle_bid_ask(bid_ask(&instrument, 1.21, 1.2102), "tick")
+            .await;
+        assert!(caches.margin_reservations.get_reservation("1").is_none());
+    }
+}
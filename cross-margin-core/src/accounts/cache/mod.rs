@@ -1,5 +1,9 @@
 mod accounts_cache;
+mod accounts_margin_cache;
 mod accounts_store;
+mod margin_reservations_cache;
 
 pub use accounts_cache::*;
-pub use accounts_store::*;
\ No newline at end of file
+pub use accounts_margin_cache::*;
+pub use accounts_store::*;
+pub use margin_reservations_cache::*;
\ No newline at end of file
@@ -5,26 +5,97 @@ use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
 
 use crate::{
     flows::{calculate_account_data, AccountCalculationResult},
+    instruments::CrossMarginInstrumentsCache,
     positions::CrossMarginActivePosition,
+    trading_groups::CrossMarginTradingGroupsCache,
+    CrossMarginError,
 };
 
+use super::CrossMarginAccountTradingState;
+
 pub trait CrossMarginAccount: Clone + Serialize + DeserializeOwned {
     fn get_trader_id(&self) -> &str;
     fn get_id(&self) -> &str;
-    fn get_stop_out(&self) -> f64;
+    // None means the trading group value is used.
+    fn get_stop_out(&self) -> Option<f64>;
+    fn get_margin_call(&self) -> Option<f64>;
     fn get_balance(&self) -> f64;
     fn get_currency(&self) -> &str;
-    fn get_leverage(&self) -> f64;
+    fn get_leverage(&self) -> Option<f64>;
     fn get_instruments_leverages(&self) -> &HashMap<String, f64>;
+    fn get_trading_group(&self) -> &str;
     fn update_balance(&mut self, delta: f64);
     fn update_trading_group(&mut self, new_group: String);
-    fn update_leverage(&mut self, leverage: f64);
-    fn set_trading_disabled(&mut self, disabled: bool);
+    fn update_leverage(&mut self, leverage: Option<f64>);
+    fn get_trading_state(&self) -> CrossMarginAccountTradingState;
+    fn set_trading_state(&mut self, state: CrossMarginAccountTradingState);
+    // Enabling only lifts the Disabled state, CloseOnly and Frozen are kept.
+    fn set_trading_disabled(&mut self, disabled: bool) {
+        if disabled {
+            self.set_trading_state(CrossMarginAccountTradingState::Disabled);
+            return;
+        }
+
+        if self.get_trading_state() == CrossMarginAccountTradingState::Disabled {
+            self.set_trading_state(CrossMarginAccountTradingState::Active);
+        }
+    }
     fn track_update(&mut self, process_id: &str, date: DateTimeAsMicroseconds);
+    // Incremented by the cache on each mutation, used for optimistic concurrency.
+    fn get_version(&self) -> u64;
+    fn set_version(&mut self, version: u64);
     fn calculate_account_margin_props(
         &self,
         positions: &Vec<&impl CrossMarginActivePosition>,
-    ) -> AccountCalculationResult {
-        return calculate_account_data(self, positions);
+        instruments_cache: &CrossMarginInstrumentsCache,
+        trading_groups_cache: &CrossMarginTradingGroupsCache,
+    ) -> Result<AccountCalculationResult, CrossMarginError> {
+        return calculate_account_data(self, positions, instruments_cache, trading_groups_cache);
+    }
+    // Pending orders reservation is taken from the free margin of the props above.
+    fn calculate_account_margin_props_with_reservations(
+        &self,
+        positions: &Vec<&impl CrossMarginActivePosition>,
+        instruments_cache: &CrossMarginInstrumentsCache,
+        trading_groups_cache: &CrossMarginTradingGroupsCache,
+        reserved_margin: f64,
+    ) -> Result<AccountCalculationResult, CrossMarginError> {
+        let mut props = self.calculate_account_margin_props(
+            positions,
+            instruments_cache,
+            trading_groups_cache,
+        )?;
+        props.free_margin -= reserved_margin;
+        props.reserved_margin += reserved_margin;
+
+        return Ok(props);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::TestAccount;
+
+    use super::*;
+
+    #[test]
+    fn test_enabling_trading_keeps_restricted_states() {
+        let mut account = TestAccount::new("acc", 1000.0, 100.0);
+
+        account.set_trading_disabled(true);
+        account.set_trading_disabled(false);
+        assert_eq!(
+            account.get_trading_state(),
+            CrossMarginAccountTradingState::Active
+        );
+
+        for state in [
+            CrossMarginAccountTradingState::CloseOnly,
+            CrossMarginAccountTradingState::Frozen,
+        ] {
+            account.set_trading_state(state);
+            account.set_trading_disabled(false);
+            assert_eq!(account.get_trading_state(), state);
+        }
     }
 }
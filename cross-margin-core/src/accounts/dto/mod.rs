@@ -1,3 +1,5 @@
 mod account;
+mod trading_state;
 
 pub use account::*;
+pub use trading_state::*;
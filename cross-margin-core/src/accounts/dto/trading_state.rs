@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossMarginAccountTradingState {
+    Active = 0,
+    CloseOnly = 1,
+    Disabled = 2,
+    Frozen = 3,
+}
+
+impl CrossMarginAccountTradingState {
+    pub fn is_open_allowed(&self) -> bool {
+        return matches!(self, CrossMarginAccountTradingState::Active);
+    }
+
+    pub fn is_client_close_allowed(&self) -> bool {
+        return matches!(
+            self,
+            CrossMarginAccountTradingState::Active | CrossMarginAccountTradingState::CloseOnly
+        );
+    }
+}
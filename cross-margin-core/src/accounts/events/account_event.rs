@@ -0,0 +1,19 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum CrossMarginAccountChange {
+    Balance { delta: f64 },
+    TradingDisabled,
+    TradingState,
+    TradingGroup,
+    Leverage,
+    // Change made by the bulk update command.
+    Bulk,
+}
+
+#[derive(Debug, Clone)]
+pub struct CrossMarginAccountEvent<T> {
+    pub account_id: String,
+    pub process_id: String,
+    pub change: CrossMarginAccountChange,
+    pub previous: T,
+    pub current: T,
+}
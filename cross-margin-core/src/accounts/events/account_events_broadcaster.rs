@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::CrossMarginAccountEvent;
+
+pub const ACCOUNT_EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CrossMarginAccountEventsRecvError {
+    // Subscriber was too slow, the oldest events were dropped and receiving continues from the next one.
+    Lagged(u64),
+    Closed,
+}
+
+pub struct CrossMarginAccountEventsSubscriber<T> {
+    receiver: broadcast::Receiver<Arc<CrossMarginAccountEvent<T>>>,
+    lagged_events: Arc<AtomicU64>,
+}
+
+impl<T: Clone> CrossMarginAccountEventsSubscriber<T> {
+    pub async fn recv(
+        &mut self,
+    ) -> Result<Arc<CrossMarginAccountEvent<T>>, CrossMarginAccountEventsRecvError> {
+        match self.receiver.recv().await {
+            Ok(event) => return Ok(event),
+            Err(RecvError::Lagged(count)) => {
+                self.lagged_events.fetch_add(count, Ordering::Relaxed);
+                metrics::counter!("account_events_lagged").increment(count);
+                return Err(CrossMarginAccountEventsRecvError::Lagged(count));
+            }
+            Err(RecvError::Closed) => return Err(CrossMarginAccountEventsRecvError::Closed),
+        }
+    }
+}
+
+// Fans account changes out to global and per account subscribers.
+pub struct CrossMarginAccountEventsBroadcaster<T> {
+    capacity: usize,
+    global: broadcast::Sender<Arc<CrossMarginAccountEvent<T>>>,
+    // ACCOUNT_ID - SENDER
+    accounts: HashMap<String, broadcast::Sender<Arc<CrossMarginAccountEvent<T>>>>,
+    lagged_events: Arc<AtomicU64>,
+}
+
+impl<T: Clone> CrossMarginAccountEventsBroadcaster<T> {
+    pub fn new(capacity: usize) -> Self {
+        let (global, _) = broadcast::channel(capacity);
+
+        Self {
+            capacity,
+            global,
+            accounts: HashMap::new(),
+            lagged_events: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn subscribe_all(&self) -> CrossMarginAccountEventsSubscriber<T> {
+        return CrossMarginAccountEventsSubscriber {
+            receiver: self.global.subscribe(),
+            lagged_events: self.lagged_events.clone(),
+        };
+    }
+
+    pub fn subscribe_account(&mut self, account_id: &str) -> CrossMarginAccountEventsSubscriber<T> {
+        let capacity = self.capacity;
+        let sender = self
+            .accounts
+            .entry(account_id.to_string())
+            .or_insert_with(|| broadcast::channel(capacity).0);
+
+        return CrossMarginAccountEventsSubscriber {
+            receiver: sender.subscribe(),
+            lagged_events: self.lagged_events.clone(),
+        };
+    }
+
+    pub fn has_subscribers(&self, account_id: &str) -> bool {
+        return self.global.receiver_count() > 0
+            || self
+                .accounts
+                .get(account_id)
+                .map(|x| x.receiver_count() > 0)
+                .unwrap_or(false);
+    }
+
+    // Total events dropped for lagging subscribers.
+    pub fn get_lagged_events(&self) -> u64 {
+        return self.lagged_events.load(Ordering::Relaxed);
+    }
+
+    // Account subscribers are closed, the account can't publish any more events.
+    pub fn remove_account(&mut self, account_id: &str) {
+        self.accounts.remove(account_id);
+    }
+
+    pub fn publish(&mut self, event: CrossMarginAccountEvent<T>) {
+        let event = Arc::new(event);
+
+        // send fails only when there are no receivers
+        let _ = self.global.send(event.clone());
+
+        if let Some(sender) = self.accounts.get(&event.account_id) {
+            if sender.send(event.clone()).is_err() {
+                self.accounts.remove(&event.account_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::accounts::CrossMarginAccountChange;
+
+    use super::*;
+
+    fn event(account_id: &str, previous: f64, current: f64) -> CrossMarginAccountEvent<f64> {
+        return CrossMarginAccountEvent {
+            account_id: account_id.to_string(),
+            process_id: "process".to_string(),
+            change: CrossMarginAccountChange::Balance {
+                delta: current - previous,
+            },
+            previous,
+            current,
+        };
+    }
+
+    #[tokio::test]
+    async fn test_account_subscriber_receives_only_own_events() {
+        let mut broadcaster = CrossMarginAccountEventsBroadcaster::new(16);
+        let mut all = broadcaster.subscribe_all();
+        let mut acc = broadcaster.subscribe_account("acc");
+
+        broadcaster.publish(event("other", 0.0, 1.0));
+        broadcaster.publish(event("acc", 1.0, 2.0));
+
+        assert_eq!(all.recv().await.unwrap().account_id, "other");
+        assert_eq!(all.recv().await.unwrap().account_id, "acc");
+        assert_eq!(acc.recv().await.unwrap().current, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_is_reported() {
+        let mut broadcaster = CrossMarginAccountEventsBroadcaster::new(2);
+        let mut all = broadcaster.subscribe_all();
+
+        for i in 0..5 {
+            broadcaster.publish(event("acc", i as f64, i as f64 + 1.0));
+        }
+
+        assert_eq!(
+            all.recv().await.unwrap_err(),
+            CrossMarginAccountEventsRecvError::Lagged(3)
+        );
+        assert_eq!(broadcaster.get_lagged_events(), 3);
+        assert_eq!(all.recv().await.unwrap().previous, 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_removed_account_subscribers_are_closed() {
+        let mut broadcaster = CrossMarginAccountEventsBroadcaster::<f64>::new(16);
+        let mut acc = broadcaster.subscribe_account("acc");
+
+        broadcaster.remove_account("acc");
+
+        assert!(!broadcaster.has_subscribers("acc"));
+        assert_eq!(
+            acc.recv().await.unwrap_err(),
+            CrossMarginAccountEventsRecvError::Closed
+        );
+    }
+}
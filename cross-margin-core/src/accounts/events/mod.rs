@@ -0,0 +1,5 @@
+mod account_event;
+mod account_events_broadcaster;
+
+pub use account_event::*;
+pub use account_events_broadcaster::*;
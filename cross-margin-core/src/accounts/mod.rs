@@ -1,5 +1,7 @@
 mod dto;
 mod cache;
+mod events;
 
 pub use dto::*;
-pub use cache::*;
\ No newline at end of file
+pub use cache::*;
+pub use events::*;
\ No newline at end of file
@@ -0,0 +1,215 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{
+    accounts::{
+        CrossMarginAccount, CrossMarginAccountsMarginCache, CrossMarginMarginReservation,
+        CrossMarginMarginReservationsCache,
+    },
+    exposure::CrossMarginExposureCache,
+    instruments::CrossMarginInstrumentsCache,
+    positions::{
+        CrossMarginActivePosition, CrossMarginPendingPosition, CrossMarginPriceDependencyIndex,
+        CrossMarginSlippageStat, CrossMarginSlippageStats,
+    },
+    trading_groups::CrossMarginTradingGroupsCache,
+    CrossMarginBidAsk, CrossMarginError,
+};
+
+use super::{
+    initialize_account_cache, initialize_active_positions_cache, initialize_bid_ask_cache,
+    initialize_pending_cache, CrossMarginCacheInstrument, CrossMarginCaches,
+    CrossMarginCachesSnapshot, CrossMarginClock, CrossMarginEngineSettings,
+    CrossMarginIdempotencyCache, CrossMarginIdempotencySnapshot, CrossMarginIdempotentResult,
+    CrossMarginSystemClock,
+};
+
+// Registries and settings are set before the caches are built, so loaded positions are rated with them.
+pub struct CrossMarginCachesBuilder<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    pub accounts: Vec<A>,
+    pub active_positions: Vec<AP>,
+    pub pending_positions: Vec<PP>,
+    pub instruments: Vec<CrossMarginCacheInstrument>,
+    pub collaterals: Vec<String>,
+    pub prices: Vec<CrossMarginBidAsk>,
+    pub instruments_cache: CrossMarginInstrumentsCache,
+    pub trading_groups_cache: CrossMarginTradingGroupsCache,
+    pub settings: CrossMarginEngineSettings,
+    pub clock: Arc<dyn CrossMarginClock>,
+    pub idempotency: Option<CrossMarginIdempotencySnapshot<CrossMarginIdempotentResult<A, AP, PP>>>,
+    pub margin_reservations: Vec<CrossMarginMarginReservation>,
+    pub slippage_stats: HashMap<String, CrossMarginSlippageStat>,
+    pub margin_call_accounts: HashSet<String>,
+    pub kept_pending_orders: HashSet<String>,
+    pub last_rollover: Option<DateTimeAsMicroseconds>,
+}
+
+impl<A, AP, PP> CrossMarginCachesBuilder<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    pub fn new(
+        accounts: Vec<A>,
+        active_positions: Vec<AP>,
+        pending_positions: Vec<PP>,
+        instruments: Vec<CrossMarginCacheInstrument>,
+        collaterals: Vec<String>,
+        prices: Vec<CrossMarginBidAsk>,
+    ) -> Self {
+        Self {
+            accounts,
+            active_positions,
+            pending_positions,
+            instruments,
+            collaterals,
+            prices,
+            instruments_cache: CrossMarginInstrumentsCache::new(vec![]),
+            trading_groups_cache: CrossMarginTradingGroupsCache::new(vec![]),
+            settings: CrossMarginEngineSettings::default(),
+            clock: Arc::new(CrossMarginSystemClock),
+            idempotency: None,
+            margin_reservations: vec![],
+            slippage_stats: HashMap::new(),
+            margin_call_accounts: HashSet::new(),
+            kept_pending_orders: HashSet::new(),
+            last_rollover: None,
+        }
+    }
+
+    pub fn with_instruments_cache(
+        mut self,
+        instruments_cache: CrossMarginInstrumentsCache,
+    ) -> Self {
+        self.instruments_cache = instruments_cache;
+        self
+    }
+
+    pub fn with_trading_groups_cache(
+        mut self,
+        trading_groups_cache: CrossMarginTradingGroupsCache,
+    ) -> Self {
+        self.trading_groups_cache = trading_groups_cache;
+        self
+    }
+
+    pub fn with_settings(mut self, settings: CrossMarginEngineSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn CrossMarginClock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Retries of mutations applied before the snapshot keep returning the original result.
+    pub fn with_idempotency_snapshot(
+        mut self,
+        idempotency: CrossMarginIdempotencySnapshot<CrossMarginIdempotentResult<A, AP, PP>>,
+    ) -> Self {
+        self.idempotency = Some(idempotency);
+        self
+    }
+
+    // Replaces accounts, positions and prices, the caches continue from the snapshot state.
+    pub fn with_snapshot(mut self, snapshot: CrossMarginCachesSnapshot<A, AP, PP>) -> Self {
+        self.accounts = snapshot.accounts;
+        self.active_positions = snapshot.active_positions;
+        self.pending_positions = snapshot.pending_positions;
+        self.prices = snapshot.prices;
+        self.idempotency = Some(snapshot.idempotency);
+        self.margin_reservations = snapshot.margin_reservations;
+        self.slippage_stats = snapshot.slippage_stats;
+        self.margin_call_accounts = snapshot.margin_call_accounts.into_iter().collect();
+        self.kept_pending_orders = snapshot.kept_pending_orders.into_iter().collect();
+        self.last_rollover = snapshot.last_rollover;
+        self
+    }
+
+    pub async fn build(self) -> Result<CrossMarginCaches<A, AP, PP>, CrossMarginError> {
+        let bid_ask_cache =
+            initialize_bid_ask_cache(self.instruments, self.collaterals, self.prices).await;
+        let accounts_cache = initialize_account_cache(self.accounts).await;
+
+        let active_cache = match initialize_active_positions_cache(
+            self.active_positions,
+            &bid_ask_cache,
+            &accounts_cache,
+            &self.instruments_cache,
+            &self.trading_groups_cache,
+        )
+        .await
+        {
+            Ok(src) => src,
+            Err(errs) => return Err(get_multi_error(errs)),
+        };
+
+        let pending_positions_cache = match initialize_pending_cache(self.pending_positions).await {
+            Ok(src) => src,
+            Err(errs) => return Err(get_multi_error(errs)),
+        };
+
+        let mut exposure_cache = CrossMarginExposureCache::new();
+
+        for position in active_cache.positions.values() {
+            if let Some(account) = accounts_cache.get_account(position.get_account_id()) {
+                exposure_cache.update_position(position, account.get_trading_group());
+            }
+        }
+
+        let idempotency_cache = match self.idempotency {
+            Some(snapshot) => CrossMarginIdempotencyCache::from_snapshot(
+                snapshot,
+                self.settings.idempotency_window,
+            ),
+            None => CrossMarginIdempotencyCache::new(),
+        };
+
+        let mut margin_reservations = CrossMarginMarginReservationsCache::new();
+
+        for reservation in self.margin_reservations {
+            margin_reservations.reserve(reservation);
+        }
+
+        let mut caches = CrossMarginCaches {
+            prices_cache: bid_ask_cache,
+            accounts_cache,
+            active_positions_cache: active_cache,
+            pending_positions_cache,
+            instruments_cache: self.instruments_cache,
+            trading_groups_cache: self.trading_groups_cache,
+            exposure_cache,
+            accounts_margin_cache: CrossMarginAccountsMarginCache::new(),
+            price_dependency_index: CrossMarginPriceDependencyIndex::new(),
+            margin_reservations,
+            slippage_stats: CrossMarginSlippageStats::from_stats(self.slippage_stats),
+            settings: self.settings,
+            clock: self.clock,
+            pending_converter: None,
+            listeners: vec![],
+            margin_call_accounts: self.margin_call_accounts,
+            kept_pending_orders: self.kept_pending_orders,
+            idempotency_cache,
+            last_rollover: self.last_rollover,
+        };
+
+        caches.rebuild_price_dependency_index();
+
+        return Ok(caches);
+    }
+}
+
+fn get_multi_error(errors: Vec<CrossMarginError>) -> CrossMarginError {
+    return CrossMarginError::MultiError(errors.iter().map(|x| format!("{:?}", x)).collect());
+}
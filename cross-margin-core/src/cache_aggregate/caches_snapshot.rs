@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{
+    accounts::{CrossMarginAccount, CrossMarginMarginReservation},
+    positions::{CrossMarginActivePosition, CrossMarginPendingPosition, CrossMarginSlippageStat},
+    CrossMarginBidAsk,
+};
+
+use super::{CrossMarginCaches, CrossMarginIdempotencySnapshot, CrossMarginIdempotentResult};
+
+// Caches state required to start again, registries and settings are not included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginCachesSnapshot<A, AP, PP> {
+    pub accounts: Vec<A>,
+    pub active_positions: Vec<AP>,
+    pub pending_positions: Vec<PP>,
+    pub prices: Vec<CrossMarginBidAsk>,
+    pub margin_reservations: Vec<CrossMarginMarginReservation>,
+    pub slippage_stats: HashMap<String, CrossMarginSlippageStat>,
+    pub margin_call_accounts: Vec<String>,
+    pub kept_pending_orders: Vec<String>,
+    pub last_rollover: Option<DateTimeAsMicroseconds>,
+    pub idempotency: CrossMarginIdempotencySnapshot<CrossMarginIdempotentResult<A, AP, PP>>,
+}
+
+impl<A, AP, PP> CrossMarginCachesSnapshot<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    pub async fn new(caches: &CrossMarginCaches<A, AP, PP>) -> Self {
+        return Self {
+            accounts: caches.accounts_cache.get_all().await,
+            active_positions: caches
+                .active_positions_cache
+                .positions
+                .values()
+                .cloned()
+                .collect(),
+            pending_positions: caches
+                .pending_positions_cache
+                .positions
+                .values()
+                .cloned()
+                .collect(),
+            prices: caches
+                .prices_cache
+                .get_all()
+                .into_iter()
+                .map(|x| x.as_ref().clone())
+                .collect(),
+            margin_reservations: caches
+                .margin_reservations
+                .get_all()
+                .into_iter()
+                .cloned()
+                .collect(),
+            slippage_stats: caches.slippage_stats.get_all().clone(),
+            margin_call_accounts: caches.margin_call_accounts.iter().cloned().collect(),
+            kept_pending_orders: caches.kept_pending_orders.iter().cloned().collect(),
+            last_rollover: caches.last_rollover,
+            idempotency: caches.idempotency_cache.get_snapshot(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{
+        positions::CrossMarginStopExecution,
+        test_utils::{
+            create_caches, default_trading_groups, eurusd, TestAccount, TestActivePosition,
+        },
+        CrossMarginCaches, CrossMarginCloseReason, CrossMarginInstrumentsCache,
+        CrossMarginPositionSide,
+    };
+
+    use super::CrossMarginCachesSnapshot;
+
+    #[tokio::test]
+    async fn test_engine_state_is_restored() {
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.idempotency_window = 100;
+        caches
+            .update_account_balance("acc", 100.0, "deposit", false, None)
+            .await
+            .unwrap();
+        caches.last_rollover = Some(DateTimeAsMicroseconds::new(1_700_000_000_000_000));
+        caches.margin_call_accounts.insert("acc".to_string());
+        caches.kept_pending_orders.insert("order".to_string());
+        caches.slippage_stats.record(
+            "EURUSD",
+            &CrossMarginCloseReason::Sl,
+            &CrossMarginStopExecution {
+                requested_price: 1.05,
+                market_price: 1.04,
+                execution_price: 1.04,
+                slippage: -0.01,
+                pl_adjustment: 0.0,
+            },
+        );
+
+        let snapshot = CrossMarginCachesSnapshot::new(&caches).await;
+        let mut restored = CrossMarginCaches::from_snapshot(
+            snapshot,
+            vec![eurusd()],
+            vec!["USD".to_string()],
+            CrossMarginInstrumentsCache::new(vec![]),
+            default_trading_groups(),
+            caches.settings.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            restored.last_rollover.map(|x| x.unix_microseconds),
+            Some(1_700_000_000_000_000)
+        );
+        assert!(restored.margin_call_accounts.contains("acc"));
+        assert!(restored.kept_pending_orders.contains("order"));
+        assert_eq!(restored.slippage_stats.get("EURUSD").unwrap().sl_count, 1);
+        assert!(restored.prices_cache.get_by_id("EURUSD").is_some());
+
+        // the retried deposit returns the result applied before the snapshot
+        assert_eq!(restored.idempotency_cache.len(), 1);
+        let account = restored
+            .update_account_balance("acc", 100.0, "deposit", false, None)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, 1100.0);
+    }
+
+    #[tokio::test]
+    async fn test_account_with_positions_is_restored() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "position",
+                    "acc",
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    1.0,
+                    1.1002,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+
+        let snapshot = CrossMarginCachesSnapshot::new(&caches).await;
+        let restored = CrossMarginCaches::from_snapshot(
+            snapshot,
+            vec![instrument.clone()],
+            vec!["USD".to_string()],
+            CrossMarginInstrumentsCache::new(vec![]),
+            default_trading_groups(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+
+        let position = restored
+            .active_positions_cache
+            .get_by_id("position")
+            .unwrap();
+        assert_eq!(position.active_price, 1.1);
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+pub trait CrossMarginClock: Send + Sync {
+    fn now(&self) -> DateTimeAsMicroseconds;
+}
+
+pub struct CrossMarginSystemClock;
+
+impl CrossMarginClock for CrossMarginSystemClock {
+    fn now(&self) -> DateTimeAsMicroseconds {
+        return DateTimeAsMicroseconds::now();
+    }
+}
+
+pub struct CrossMarginManualClock {
+    now: AtomicI64,
+}
+
+impl CrossMarginManualClock {
+    pub fn new(now: DateTimeAsMicroseconds) -> Self {
+        Self {
+            now: AtomicI64::new(now.unix_microseconds),
+        }
+    }
+
+    pub fn set(&self, now: DateTimeAsMicroseconds) {
+        self.now.store(now.unix_microseconds, Ordering::SeqCst);
+    }
+}
+
+impl CrossMarginClock for CrossMarginManualClock {
+    fn now(&self) -> DateTimeAsMicroseconds {
+        return DateTimeAsMicroseconds::new(self.now.load(Ordering::SeqCst));
+    }
+}
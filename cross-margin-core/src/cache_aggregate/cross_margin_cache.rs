@@ -1,29 +1,75 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
 use crate::{
-    accounts::CrossMarginAccount,
+    accounts::{
+        check_version, CrossMarginAccount, CrossMarginAccountsMarginCache,
+        CrossMarginMarginReservation, CrossMarginMarginReservationsCache,
+    },
+    commissions::{CrossMarginCommissionBreakdown, CrossMarginCommissionSide},
+    events::CrossMarginEventsListener,
+    exposure::CrossMarginExposureCache,
     flows::{
-        is_enough_balance_to_open_position, process_positions_update,
-        remove_orders_ready_to_execute, update_active_positions_rates,
+        calculate_account_commission, calculate_account_data_with_reservations,
+        calculate_account_margin_states, calculate_instrument_margin_state,
+        calculate_liquidation_price, calculate_margin_breakdown, calculate_open_position_cost,
+        calculate_stop_execution, get_account_bid_ask, process_positions_update, process_rollover,
+        remove_orders_ready_to_execute, simulate_price_shocks, update_active_positions_rates,
+        update_position_rates, validate_account_close_allowed, validate_account_open_allowed,
+        validate_active_position_order, validate_pending_position_order, validate_sl_tp_update,
+        CrossMarginAccountMarginBreakdown, UpdatePositionsDto,
     },
+    instruments::CrossMarginInstrumentsCache,
     positions::{
-        CrossMarginActivePosition, CrossMarginPendingPosition,
-        CrossMarginPendingPositionExecuteReason, PositionsCache,
+        CrossMarginActivePosition, CrossMarginPendingPosition, CrossMarginPendingPositionConverter,
+        CrossMarginPendingPositionExecuteReason, CrossMarginPendingTriggerEvent,
+        CrossMarginPositionSettlement, CrossMarginPositionsCacheQueryBuilder,
+        CrossMarginPriceDependencyIndex, CrossMarginSlippageStats, PositionsCache,
     },
-    AccountsCache, CrossMarginBidAsk, CrossMarginBidAskCache, CrossMarginCloseReason,
-    CrossMarginError,
+    trading_groups::CrossMarginTradingGroupsCache,
+    AccountCalculationResult, AccountsCache, CrossMarginAccountTradingState, CrossMarginBidAsk,
+    CrossMarginBidAskCache, CrossMarginCloseReason, CrossMarginCoalescedBidAsk, CrossMarginError,
+    CrossMarginLiquidationPrice, CrossMarginPriceShock, CrossMarginShockAccountResult,
+    ProcessRolloverResult,
 };
 
 use super::{
-    initialize_account_cache, initialize_active_positions_cache, initialize_bid_ask_cache,
-    initialize_pending_cache,
+    CrossMarginCachesBuilder, CrossMarginCachesSnapshot, CrossMarginClock,
+    CrossMarginEngineSettings, CrossMarginIdempotencyCache, CrossMarginIdempotencyKey,
+    CrossMarginIdempotentOperation, CrossMarginIdempotentResult,
 };
 
 pub struct CrossMarginCacheHandleBidAskResult<
     AP: CrossMarginActivePosition,
     PP: CrossMarginPendingPosition,
 > {
-    pub closed_positions: Vec<(AP, CrossMarginCloseReason)>,
+    pub closed_positions: Vec<(AP, CrossMarginCloseReason, CrossMarginPositionSettlement)>,
     pub failed_orders: Vec<(PP, CrossMarginPendingPositionExecuteReason)>,
     pub executed_orders: Vec<PP>,
+    // Positions opened from executed orders when a pending converter is set.
+    pub opened_positions: Vec<(AP, Option<CrossMarginCommissionBreakdown>)>,
+    pub pending_margin_events: Vec<CrossMarginPendingTriggerEvent>,
+    // Executed orders which position failed to open, such orders stay pending.
+    pub failed_opens: Vec<(PP, CrossMarginError)>,
+}
+
+impl<AP: CrossMarginActivePosition, PP: CrossMarginPendingPosition>
+    CrossMarginCacheHandleBidAskResult<AP, PP>
+{
+    // Appends a later result, e.g. of a batch which couldn't be delivered separately.
+    pub fn merge(&mut self, result: Self) {
+        self.closed_positions.extend(result.closed_positions);
+        self.failed_orders.extend(result.failed_orders);
+        self.executed_orders.extend(result.executed_orders);
+        self.opened_positions.extend(result.opened_positions);
+        self.pending_margin_events
+            .extend(result.pending_margin_events);
+        self.failed_opens.extend(result.failed_opens);
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -43,6 +89,24 @@ where
     pub accounts_cache: AccountsCache<A>,
     pub active_positions_cache: PositionsCache<AP>,
     pub pending_positions_cache: PositionsCache<PP>,
+    pub instruments_cache: CrossMarginInstrumentsCache,
+    pub trading_groups_cache: CrossMarginTradingGroupsCache,
+    pub exposure_cache: CrossMarginExposureCache,
+    pub accounts_margin_cache: CrossMarginAccountsMarginCache,
+    pub price_dependency_index: CrossMarginPriceDependencyIndex,
+    pub margin_reservations: CrossMarginMarginReservationsCache,
+    pub slippage_stats: CrossMarginSlippageStats,
+    pub settings: CrossMarginEngineSettings,
+    pub clock: Arc<dyn CrossMarginClock>,
+    // Without a converter executed orders are returned and the host opens the positions.
+    pub pending_converter: Option<Arc<dyn CrossMarginPendingPositionConverter<PP, AP>>>,
+    pub listeners: Vec<Arc<dyn CrossMarginEventsListener<A, AP, PP>>>,
+    // Accounts which margin call was already notified.
+    pub margin_call_accounts: HashSet<String>,
+    // Pending orders which kept event was already reported.
+    pub kept_pending_orders: HashSet<String>,
+    pub idempotency_cache: CrossMarginIdempotencyCache<CrossMarginIdempotentResult<A, AP, PP>>,
+    pub last_rollover: Option<DateTimeAsMicroseconds>,
 }
 
 impl<A, AP, PP> CrossMarginCaches<A, AP, PP>
@@ -51,6 +115,7 @@ where
     AP: CrossMarginActivePosition,
     PP: CrossMarginPendingPosition,
 {
+    // Loaded positions are rated with the registries, every account must belong to a registered group.
     pub async fn new(
         accounts: Vec<A>,
         active_positions: Vec<AP>,
@@ -58,33 +123,46 @@ where
         instruments: Vec<CrossMarginCacheInstrument>,
         collaterals: Vec<String>,
         prices: Vec<CrossMarginBidAsk>,
+        instruments_cache: CrossMarginInstrumentsCache,
+        trading_groups_cache: CrossMarginTradingGroupsCache,
     ) -> Result<Self, CrossMarginError> {
-        let bid_ask_cache = initialize_bid_ask_cache(instruments, collaterals, prices).await;
-        let accounts_cache = initialize_account_cache(accounts).await;
-
-        let active_cache =
-            match initialize_active_positions_cache(active_positions, &bid_ask_cache).await {
-                Ok(src) => src,
-                Err(errs) => panic!(
-                    "Multiple errors: {:?} during active cache initializations",
-                    errs
-                ),
-            };
-
-        let pending_positions_cache = match initialize_pending_cache(pending_positions).await {
-            Ok(src) => src,
-            Err(errs) => panic!(
-                "Multiple errors: {:?} during pending cache initializations",
-                errs
-            ),
-        };
+        return CrossMarginCachesBuilder::new(
+            accounts,
+            active_positions,
+            pending_positions,
+            instruments,
+            collaterals,
+            prices,
+        )
+        .with_instruments_cache(instruments_cache)
+        .with_trading_groups_cache(trading_groups_cache)
+        .build()
+        .await;
+    }
 
-        return Ok(CrossMarginCaches {
-            prices_cache: bid_ask_cache,
-            accounts_cache,
-            active_positions_cache: active_cache,
-            pending_positions_cache,
-        });
+    // Settings are required to restore the idempotency window, the clock is the system one.
+    pub async fn from_snapshot(
+        snapshot: CrossMarginCachesSnapshot<A, AP, PP>,
+        instruments: Vec<CrossMarginCacheInstrument>,
+        collaterals: Vec<String>,
+        instruments_cache: CrossMarginInstrumentsCache,
+        trading_groups_cache: CrossMarginTradingGroupsCache,
+        settings: CrossMarginEngineSettings,
+    ) -> Result<Self, CrossMarginError> {
+        return CrossMarginCachesBuilder::new(
+            vec![],
+            vec![],
+            vec![],
+            instruments,
+            collaterals,
+            vec![],
+        )
+        .with_instruments_cache(instruments_cache)
+        .with_trading_groups_cache(trading_groups_cache)
+        .with_settings(settings)
+        .with_snapshot(snapshot)
+        .build()
+        .await;
     }
 
     pub async fn is_enough_balance_to_open_position(
@@ -95,17 +173,23 @@ where
         base: &str,
         instrument_id: &str,
     ) -> Result<bool, CrossMarginError> {
-        return is_enough_balance_to_open_position(
+        self.validate_market_open(instrument_id)?;
+
+        let cost = calculate_open_position_cost(
             &self.accounts_cache,
             &self.active_positions_cache,
             &self.prices_cache,
+            &self.instruments_cache,
+            &self.trading_groups_cache,
             account_id,
             lots_size,
             lots_amount,
             base,
-            instrument_id
-        )
-        .await;
+            instrument_id,
+            self.get_reserved_margin(account_id),
+        )?;
+
+        return Ok(cost.is_enough());
     }
 
     pub async fn handle_bid_ask(
@@ -113,78 +197,1310 @@ where
         bid_ask: CrossMarginBidAsk,
         process_id: &str,
     ) -> CrossMarginCacheHandleBidAskResult<AP, PP> {
-        self.prices_cache.handle_new(bid_ask.clone());
-        let updated_positions = update_active_positions_rates(self, &bid_ask);
+        return self.handle_bid_ask_batch(vec![bid_ask], process_id).await;
+    }
+
+    // Only the latest price of each asset pair is applied and positions are recalculated once,
+    // price level sl/tp are also checked against the extremes of the skipped ticks.
+    pub async fn handle_bid_ask_batch(
+        &mut self,
+        prices: Vec<CrossMarginBidAsk>,
+        process_id: &str,
+    ) -> CrossMarginCacheHandleBidAskResult<AP, PP> {
+        let coalesced_prices = CrossMarginCoalescedBidAsk::coalesce(prices);
+        let is_new_source = self.prices_cache.handle_coalesced(&coalesced_prices);
+
+        return self
+            .handle_applied_bid_ask_batch(coalesced_prices, is_new_source, process_id)
+            .await;
+    }
+
+    // Prices are already applied to the prices cache, e.g. a cache shared by shards.
+    pub async fn handle_applied_bid_ask_batch(
+        &mut self,
+        coalesced_prices: Vec<CrossMarginCoalescedBidAsk>,
+        is_new_source: bool,
+        process_id: &str,
+    ) -> CrossMarginCacheHandleBidAskResult<AP, PP> {
+        // a new source can replace cross rates used by existing positions
+        if is_new_source {
+            self.rebuild_price_dependency_index();
+        }
+
+        let mut updated_positions = update_active_positions_rates(self, &coalesced_prices);
+
+        let mut updated_instruments = HashSet::new();
+
+        for update in updated_positions.iter() {
+            self.refresh_position_exposure(&update.position_id);
+
+            if let Some(position) = self.active_positions_cache.get_by_id(&update.position_id) {
+                self.notify(|x| x.on_position_updated(position));
+
+                updated_instruments.insert((
+                    update.account_id.clone(),
+                    position.get_instrument_id().to_string(),
+                    position.get_base().to_string(),
+                    position.get_quote().to_string(),
+                ));
+            }
+        }
+
+        for (account_id, instrument_id, base, quote) in updated_instruments {
+            self.refresh_account_instrument_margin(&account_id, &instrument_id, &base, &quote);
+        }
+
+        let repriced_orders: HashSet<String> = coalesced_prices
+            .iter()
+            .flat_map(|x| {
+                self.margin_reservations
+                    .get_source_orders(&x.latest.asset_pair)
+            })
+            .collect();
+        self.reprice_reservations(repriced_orders);
+
+        let mut failed_orders = vec![];
+        let mut triggered_orders = vec![];
+        let mut pending_margin_events = vec![];
+
+        let mut batch_committed_margin = HashMap::new();
+
+        for coalesced in coalesced_prices.iter() {
+            let executed_limits_orders =
+                remove_orders_ready_to_execute(self, coalesced, &mut batch_committed_margin).await;
+            failed_orders.extend(executed_limits_orders.failed_orders);
+            pending_margin_events.extend(executed_limits_orders.margin_events);
+            triggered_orders.extend(executed_limits_orders.executed_orders);
+        }
+
+        let mut executed_orders = vec![];
+        let mut opened_positions = vec![];
+        let mut failed_opens = vec![];
+
+        for (order, bid_ask) in triggered_orders {
+            let Some(converter) = self.pending_converter.clone() else {
+                self.remove_executed_order(order.get_id());
+                executed_orders.push(order);
+                continue;
+            };
+
+            match self
+                .open_executed_order(converter.as_ref(), &order, &bid_ask, process_id)
+                .await
+            {
+                Ok((position, commission)) => {
+                    self.remove_executed_order(order.get_id());
+                    // new positions take part in the stop out check of this tick
+                    updated_positions.push(UpdatePositionsDto {
+                        trader_id: position.get_trader_id().to_string(),
+                        account_id: position.get_account_id().to_string(),
+                        position_id: position.get_id().to_string(),
+                        close_position_reason: None,
+                    });
+                    opened_positions.push((position, commission));
+                    executed_orders.push(order);
+                }
+                Err(err) => failed_opens.push((order, err)),
+            }
+        }
+
+        for order in executed_orders.iter() {
+            self.notify(|x| x.on_pending_triggered(order));
+        }
+
+        for (order, reason) in failed_orders.iter() {
+            self.notify(|x| x.on_pending_rejected(order, reason));
+        }
+
         let closed_positions = process_positions_update(self, updated_positions, process_id).await;
-        let executed_limits_orders = remove_orders_ready_to_execute(self, &bid_ask).await;
 
         return CrossMarginCacheHandleBidAskResult {
             closed_positions,
-            failed_orders: executed_limits_orders.failed_orders,
-            executed_orders: executed_limits_orders.executed_orders,
+            failed_orders,
+            executed_orders,
+            opened_positions,
+            pending_margin_events,
+            failed_opens,
         };
     }
 
+    fn remove_executed_order(&mut self, order_id: &str) {
+        self.pending_positions_cache.remove_position(order_id);
+        self.margin_reservations.release(order_id);
+        self.kept_pending_orders.remove(order_id);
+    }
+
+    // Opens the position at the trigger account price, nothing is changed when any step fails.
+    async fn open_executed_order(
+        &mut self,
+        converter: &dyn CrossMarginPendingPositionConverter<PP, AP>,
+        order: &PP,
+        bid_ask: &CrossMarginBidAsk,
+        process_id: &str,
+    ) -> Result<(AP, Option<CrossMarginCommissionBreakdown>), CrossMarginError> {
+        let open_price = bid_ask.get_open_price(order.get_side());
+
+        let mut position = converter.convert(order, open_price, bid_ask.date);
+        position.update_sl(order.get_sl_price(), order.get_sl_profit());
+        position.update_tp(order.get_tp_price(), order.get_tp_profit());
+
+        let account = self
+            .accounts_cache
+            .get_account(order.get_account_id())
+            .ok_or(CrossMarginError::AccountNotFound)?;
+        let spread_markup = self
+            .trading_groups_cache
+            .get_account_group(account)?
+            .spread_markup
+            .as_ref();
+
+        update_position_rates(
+            &mut position,
+            &self.prices_cache,
+            &self.instruments_cache,
+            spread_markup,
+        )?;
+
+        let commission = self.calculate_commission(&position, CrossMarginCommissionSide::Open)?;
+
+        if let Some(commission) = &commission {
+            self.change_account_balance(
+                position.get_account_id(),
+                -commission.charged_fee,
+                process_id,
+                false,
+                None,
+            )
+            .await?;
+        }
+
+        self.insert_active_position(position.clone());
+
+        return Ok((position, commission));
+    }
+
+    pub async fn process_rollover(&mut self, process_id: &str) -> ProcessRolloverResult<AP> {
+        return process_rollover(self, process_id).await;
+    }
+
     pub async fn add_active_position(
         &mut self,
         position: AP,
-        _: &str,
+        process_id: &str,
+    ) -> Result<Option<CrossMarginCommissionBreakdown>, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::Commission(commission)) = self
+            .get_idempotent_result(
+                CrossMarginIdempotentOperation::AddActivePosition,
+                position.get_id(),
+                process_id,
+            )
+        {
+            return Ok(commission);
+        }
+
+        let account = self
+            .accounts_cache
+            .get_account(position.get_account_id())
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        validate_account_open_allowed(account)?;
+        self.validate_market_open(position.get_instrument_id())?;
+
+        let market_price = self
+            .get_account_bid_ask(position.get_account_id(), position.get_instrument_id())?
+            .get_close_price(position.get_side());
+        validate_active_position_order(&self.instruments_cache, &position, market_price)?;
+
+        let commission = self.calculate_commission(&position, CrossMarginCommissionSide::Open)?;
+
+        if let Some(commission) = &commission {
+            self.change_account_balance(
+                position.get_account_id(),
+                -commission.charged_fee,
+                process_id,
+                false,
+                None,
+            )
+            .await?;
+        }
+
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::AddActivePosition,
+            position.get_id(),
+            process_id,
+            CrossMarginIdempotentResult::Commission(commission.clone()),
+        );
+        self.insert_active_position(position);
+
+        return Ok(commission);
+    }
+
+    pub fn add_listener(&mut self, listener: Arc<dyn CrossMarginEventsListener<A, AP, PP>>) {
+        self.listeners.push(listener);
+    }
+
+    pub fn notify(&self, event: impl Fn(&dyn CrossMarginEventsListener<A, AP, PP>)) {
+        for listener in self.listeners.iter() {
+            event(listener.as_ref());
+        }
+    }
+
+    // Deposits and withdrawals, engine balance changes are not remembered for idempotency.
+    pub async fn update_account_balance(
+        &mut self,
+        account_id: &str,
+        delta: f64,
+        process_id: &str,
+        allow_negative_balance: bool,
+        expected_version: Option<u64>,
+    ) -> Result<A, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::Account(account)) = self.get_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateBalance,
+            account_id,
+            process_id,
+        ) {
+            return Ok(account);
+        }
+
+        let account = self
+            .change_account_balance(
+                account_id,
+                delta,
+                process_id,
+                allow_negative_balance,
+                expected_version,
+            )
+            .await?;
+        self.accounts_margin_cache.invalidate_account(account_id);
+
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateBalance,
+            account_id,
+            process_id,
+            CrossMarginIdempotentResult::Account(account.clone()),
+        );
+
+        return Ok(account);
+    }
+
+    fn get_idempotent_result(
+        &self,
+        operation: CrossMarginIdempotentOperation,
+        target_id: &str,
+        process_id: &str,
+    ) -> Option<CrossMarginIdempotentResult<A, AP, PP>> {
+        let key = CrossMarginIdempotencyKey::new(operation, target_id, process_id);
+
+        return self.idempotency_cache.get(&key).cloned();
+    }
+
+    fn save_idempotent_result(
+        &mut self,
+        operation: CrossMarginIdempotentOperation,
+        target_id: &str,
+        process_id: &str,
+        result: CrossMarginIdempotentResult<A, AP, PP>,
+    ) {
+        self.idempotency_cache.insert(
+            CrossMarginIdempotencyKey::new(operation, target_id, process_id),
+            result,
+            self.settings.idempotency_window,
+        );
+    }
+
+    async fn change_account_balance(
+        &mut self,
+        account_id: &str,
+        delta: f64,
+        process_id: &str,
+        allow_negative_balance: bool,
+        expected_version: Option<u64>,
+    ) -> Result<A, CrossMarginError> {
+        let account = self
+            .accounts_cache
+            .update_balance(
+                account_id,
+                delta,
+                process_id,
+                allow_negative_balance,
+                expected_version,
+            )
+            .await?;
+
+        self.notify(|x| x.on_balance_changed(&account, delta, process_id));
+
+        return Ok(account);
+    }
+
+    fn insert_active_position(&mut self, position: AP) {
+        let position_id = position.get_id().to_string();
+        let account_id = position.get_account_id().to_string();
+        let instrument_id = position.get_instrument_id().to_string();
+        let base = position.get_base().to_string();
+        let quote = position.get_quote().to_string();
+
+        self.active_positions_cache.add_position(position);
+        self.index_position_prices(&position_id);
+        self.refresh_position_exposure(&position_id);
+        self.refresh_account_instrument_margin(&account_id, &instrument_id, &base, &quote);
+
+        if let Some(position) = self.active_positions_cache.get_by_id(&position_id) {
+            self.notify(|x| x.on_position_opened(position));
+        }
+    }
+
+    pub async fn add_pending_position(
+        &mut self,
+        position: PP,
+        process_id: &str,
     ) -> Result<(), CrossMarginError> {
-        self.active_positions_cache.add_position(position.clone());
+        if let Some(CrossMarginIdempotentResult::Empty) = self.get_idempotent_result(
+            CrossMarginIdempotentOperation::AddPendingPosition,
+            position.get_id(),
+            process_id,
+        ) {
+            return Ok(());
+        }
+
+        let account = self
+            .accounts_cache
+            .get_account(position.get_account_id())
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        validate_account_open_allowed(account)?;
+        self.validate_market_open(position.get_instrument_id())?;
+
+        let market_price = self
+            .get_account_bid_ask(position.get_account_id(), position.get_instrument_id())?
+            .get_open_price(position.get_side());
+        validate_pending_position_order(&self.instruments_cache, &position, market_price)?;
+
+        if self.settings.reserve_pending_margin {
+            let cost = calculate_open_position_cost(
+                &self.accounts_cache,
+                &self.active_positions_cache,
+                &self.prices_cache,
+                &self.instruments_cache,
+                &self.trading_groups_cache,
+                position.get_account_id(),
+                position.get_lots_size(),
+                position.get_lots_amount(),
+                position.get_base(),
+                position.get_instrument_id(),
+                self.get_reserved_margin(position.get_account_id()),
+            )?;
+
+            if !cost.is_enough() {
+                return Err(CrossMarginError::NotEnoughBalance);
+            }
+
+            self.reserve_pending_margin(&position)?;
+        }
+
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::AddPendingPosition,
+            position.get_id(),
+            process_id,
+            CrossMarginIdempotentResult::Empty,
+        );
+        self.pending_positions_cache.add_position(position);
         return Ok(());
     }
 
+    pub async fn remove_pending_position(
+        &mut self,
+        id: &str,
+        process_id: &str,
+    ) -> Result<PP, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::PendingPosition(position)) = self
+            .get_idempotent_result(
+                CrossMarginIdempotentOperation::RemovePendingPosition,
+                id,
+                process_id,
+            )
+        {
+            return Ok(position);
+        }
+
+        let position = self
+            .pending_positions_cache
+            .remove_position(id)
+            .ok_or(CrossMarginError::PositionNotFound)?;
+
+        self.margin_reservations.release(id);
+        self.kept_pending_orders.remove(id);
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::RemovePendingPosition,
+            id,
+            process_id,
+            CrossMarginIdempotentResult::PendingPosition(position.clone()),
+        );
+
+        return Ok(position);
+    }
+
+    // Reserves margin for loaded orders which have no reservation, e.g. after the mode was enabled.
+    pub fn reserve_pending_orders_margin(&mut self) -> Vec<(String, CrossMarginError)> {
+        let orders: Vec<PP> = self
+            .pending_positions_cache
+            .positions
+            .values()
+            .filter(|x| {
+                self.margin_reservations
+                    .get_reservation(x.get_id())
+                    .is_none()
+            })
+            .cloned()
+            .collect();
+
+        let mut errors = vec![];
+
+        for order in orders {
+            if let Err(err) = self.reserve_pending_margin(&order) {
+                errors.push((order.get_id().to_string(), err));
+            }
+        }
+
+        return errors;
+    }
+
+    fn reserve_pending_margin(&mut self, position: &PP) -> Result<(), CrossMarginError> {
+        let account = self
+            .accounts_cache
+            .get_account(position.get_account_id())
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        let margin = calculate_open_position_cost(
+            &self.accounts_cache,
+            &self.active_positions_cache,
+            &self.prices_cache,
+            &self.instruments_cache,
+            &self.trading_groups_cache,
+            position.get_account_id(),
+            position.get_lots_size(),
+            position.get_lots_amount(),
+            position.get_base(),
+            position.get_instrument_id(),
+            0.0,
+        )?
+        .margin;
+
+        let mut sources = vec![position.get_instrument_id().to_string()];
+        sources.extend(
+            self.prices_cache
+                .get_price_sources(position.get_base(), account.get_currency()),
+        );
+
+        self.margin_reservations
+            .reserve(CrossMarginMarginReservation {
+                order_id: position.get_id().to_string(),
+                account_id: position.get_account_id().to_string(),
+                margin,
+                sources,
+            });
+
+        return Ok(());
+    }
+
+    // Reservations follow the current margin rate and account leverage. Reservations of orders
+    // removed from the pending cache directly are released, failed re-pricing keeps the old value.
+    fn reprice_reservations(&mut self, order_ids: impl IntoIterator<Item = String>) {
+        for order_id in order_ids {
+            let Some(order) = self.pending_positions_cache.get_by_id(&order_id).cloned() else {
+                self.margin_reservations.release(&order_id);
+                continue;
+            };
+
+            let _ = self.reserve_pending_margin(&order);
+        }
+    }
+
+    fn reprice_account_reservations(&mut self, account_id: &str) {
+        let orders: Vec<String> = self
+            .margin_reservations
+            .get_account_reservations(account_id)
+            .iter()
+            .map(|x| x.order_id.clone())
+            .collect();
+
+        self.reprice_reservations(orders);
+    }
+
+    // Reserved margin of the account orders which are still in the pending cache.
+    pub fn get_reserved_margin(&self, account_id: &str) -> f64 {
+        return self
+            .margin_reservations
+            .get_account_reservations(account_id)
+            .iter()
+            .filter(|x| {
+                self.pending_positions_cache
+                    .get_by_id(&x.order_id)
+                    .is_some()
+            })
+            .map(|x| x.margin)
+            .sum();
+    }
+
+    pub async fn update_active_position_sl_tp(
+        &mut self,
+        id: &str,
+        sl_price: Option<f64>,
+        sl_profit: Option<f64>,
+        tp_price: Option<f64>,
+        tp_profit: Option<f64>,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<AP, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::ActivePosition(position)) =
+            self.get_idempotent_result(CrossMarginIdempotentOperation::UpdateSlTp, id, process_id)
+        {
+            return Ok(position);
+        }
+
+        let position = self
+            .active_positions_cache
+            .get_by_id(id)
+            .ok_or(CrossMarginError::PositionNotFound)?;
+        check_version(position.get_version(), expected_version)?;
+
+        let account = self
+            .accounts_cache
+            .get_account(position.get_account_id())
+            .ok_or(CrossMarginError::AccountNotFound)?;
+        validate_account_close_allowed(account)?;
+
+        let market_price = self
+            .get_account_bid_ask(position.get_account_id(), position.get_instrument_id())?
+            .get_close_price(position.get_side());
+        validate_sl_tp_update(
+            &self.instruments_cache,
+            position.get_instrument_id(),
+            position.get_side(),
+            market_price,
+            sl_price,
+            tp_price,
+        )?;
+
+        let position = self
+            .active_positions_cache
+            .update_position(id, |position| {
+                let position = position?;
+                position.update_sl(sl_price, sl_profit);
+                position.update_tp(tp_price, tp_profit);
+                position.set_version(position.get_version() + 1);
+
+                return Some(position.clone());
+            })
+            .ok_or(CrossMarginError::PositionNotFound)?;
+
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateSlTp,
+            id,
+            process_id,
+            CrossMarginIdempotentResult::ActivePosition(position.clone()),
+        );
+
+        return Ok(position);
+    }
+
+    pub fn calculate_liquidation_price(
+        &self,
+        account_id: &str,
+        instrument_id: &str,
+    ) -> Result<CrossMarginLiquidationPrice, CrossMarginError> {
+        let account = self
+            .accounts_cache
+            .get_account(account_id)
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        let account_positions = self
+            .active_positions_cache
+            .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id));
+
+        return calculate_liquidation_price(
+            account,
+            &account_positions,
+            &self.prices_cache,
+            &self.instruments_cache,
+            &self.trading_groups_cache,
+            instrument_id,
+        );
+    }
+
+    pub fn get_account_margin_breakdown(
+        &self,
+        account_id: &str,
+    ) -> Result<CrossMarginAccountMarginBreakdown, CrossMarginError> {
+        let account = self
+            .accounts_cache
+            .get_account(account_id)
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        let account_positions = self
+            .active_positions_cache
+            .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id));
+
+        return calculate_margin_breakdown(
+            account,
+            &account_positions,
+            &self.instruments_cache,
+            &self.trading_groups_cache,
+        );
+    }
+
+    // Runs on a copy of the prices, live positions and accounts are not changed.
+    pub fn simulate_price_shocks(
+        &self,
+        shocks: &[CrossMarginPriceShock],
+    ) -> Result<Vec<CrossMarginShockAccountResult>, CrossMarginError> {
+        return simulate_price_shocks(self, shocks);
+    }
+
+    pub async fn update_account_trading_group(
+        &mut self,
+        account_id: &str,
+        trading_group: &str,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<A, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::Account(account)) = self.get_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateTradingGroup,
+            account_id,
+            process_id,
+        ) {
+            return Ok(account);
+        }
+
+        if self.trading_groups_cache.get_group(trading_group).is_none() {
+            return Err(CrossMarginError::TradingGroupNotFound(
+                trading_group.to_string(),
+            ));
+        }
+
+        let account = self
+            .accounts_cache
+            .update_trading_group(account_id, trading_group, process_id, expected_version)
+            .await?;
+
+        let positions_ids: Vec<String> = self
+            .active_positions_cache
+            .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id))
+            .iter()
+            .map(|x| x.get_id().to_string())
+            .collect();
+
+        for position_id in positions_ids {
+            self.refresh_position_exposure(&position_id);
+        }
+
+        self.accounts_margin_cache.invalidate_account(account_id);
+        self.reprice_account_reservations(account_id);
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateTradingGroup,
+            account_id,
+            process_id,
+            CrossMarginIdempotentResult::Account(account.clone()),
+        );
+
+        return Ok(account);
+    }
+
+    pub async fn update_account_trading_disabled(
+        &mut self,
+        account_id: &str,
+        trading_disabled: bool,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<A, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::Account(account)) = self.get_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateTradingDisabled,
+            account_id,
+            process_id,
+        ) {
+            return Ok(account);
+        }
+
+        let account = self
+            .accounts_cache
+            .update_trading_disabled(account_id, trading_disabled, process_id, expected_version)
+            .await?;
+
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateTradingDisabled,
+            account_id,
+            process_id,
+            CrossMarginIdempotentResult::Account(account.clone()),
+        );
+
+        return Ok(account);
+    }
+
+    pub async fn update_account_trading_state(
+        &mut self,
+        account_id: &str,
+        trading_state: CrossMarginAccountTradingState,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<A, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::Account(account)) = self.get_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateTradingState,
+            account_id,
+            process_id,
+        ) {
+            return Ok(account);
+        }
+
+        let account = self
+            .accounts_cache
+            .update_trading_state(account_id, trading_state, process_id, expected_version)
+            .await?;
+
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateTradingState,
+            account_id,
+            process_id,
+            CrossMarginIdempotentResult::Account(account.clone()),
+        );
+
+        return Ok(account);
+    }
+
+    pub async fn update_account_leverage(
+        &mut self,
+        account_id: &str,
+        leverage: Option<f64>,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<A, CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::Account(account)) = self.get_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateLeverage,
+            account_id,
+            process_id,
+        ) {
+            return Ok(account);
+        }
+
+        let account = self
+            .accounts_cache
+            .update_leverage(account_id, leverage, process_id, expected_version)
+            .await?;
+
+        self.accounts_margin_cache.invalidate_account(account_id);
+        self.reprice_account_reservations(account_id);
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::UpdateLeverage,
+            account_id,
+            process_id,
+            CrossMarginIdempotentResult::Account(account.clone()),
+        );
+
+        return Ok(account);
+    }
+
+    // Margin, equity and margin level from the cached per instrument state, accounts which are not
+    // cached or were changed since their state was calculated are fully calculated.
+    pub fn get_account_summary(
+        &self,
+        account_id: &str,
+    ) -> Result<AccountCalculationResult, CrossMarginError> {
+        let account = self
+            .accounts_cache
+            .get_account(account_id)
+            .ok_or(CrossMarginError::AccountNotFound)?;
+        let reserved_margin = self.get_reserved_margin(account_id);
+
+        let revision = (
+            self.instruments_cache.get_margin_revision(),
+            self.trading_groups_cache.get_revision(),
+        );
+
+        if self.accounts_margin_cache.get_revision() == revision {
+            if let Some(summary) = self
+                .accounts_margin_cache
+                .get_summary(account, reserved_margin)
+            {
+                return Ok(summary);
+            }
+        }
+
+        let account_positions = self
+            .active_positions_cache
+            .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id));
+
+        return calculate_account_data_with_reservations(
+            account,
+            &account_positions,
+            &self.instruments_cache,
+            &self.trading_groups_cache,
+            reserved_margin,
+        );
+    }
+
+    fn refresh_account_instrument_margin(
+        &mut self,
+        account_id: &str,
+        instrument_id: &str,
+        base: &str,
+        quote: &str,
+    ) {
+        self.accounts_margin_cache.ensure_revision(
+            self.instruments_cache.get_margin_revision(),
+            self.trading_groups_cache.get_revision(),
+        );
+
+        let Some(account) = self.accounts_cache.get_account(account_id) else {
+            self.accounts_margin_cache.invalidate_account(account_id);
+            return;
+        };
+
+        // first change of the account or its margin values were changed, all instruments are calculated
+        if !self.accounts_margin_cache.has_account(account) {
+            let account_positions = self.active_positions_cache.query_positions(
+                CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id),
+            );
+
+            match calculate_account_margin_states(
+                account,
+                &account_positions,
+                &self.instruments_cache,
+                &self.trading_groups_cache,
+            ) {
+                Ok(states) => self.accounts_margin_cache.set_account(account, states),
+                Err(_) => self.accounts_margin_cache.invalidate_account(account_id),
+            }
+
+            return;
+        }
+
+        let positions: Vec<&AP> = self
+            .active_positions_cache
+            .query_positions(
+                CrossMarginPositionsCacheQueryBuilder::new()
+                    .with_account(account_id)
+                    .with_base(base)
+                    .with_quote(quote),
+            )
+            .into_iter()
+            .filter(|x| x.get_instrument_id() == instrument_id)
+            .collect();
+
+        let state = calculate_instrument_margin_state(
+            account,
+            instrument_id,
+            &positions,
+            &self.instruments_cache,
+            &self.trading_groups_cache,
+        );
+
+        match state {
+            Ok(state) => {
+                self.accounts_margin_cache
+                    .update_instrument(account_id, instrument_id, state);
+            }
+            Err(_) => self.accounts_margin_cache.invalidate_account(account_id),
+        }
+    }
+
+    pub fn rebuild_price_dependency_index(&mut self) {
+        let positions_ids: Vec<String> = self
+            .active_positions_cache
+            .positions
+            .keys()
+            .cloned()
+            .collect();
+
+        self.price_dependency_index.clear();
+
+        for position_id in positions_ids {
+            self.index_position_prices(&position_id);
+        }
+    }
+
+    fn index_position_prices(&mut self, position_id: &str) {
+        let Some(position) = self.active_positions_cache.get_by_id(position_id) else {
+            self.price_dependency_index.remove_position(position_id);
+            return;
+        };
+
+        let mut sources = vec![position.get_instrument_id().to_string()];
+        sources.extend(
+            self.prices_cache
+                .get_price_sources(position.get_quote(), position.get_collateral()),
+        );
+
+        self.price_dependency_index
+            .add_position(position_id, position.get_account_id(), sources);
+    }
+
+    fn refresh_position_exposure(&mut self, position_id: &str) {
+        let Some(position) = self.active_positions_cache.get_by_id(position_id) else {
+            self.exposure_cache.remove_position(position_id);
+            return;
+        };
+
+        let Some(account) = self.accounts_cache.get_account(position.get_account_id()) else {
+            return;
+        };
+
+        self.exposure_cache
+            .update_position(position, account.get_trading_group());
+    }
+
+    pub fn is_market_open(&self, instrument_id: &str) -> bool {
+        return self
+            .instruments_cache
+            .is_market_open(instrument_id, self.clock.now());
+    }
+
+    fn validate_market_open(&self, instrument_id: &str) -> Result<(), CrossMarginError> {
+        if self.is_market_open(instrument_id) {
+            return Ok(());
+        }
+
+        return Err(CrossMarginError::MarketClosed(instrument_id.to_string()));
+    }
+
+    fn calculate_commission(
+        &self,
+        position: &AP,
+        side: CrossMarginCommissionSide,
+    ) -> Result<Option<CrossMarginCommissionBreakdown>, CrossMarginError> {
+        let account = self
+            .accounts_cache
+            .get_account(position.get_account_id())
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        let price = match side {
+            CrossMarginCommissionSide::Open => position.get_open_price(),
+            CrossMarginCommissionSide::Close => position.get_active_price(),
+        };
+
+        return calculate_account_commission(
+            &self.trading_groups_cache,
+            &self.prices_cache,
+            account,
+            position.get_instrument_id(),
+            position.get_quote(),
+            position.get_lots_size(),
+            position.get_lots_amount(),
+            price,
+            side,
+        );
+    }
+
+    // Instrument price with the account trading group spread markup, used for open prices.
+    pub fn get_account_bid_ask(
+        &self,
+        account_id: &str,
+        instrument_id: &str,
+    ) -> Result<CrossMarginBidAsk, CrossMarginError> {
+        let account = self
+            .accounts_cache
+            .get_account(account_id)
+            .ok_or(CrossMarginError::AccountNotFound)?;
+
+        let bid_ask =
+            self.prices_cache
+                .get_by_id(instrument_id)
+                .ok_or(CrossMarginError::AssetNotFound(format!(
+                    "{} NOT FOUND",
+                    instrument_id
+                )))?;
+
+        return get_account_bid_ask(
+            &self.trading_groups_cache,
+            &self.instruments_cache,
+            account,
+            &bid_ask,
+        );
+    }
+
     pub async fn remove_active_position(
         &mut self,
         id: &str,
         process_id: &str,
-    ) -> Result<(AP, A), CrossMarginError> {
+        expected_version: Option<u64>,
+    ) -> Result<(AP, A, CrossMarginPositionSettlement), CrossMarginError> {
+        if let Some(CrossMarginIdempotentResult::ClosedPosition(position, account, settlement)) =
+            self.get_idempotent_result(
+                CrossMarginIdempotentOperation::RemoveActivePosition,
+                id,
+                process_id,
+            )
+        {
+            return Ok((position, account, settlement));
+        }
+
+        let position = self
+            .active_positions_cache
+            .get_by_id(id)
+            .ok_or(CrossMarginError::PositionNotFound)?;
+        check_version(position.get_version(), expected_version)?;
+        let account = self
+            .accounts_cache
+            .get_account(position.get_account_id())
+            .ok_or(CrossMarginError::AccountNotFound)?;
+        validate_account_close_allowed(account)?;
+        let commission = self.calculate_commission(position, CrossMarginCommissionSide::Close)?;
+
         let removed_position = self
             .active_positions_cache
             .remove_position(id)
             .ok_or(CrossMarginError::PositionNotFound)?;
 
+        let settlement = CrossMarginPositionSettlement::new(&removed_position, commission);
+        self.exposure_cache.remove_position(id);
+        self.price_dependency_index.remove_position(id);
+        self.refresh_account_instrument_margin(
+            removed_position.get_account_id(),
+            removed_position.get_instrument_id(),
+            removed_position.get_base(),
+            removed_position.get_quote(),
+        );
+
         let account_after_update = self
-            .accounts_cache
-            .update_balance(
+            .change_account_balance(
                 removed_position.get_account_id(),
-                removed_position.get_pl(),
+                settlement.get_total(),
                 process_id,
                 true,
+                None,
             )
             .await?;
 
-        return Ok((removed_position, account_after_update));
+        self.notify(|x| {
+            x.on_position_closed(
+                &removed_position,
+                &CrossMarginCloseReason::ClientCommand,
+                &settlement,
+            )
+        });
+
+        self.save_idempotent_result(
+            CrossMarginIdempotentOperation::RemoveActivePosition,
+            id,
+            process_id,
+            CrossMarginIdempotentResult::ClosedPosition(
+                removed_position.clone(),
+                account_after_update.clone(),
+                settlement.clone(),
+            ),
+        );
+
+        return Ok((removed_position, account_after_update, settlement));
     }
 
     pub async fn remove_active_positions(
         &mut self,
         ids: &[(String, CrossMarginCloseReason)],
         process_id: &str,
-    ) -> Vec<(AP, CrossMarginCloseReason)> {
-        let removed_positions: Vec<(AP, CrossMarginCloseReason)> = ids
-            .into_iter()
-            .filter_map(|(id, close_reason)| {
-                if let Some(removed_position) = self.active_positions_cache.remove_position(id) {
-                    return Some((removed_position, close_reason.clone()));
+    ) -> Vec<(AP, CrossMarginCloseReason, CrossMarginPositionSettlement)> {
+        let mut removed_positions = Vec::with_capacity(ids.len());
+
+        for (id, close_reason) in ids {
+            // forced closes must not fail on a missing conversion price, so commission is skipped
+            let commission = self
+                .active_positions_cache
+                .get_by_id(id)
+                .and_then(|position| {
+                    self.calculate_commission(position, CrossMarginCommissionSide::Close)
+                        .ok()
+                        .flatten()
+                });
+
+            let stop_execution = self
+                .active_positions_cache
+                .get_by_id(id)
+                .and_then(|position| {
+                    let account = self.accounts_cache.get_account(position.get_account_id())?;
+                    let trading_group =
+                        self.trading_groups_cache.get_account_group(account).ok()?;
+
+                    return calculate_stop_execution(position, close_reason, trading_group);
+                });
+
+            if let Some(mut removed_position) = self.active_positions_cache.remove_position(id) {
+                if let Some(stop_execution) = &stop_execution {
+                    // the position is closed at the execution price
+                    removed_position
+                        .update_pl(removed_position.get_pl() + stop_execution.pl_adjustment);
+                    self.slippage_stats.record(
+                        removed_position.get_instrument_id(),
+                        close_reason,
+                        stop_execution,
+                    );
                 }
 
-                return None;
-            })
-            .collect();
+                let settlement = CrossMarginPositionSettlement::new(&removed_position, commission)
+                    .with_stop_execution(stop_execution);
+                self.exposure_cache.remove_position(id);
+                self.price_dependency_index.remove_position(id);
+                self.refresh_account_instrument_margin(
+                    removed_position.get_account_id(),
+                    removed_position.get_instrument_id(),
+                    removed_position.get_base(),
+                    removed_position.get_quote(),
+                );
+                removed_positions.push((removed_position, close_reason.clone(), settlement));
+            }
+        }
 
-        for (position, _) in removed_positions.iter() {
-            self.accounts_cache
-                .update_balance(
-                    position.get_account_id(),
-                    position.get_pl(),
-                    process_id,
-                    true,
-                )
-                .await
-                .unwrap();
+        for (position, close_reason, settlement) in removed_positions.iter() {
+            self.change_account_balance(
+                position.get_account_id(),
+                settlement.get_total(),
+                process_id,
+                true,
+                None,
+            )
+            .await
+            .unwrap();
+            self.notify(|x| x.on_position_closed(position, close_reason, settlement));
         }
 
         return removed_positions;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{
+        test_utils::{create_caches, eurusd, TestAccount, TestActivePosition},
+        CrossMarginAccount, CrossMarginAccountTradingState, CrossMarginError,
+        CrossMarginManualClock, CrossMarginPositionSide, CrossMarginTradingSchedule,
+        CrossMarginTradingTimeRange, CrossMarginWeeklySession,
+    };
+
+    #[tokio::test]
+    async fn test_stale_versions_are_rejected() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+
+        let account = caches
+            .update_account_balance("acc", 100.0, "deposit", false, Some(0))
+            .await
+            .unwrap();
+        assert_eq!(account.get_version(), 1);
+
+        let result = caches
+            .update_account_balance("acc", 100.0, "second-deposit", false, Some(0))
+            .await;
+        assert!(matches!(
+            result,
+            Err(CrossMarginError::VersionMismatch {
+                expected: 0,
+                actual: 1
+            })
+        ));
+
+        let position = TestActivePosition::new(
+            "position",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1002,
+        );
+        caches.add_active_position(position, "open").await.unwrap();
+        caches
+            .update_active_position_sl_tp("position", Some(1.0), None, None, None, "sl", Some(0))
+            .await
+            .unwrap();
+
+        let result = caches
+            .remove_active_position("position", "close", Some(0))
+            .await;
+        assert!(matches!(
+            result,
+            Err(CrossMarginError::VersionMismatch {
+                expected: 0,
+                actual: 1
+            })
+        ));
+        assert!(caches
+            .active_positions_cache
+            .get_by_id("position")
+            .is_some());
+
+        caches
+            .remove_active_position("position", "close", Some(1))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_frozen_account_can_not_close_positions() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        let position = TestActivePosition::new(
+            "position",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1002,
+        );
+        caches.add_active_position(position, "open").await.unwrap();
+        caches
+            .accounts_cache
+            .update_trading_state("acc", CrossMarginAccountTradingState::Frozen, "state", None)
+            .await
+            .unwrap();
+
+        let result = caches
+            .remove_active_position("position", "close", None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(CrossMarginError::TradingNotAllowed(
+                CrossMarginAccountTradingState::Frozen
+            ))
+        ));
+        assert!(caches
+            .active_positions_cache
+            .get_by_id("position")
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_position_is_not_opened_on_closed_market() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        // 2024-01-06 12:00 UTC, Saturday
+        caches.clock = Arc::new(CrossMarginManualClock::new(DateTimeAsMicroseconds::new(
+            1_704_542_400_000_000,
+        )));
+        caches
+            .instruments_cache
+            .update_schedule(CrossMarginTradingSchedule {
+                instrument_id: instrument.id.clone(),
+                sessions: (0..5)
+                    .map(|day_of_week| CrossMarginWeeklySession {
+                        day_of_week,
+                        range: CrossMarginTradingTimeRange {
+                            from_minute: 0,
+                            to_minute: 24 * 60,
+                        },
+                    })
+                    .collect(),
+                overrides: vec![],
+            });
+
+        let position = TestActivePosition::new(
+            "position",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1002,
+        );
+        let result = caches.add_active_position(position, "open").await;
+
+        assert!(matches!(result, Err(CrossMarginError::MarketClosed(_))));
+        assert!(caches.active_positions_cache.positions.is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+// 21:00 UTC
+pub const DEFAULT_ROLLOVER_MINUTE: u32 = 21 * 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossMarginPendingMarginPolicy {
+    // Order stays in the cache and is checked again on the next tick.
+    #[default]
+    KeepAndRetry = 0,
+    Reject = 1,
+    // Order is executed with the affordable lots amount, the rest is rejected.
+    PartialFill = 2,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrossMarginEngineSettings {
+    pub sl_tp_on_closed_market: bool,
+    pub pending_on_closed_market: bool,
+    pub stop_out_on_closed_market: bool,
+    // Pending orders reserve margin at placement, the reservation lowers free margin.
+    pub reserve_pending_margin: bool,
+    pub pending_margin_policy: CrossMarginPendingMarginPolicy,
+    // Minutes from the start of the UTC day, None means DEFAULT_ROLLOVER_MINUTE.
+    pub rollover_minute: Option<u32>,
+    // Amount of remembered mutation results, repeated process_id returns the remembered result.
+    // Zero disables idempotency.
+    pub idempotency_window: usize,
+}
@@ -0,0 +1,281 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commissions::CrossMarginCommissionBreakdown, positions::CrossMarginPositionSettlement,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CrossMarginIdempotentOperation {
+    UpdateBalance,
+    UpdateLeverage,
+    UpdateTradingGroup,
+    UpdateTradingDisabled,
+    UpdateTradingState,
+    AddActivePosition,
+    RemoveActivePosition,
+    UpdateSlTp,
+    AddPendingPosition,
+    RemovePendingPosition,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CrossMarginIdempotencyKey {
+    pub operation: CrossMarginIdempotentOperation,
+    // Account or position id the operation is applied to.
+    pub target_id: String,
+    pub process_id: String,
+}
+
+impl CrossMarginIdempotencyKey {
+    pub fn new(
+        operation: CrossMarginIdempotentOperation,
+        target_id: &str,
+        process_id: &str,
+    ) -> Self {
+        Self {
+            operation,
+            target_id: target_id.to_string(),
+            process_id: process_id.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CrossMarginIdempotentResult<A, AP, PP> {
+    Empty,
+    Account(A),
+    ActivePosition(AP),
+    PendingPosition(PP),
+    Commission(Option<CrossMarginCommissionBreakdown>),
+    ClosedPosition(AP, A, CrossMarginPositionSettlement),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginIdempotencySnapshot<R> {
+    // Oldest first.
+    pub entries: Vec<(CrossMarginIdempotencyKey, R)>,
+}
+
+// Results of successfully applied mutations, the oldest are evicted when the window is full.
+pub struct CrossMarginIdempotencyCache<R> {
+    results: HashMap<CrossMarginIdempotencyKey, R>,
+    order: VecDeque<CrossMarginIdempotencyKey>,
+}
+
+impl<R: Clone> CrossMarginIdempotencyCache<R> {
+    pub fn new() -> Self {
+        Self {
+            results: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    // Only the newest results fitting the window are restored.
+    pub fn from_snapshot(snapshot: CrossMarginIdempotencySnapshot<R>, window: usize) -> Self {
+        let mut cache = Self::new();
+
+        for (key, result) in snapshot.entries {
+            cache.insert(key, result, window);
+        }
+
+        return cache;
+    }
+
+    pub fn get(&self, key: &CrossMarginIdempotencyKey) -> Option<&R> {
+        return self.results.get(key);
+    }
+
+    // Zero window keeps nothing.
+    pub fn insert(&mut self, key: CrossMarginIdempotencyKey, result: R, window: usize) {
+        if window == 0 {
+            return;
+        }
+
+        if self.results.insert(key.clone(), result).is_none() {
+            self.order.push_back(key);
+        }
+
+        while self.order.len() > window {
+            if let Some(key) = self.order.pop_front() {
+                self.results.remove(&key);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.order.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.order.clear();
+    }
+
+    pub fn get_snapshot(&self) -> CrossMarginIdempotencySnapshot<R> {
+        return CrossMarginIdempotencySnapshot {
+            entries: self
+                .order
+                .iter()
+                .filter_map(|key| Some((key.clone(), self.results.get(key)?.clone())))
+                .collect(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{
+            bid_ask, create_caches, default_trading_groups, eurusd, TestAccount, TestActivePosition,
+        },
+        CrossMarginAccount, CrossMarginAccountTradingState, CrossMarginCachesBuilder,
+        CrossMarginPositionSide,
+    };
+
+    use super::*;
+
+    fn key(process_id: &str) -> CrossMarginIdempotencyKey {
+        return CrossMarginIdempotencyKey::new(
+            CrossMarginIdempotentOperation::UpdateBalance,
+            "acc",
+            process_id,
+        );
+    }
+
+    #[test]
+    fn test_oldest_results_are_evicted() {
+        let mut cache = CrossMarginIdempotencyCache::new();
+
+        for i in 0..5 {
+            cache.insert(key(&i.to_string()), i, 3);
+        }
+
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(&key("1")).is_none());
+        assert_eq!(cache.get(&key("4")), Some(&4));
+
+        let restored = CrossMarginIdempotencyCache::from_snapshot(cache.get_snapshot(), 3);
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.get(&key("2")), Some(&2));
+
+        let restored = CrossMarginIdempotencyCache::from_snapshot(cache.get_snapshot(), 1);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored.get(&key("4")), Some(&4));
+    }
+
+    #[tokio::test]
+    async fn test_retried_mutations_are_not_applied_twice() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.idempotency_window = 100;
+
+        for _ in 0..2 {
+            let account = caches
+                .update_account_balance("acc", 500.0, "deposit", false, None)
+                .await
+                .unwrap();
+            assert_eq!(account.get_balance(), 1500.0);
+        }
+
+        let position = TestActivePosition::new(
+            "position",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1,
+        );
+        caches.add_active_position(position, "open").await.unwrap();
+
+        let (_, first, _) = caches
+            .remove_active_position("position", "close", None)
+            .await
+            .unwrap();
+        let (_, retried, _) = caches
+            .remove_active_position("position", "close", None)
+            .await
+            .unwrap();
+
+        assert_eq!(first.get_balance(), retried.get_balance());
+        assert_eq!(
+            caches
+                .accounts_cache
+                .get_account("acc")
+                .unwrap()
+                .get_balance(),
+            first.get_balance()
+        );
+        assert!(caches
+            .remove_active_position("position", "other-close", None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retried_trading_state_changes_are_not_applied_twice() {
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.idempotency_window = 100;
+
+        for _ in 0..2 {
+            let account = caches
+                .update_account_trading_state(
+                    "acc",
+                    CrossMarginAccountTradingState::CloseOnly,
+                    "close-only",
+                    Some(0),
+                )
+                .await
+                .unwrap();
+            assert_eq!(account.get_version(), 1);
+        }
+
+        for _ in 0..2 {
+            let account = caches
+                .update_account_trading_disabled("acc", true, "disable", None)
+                .await
+                .unwrap();
+            assert_eq!(account.get_version(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_results_are_restored_from_snapshot() {
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.idempotency_window = 100;
+        caches
+            .update_account_balance("acc", 500.0, "deposit", false, None)
+            .await
+            .unwrap();
+
+        let mut restored = CrossMarginCachesBuilder::new(
+            caches.accounts_cache.get_all().await,
+            vec![],
+            vec![],
+            vec![eurusd()],
+            vec!["USD".to_string()],
+            vec![bid_ask(&eurusd(), 1.1, 1.1002)],
+        )
+        .with_trading_groups_cache(default_trading_groups())
+        .with_settings(caches.settings.clone())
+        .with_idempotency_snapshot(caches.idempotency_cache.get_snapshot())
+        .build()
+        .await
+        .unwrap();
+
+        let account = restored
+            .update_account_balance("acc", 500.0, "deposit", false, None)
+            .await
+            .unwrap();
+        assert_eq!(account.get_balance(), 1500.0);
+        assert_eq!(
+            restored
+                .accounts_cache
+                .get_account("acc")
+                .unwrap()
+                .get_balance(),
+            1500.0
+        );
+    }
+}
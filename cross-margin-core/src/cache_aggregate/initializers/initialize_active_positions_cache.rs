@@ -1,26 +1,134 @@
 use crate::{
     flows::update_position_rates,
+    instruments::CrossMarginInstrumentsCache,
     positions::{CrossMarginActivePosition, PositionsCache},
-    CrossMarginBidAskCache, CrossMarginError,
+    trading_groups::CrossMarginTradingGroupsCache,
+    AccountsCache, CrossMarginAccount, CrossMarginBidAskCache, CrossMarginError,
 };
 
-pub async fn initialize_active_positions_cache<T: CrossMarginActivePosition>(
+// Positions are rated with the account trading group markup, the same way as on ticks.
+pub async fn initialize_active_positions_cache<
+    A: CrossMarginAccount,
+    T: CrossMarginActivePosition,
+>(
     raw_positions: Vec<T>,
     cache: &CrossMarginBidAskCache,
+    accounts_cache: &AccountsCache<A>,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
 ) -> Result<PositionsCache<T>, Vec<CrossMarginError>> {
     let mut active_positions = vec![];
     let mut errors = vec![];
 
     for mut position in raw_positions {
-        match update_position_rates(&mut position, cache) {
+        let spread_markup = match accounts_cache.get_account(position.get_account_id()) {
+            Some(account) => match trading_groups_cache.get_account_group(account) {
+                Ok(group) => group.spread_markup.as_ref(),
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        match update_position_rates(&mut position, cache, instruments_cache, spread_markup) {
             Ok(_) => active_positions.push(position),
             Err(err) => errors.push(err),
         }
     }
 
     if errors.is_empty() {
-        Ok(PositionsCache::new("ActivePositions".to_string(), active_positions))
+        Ok(PositionsCache::new(
+            "ActivePositions".to_string(),
+            active_positions,
+        ))
     } else {
         Err(errors)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{bid_ask, eurusd, TestAccount, TestActivePosition, TestCaches},
+        trading_groups::{
+            CrossMarginSpreadMarkup, CrossMarginSpreadMarkupType, CrossMarginTradingGroup,
+            CrossMarginTradingGroupsCache,
+        },
+        CrossMarginCaches, CrossMarginCachesBuilder, CrossMarginError, CrossMarginInstrumentSpec,
+        CrossMarginInstrumentsCache, CrossMarginPositionSide,
+    };
+
+    #[tokio::test]
+    async fn test_loaded_positions_are_rated_with_group_markup() {
+        let instrument = eurusd();
+        let mut group = CrossMarginTradingGroup::new("default");
+        group.spread_markup = Some(CrossMarginSpreadMarkup {
+            markup_type: CrossMarginSpreadMarkupType::Points,
+            bid: 10.0,
+            ask: 20.0,
+        });
+
+        let caches: TestCaches = CrossMarginCachesBuilder::new(
+            vec![TestAccount::new("acc", 1000.0, 100.0)],
+            vec![TestActivePosition::new(
+                "position",
+                "acc",
+                &instrument,
+                CrossMarginPositionSide::Buy,
+                1.0,
+                1.1002,
+            )],
+            vec![],
+            vec![instrument.clone()],
+            vec!["USD".to_string()],
+            vec![bid_ask(&instrument, 1.1, 1.1002)],
+        )
+        .with_instruments_cache(CrossMarginInstrumentsCache::new(vec![
+            CrossMarginInstrumentSpec {
+                id: instrument.id.clone(),
+                contract_size: 1.0,
+                min_lots: 0.0,
+                max_lots: 1000.0,
+                lot_step: 0.0,
+                digits: 5,
+                max_leverage: None,
+                stops_level: 0,
+            },
+        ]))
+        .with_trading_groups_cache(CrossMarginTradingGroupsCache::new(vec![group]))
+        .build()
+        .await
+        .unwrap();
+
+        let position = caches.active_positions_cache.get_by_id("position").unwrap();
+        assert!((position.active_price - 1.0999).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_positions_of_unknown_group_are_returned_as_error() {
+        let instrument = eurusd();
+
+        let result: Result<TestCaches, CrossMarginError> = CrossMarginCaches::new(
+            vec![TestAccount::new("acc", 1000.0, 100.0)],
+            vec![TestActivePosition::new(
+                "position",
+                "acc",
+                &instrument,
+                CrossMarginPositionSide::Buy,
+                1.0,
+                1.1002,
+            )],
+            vec![],
+            vec![instrument.clone()],
+            vec!["USD".to_string()],
+            vec![bid_ask(&instrument, 1.1, 1.1002)],
+            CrossMarginInstrumentsCache::new(vec![]),
+            CrossMarginTradingGroupsCache::new(vec![]),
+        )
+        .await;
+
+        assert!(matches!(result, Err(CrossMarginError::MultiError(_))));
+    }
+}
@@ -1,5 +1,17 @@
+mod caches_builder;
+mod caches_snapshot;
+mod clock;
 mod cross_margin_cache;
+mod engine_settings;
+mod idempotency_cache;
 mod initializers;
+mod sharded_caches;
 
+pub use caches_builder::*;
+pub use caches_snapshot::*;
+pub use clock::*;
 pub use cross_margin_cache::*;
-pub use initializers::*;
\ No newline at end of file
+pub use engine_settings::*;
+pub use idempotency_cache::*;
+pub use initializers::*;
+pub use sharded_caches::*;
@@ -0,0 +1,353 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{
+    accounts::CrossMarginAccount,
+    instruments::CrossMarginInstrumentsCache,
+    positions::{CrossMarginActivePosition, CrossMarginPendingPosition},
+    trading_groups::CrossMarginTradingGroupsCache,
+    CrossMarginBidAsk, CrossMarginBidAskCache, CrossMarginCoalescedBidAsk, CrossMarginError,
+};
+
+use super::{CrossMarginCacheHandleBidAskResult, CrossMarginCacheInstrument, CrossMarginCaches};
+
+// Accounts with their positions are split across shards by account id, so ticks are processed by
+// all shards in parallel. Prices are shared, every shard keeps its own copy of settings.
+pub struct CrossMarginShardedCaches<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    pub shards: Vec<Arc<Mutex<CrossMarginCaches<A, AP, PP>>>>,
+    // Same cache as the shards one, a tick is applied to it once.
+    pub prices_cache: CrossMarginBidAskCache,
+}
+
+impl<A, AP, PP> CrossMarginShardedCaches<A, AP, PP>
+where
+    A: CrossMarginAccount + Send + Sync + 'static,
+    AP: CrossMarginActivePosition + Send + Sync + 'static,
+    PP: CrossMarginPendingPosition + Send + Sync + 'static,
+{
+    pub async fn new(
+        shards_count: usize,
+        accounts: Vec<A>,
+        active_positions: Vec<AP>,
+        pending_positions: Vec<PP>,
+        instruments: Vec<CrossMarginCacheInstrument>,
+        collaterals: Vec<String>,
+        prices: Vec<CrossMarginBidAsk>,
+        instruments_cache: CrossMarginInstrumentsCache,
+        trading_groups_cache: CrossMarginTradingGroupsCache,
+    ) -> Result<Self, CrossMarginError> {
+        let shards_count = shards_count.max(1);
+
+        let mut shard_accounts: Vec<Vec<A>> = (0..shards_count).map(|_| vec![]).collect();
+        let mut shard_active: Vec<Vec<AP>> = (0..shards_count).map(|_| vec![]).collect();
+        let mut shard_pending: Vec<Vec<PP>> = (0..shards_count).map(|_| vec![]).collect();
+
+        for account in accounts {
+            shard_accounts[get_shard_index(account.get_id(), shards_count)].push(account);
+        }
+
+        for position in active_positions {
+            shard_active[get_shard_index(position.get_account_id(), shards_count)].push(position);
+        }
+
+        for position in pending_positions {
+            shard_pending[get_shard_index(position.get_account_id(), shards_count)].push(position);
+        }
+
+        let mut shards = Vec::with_capacity(shards_count);
+        let mut prices_cache: Option<CrossMarginBidAskCache> = None;
+
+        for ((accounts, active_positions), pending_positions) in shard_accounts
+            .into_iter()
+            .zip(shard_active)
+            .zip(shard_pending)
+        {
+            let mut caches = CrossMarginCaches::new(
+                accounts,
+                active_positions,
+                pending_positions,
+                instruments.clone(),
+                collaterals.clone(),
+                prices.clone(),
+                instruments_cache.clone(),
+                trading_groups_cache.clone(),
+            )
+            .await?;
+
+            // shards are built from the same prices, so the first shard prices are shared
+            match &prices_cache {
+                Some(prices_cache) => caches.prices_cache = prices_cache.share(),
+                None => prices_cache = Some(caches.prices_cache.share()),
+            }
+
+            shards.push(Arc::new(Mutex::new(caches)));
+        }
+
+        return Ok(Self {
+            shards,
+            prices_cache: prices_cache.unwrap(),
+        });
+    }
+
+    pub fn get_shard_index(&self, account_id: &str) -> usize {
+        return get_shard_index(account_id, self.shards.len());
+    }
+
+    // Account commands are executed on the shard which owns the account.
+    pub async fn lock_account_shard(
+        &self,
+        account_id: &str,
+    ) -> MutexGuard<'_, CrossMarginCaches<A, AP, PP>> {
+        return self.shards[self.get_shard_index(account_id)].lock().await;
+    }
+
+    // Settings, instruments and trading groups must be the same on every shard. All shards are
+    // locked before the update, so no tick sees it applied to a part of the shards.
+    pub async fn update_shards(&self, update: impl Fn(&mut CrossMarginCaches<A, AP, PP>)) {
+        let mut shards = self.lock_shards().await;
+
+        for shard in shards.iter_mut() {
+            update(&mut *shard);
+        }
+    }
+
+    // Shards are always locked in the same order, so concurrent callers can't deadlock.
+    async fn lock_shards(&self) -> Vec<MutexGuard<'_, CrossMarginCaches<A, AP, PP>>> {
+        let mut shards = Vec::with_capacity(self.shards.len());
+
+        for shard in self.shards.iter() {
+            shards.push(shard.lock().await);
+        }
+
+        return shards;
+    }
+
+    pub async fn handle_bid_ask(
+        &self,
+        bid_ask: CrossMarginBidAsk,
+        process_id: &str,
+    ) -> Result<CrossMarginCacheHandleBidAskResult<AP, PP>, CrossMarginError> {
+        return self.handle_bid_ask_batch(vec![bid_ask], process_id).await;
+    }
+
+    pub async fn handle_bid_ask_batch(
+        &self,
+        prices: Vec<CrossMarginBidAsk>,
+        process_id: &str,
+    ) -> Result<CrossMarginCacheHandleBidAskResult<AP, PP>, CrossMarginError> {
+        let coalesced_prices = CrossMarginCoalescedBidAsk::coalesce(prices);
+        let is_new_source = {
+            // shards are locked, so none of them reads prices of a half applied tick
+            let _shards = self.lock_shards().await;
+            self.prices_cache
+                .share()
+                .handle_coalesced(&coalesced_prices)
+        };
+
+        let mut handles = Vec::with_capacity(self.shards.len());
+
+        for shard in self.shards.iter() {
+            let shard = shard.clone();
+            let coalesced_prices = coalesced_prices.clone();
+            let process_id = process_id.to_string();
+
+            handles.push(tokio::spawn(async move {
+                let mut shard = shard.lock().await;
+                return shard
+                    .handle_applied_bid_ask_batch(coalesced_prices, is_new_source, &process_id)
+                    .await;
+            }));
+        }
+
+        let mut result = CrossMarginCacheHandleBidAskResult {
+            closed_positions: vec![],
+            failed_orders: vec![],
+            executed_orders: vec![],
+            opened_positions: vec![],
+            pending_margin_events: vec![],
+            failed_opens: vec![],
+        };
+
+        let mut errors = vec![];
+
+        for (index, handle) in handles.into_iter().enumerate() {
+            let shard_result = match handle.await {
+                Ok(shard_result) => shard_result,
+                Err(err) => {
+                    errors.push(format!("shard {}: {}", index, err));
+                    continue;
+                }
+            };
+
+            result
+                .closed_positions
+                .extend(shard_result.closed_positions);
+            result.failed_orders.extend(shard_result.failed_orders);
+            result.executed_orders.extend(shard_result.executed_orders);
+            result
+                .opened_positions
+                .extend(shard_result.opened_positions);
+            result
+                .pending_margin_events
+                .extend(shard_result.pending_margin_events);
+            result.failed_opens.extend(shard_result.failed_opens);
+        }
+
+        if !errors.is_empty() {
+            return Err(CrossMarginError::ShardFailed(errors));
+        }
+
+        return Ok(result);
+    }
+}
+
+fn get_shard_index(account_id: &str, shards_count: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    account_id.hash(&mut hasher);
+
+    return (hasher.finish() % shards_count as u64) as usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        positions::CrossMarginPosition,
+        test_utils::{
+            bid_ask, default_trading_groups, eurusd, TestAccount, TestActivePosition,
+            TestPendingPosition,
+        },
+        CrossMarginPositionSide,
+    };
+
+    use super::*;
+
+    type TestShardedCaches =
+        CrossMarginShardedCaches<TestAccount, TestActivePosition, TestPendingPosition>;
+
+    fn create_accounts(count: usize) -> Vec<TestAccount> {
+        return (0..count)
+            .map(|i| TestAccount::new(&format!("acc-{}", i), 1000.0, 100.0))
+            .collect();
+    }
+
+    fn create_positions(accounts: usize, per_account: usize) -> Vec<TestActivePosition> {
+        let instrument = eurusd();
+        let mut positions = vec![];
+
+        for account in 0..accounts {
+            for i in 0..per_account {
+                let mut position = TestActivePosition::new(
+                    &format!("position-{}-{}", account, i),
+                    &format!("acc-{}", account),
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    1.0,
+                    1.1002,
+                );
+                position.sl_price = Some(1.09 - 0.001 * i as f64);
+                positions.push(position);
+            }
+        }
+
+        return positions;
+    }
+
+    async fn create_sharded(shards_count: usize, accounts: usize) -> TestShardedCaches {
+        let instrument = eurusd();
+
+        let caches = CrossMarginShardedCaches::new(
+            shards_count,
+            create_accounts(accounts),
+            vec![],
+            vec![],
+            vec![instrument.clone()],
+            vec!["USD".to_string()],
+            vec![bid_ask(&instrument, 1.1, 1.1002)],
+            CrossMarginInstrumentsCache::new(vec![]),
+            default_trading_groups(),
+        )
+        .await
+        .unwrap();
+
+        return caches;
+    }
+
+    async fn open_positions(caches: &TestShardedCaches, positions: Vec<TestActivePosition>) {
+        for position in positions {
+            let mut shard = caches.lock_account_shard(position.get_account_id()).await;
+            shard.add_active_position(position, "open").await.unwrap();
+        }
+    }
+
+    async fn get_balances(caches: &TestShardedCaches) -> Vec<(String, f64)> {
+        let mut balances = vec![];
+
+        for shard in caches.shards.iter() {
+            for account in shard.lock().await.accounts_cache.get_all().await {
+                balances.push((account.id.clone(), account.balance));
+            }
+        }
+
+        balances.sort_by(|x, y| x.0.cmp(&y.0));
+        return balances;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sharded_results_match_single_shard() {
+        let instrument = eurusd();
+        let single = create_sharded(1, 20).await;
+        let sharded = create_sharded(4, 20).await;
+        open_positions(&single, create_positions(20, 5)).await;
+        open_positions(&sharded, create_positions(20, 5)).await;
+
+        let mut single_closed = vec![];
+        let mut sharded_closed = vec![];
+
+        for bid in [1.095, 1.089, 1.087, 1.2] {
+            let tick = bid_ask(&instrument, bid, bid + 0.0002);
+            let result = single.handle_bid_ask(tick.clone(), "tick").await.unwrap();
+            single_closed.extend(result.closed_positions.into_iter().map(|x| x.0.id));
+            let result = sharded.handle_bid_ask(tick, "tick").await.unwrap();
+            sharded_closed.extend(result.closed_positions.into_iter().map(|x| x.0.id));
+        }
+
+        single_closed.sort();
+        sharded_closed.sort();
+
+        assert!(!single_closed.is_empty());
+        assert_eq!(single_closed, sharded_closed);
+        assert_eq!(get_balances(&single).await, get_balances(&sharded).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tick_is_applied_to_shared_prices() {
+        let instrument = eurusd();
+        let caches = create_sharded(4, 20).await;
+
+        caches
+            .handle_bid_ask(bid_ask(&instrument, 1.2, 1.2002), "tick")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            caches.prices_cache.get_by_id(&instrument.id).unwrap().bid,
+            1.2
+        );
+
+        for shard in caches.shards.iter() {
+            let shard = shard.lock().await;
+            let price = shard.prices_cache.get_by_id(&instrument.id).unwrap();
+            assert_eq!(price.bid, 1.2);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CrossMarginCommissionType {
+    // Rate is an amount in rule currency per lot.
+    PerLot = 0,
+    // Rate is a percent of the position notional.
+    NotionalPercentage = 1,
+}
+
+// Rule without instrument is a trading group default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginCommissionRule {
+    pub instrument_id: Option<String>,
+    pub commission_type: CrossMarginCommissionType,
+    pub open_rate: f64,
+    pub close_rate: f64,
+    pub min_fee: f64,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CrossMarginCommissionSide {
+    Open = 0,
+    Close = 1,
+}
+
+impl CrossMarginCommissionRule {
+    pub fn get_rate(&self, side: CrossMarginCommissionSide) -> f64 {
+        match side {
+            CrossMarginCommissionSide::Open => self.open_rate,
+            CrossMarginCommissionSide::Close => self.close_rate,
+        }
+    }
+}
+
+// All amounts are in account currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginCommissionBreakdown {
+    pub side: CrossMarginCommissionSide,
+    pub commission_type: CrossMarginCommissionType,
+    pub rate: f64,
+    pub lots_amount: f64,
+    pub notional: f64,
+    pub calculated_fee: f64,
+    pub min_fee: f64,
+    pub charged_fee: f64,
+}
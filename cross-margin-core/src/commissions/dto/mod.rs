@@ -0,0 +1,3 @@
+mod commission_rule;
+
+pub use commission_rule::*;
@@ -0,0 +1,3 @@
+mod dto;
+
+pub use dto::*;
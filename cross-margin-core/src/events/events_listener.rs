@@ -0,0 +1,31 @@
+use crate::{
+    positions::{
+        CrossMarginActivePosition, CrossMarginPendingPosition,
+        CrossMarginPendingPositionExecuteReason, CrossMarginPositionSettlement,
+    },
+    CrossMarginAccount, CrossMarginCloseReason,
+};
+
+// Engine lifecycle callbacks, called synchronously after the change is applied to the caches.
+pub trait CrossMarginEventsListener<A, AP, PP>: Send + Sync
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    fn on_position_opened(&self, _position: &AP) {}
+    fn on_position_updated(&self, _position: &AP) {}
+    fn on_position_closed(
+        &self,
+        _position: &AP,
+        _reason: &CrossMarginCloseReason,
+        _settlement: &CrossMarginPositionSettlement,
+    ) {
+    }
+    fn on_pending_triggered(&self, _order: &PP) {}
+    fn on_pending_rejected(&self, _order: &PP, _reason: &CrossMarginPendingPositionExecuteReason) {}
+    fn on_balance_changed(&self, _account: &A, _delta: f64, _process_id: &str) {}
+    // Called once when the margin level drops to the margin call level.
+    fn on_margin_call(&self, _account: &A, _margin_level: f64) {}
+    fn on_stop_out(&self, _account: &A, _margin_level: f64) {}
+}
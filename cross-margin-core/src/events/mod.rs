@@ -0,0 +1,5 @@
+mod events_listener;
+mod recording_listener;
+
+pub use events_listener::*;
+pub use recording_listener::*;
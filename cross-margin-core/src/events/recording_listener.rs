@@ -0,0 +1,207 @@
+use std::sync::Mutex;
+
+use crate::{
+    positions::{
+        CrossMarginActivePosition, CrossMarginPendingPosition,
+        CrossMarginPendingPositionExecuteReason, CrossMarginPositionSettlement,
+    },
+    CrossMarginAccount, CrossMarginCloseReason,
+};
+
+use super::CrossMarginEventsListener;
+
+#[derive(Debug, Clone)]
+pub enum CrossMarginRecordedEvent {
+    PositionOpened {
+        position_id: String,
+    },
+    PositionUpdated {
+        position_id: String,
+    },
+    PositionClosed {
+        position_id: String,
+        reason: CrossMarginCloseReason,
+        settlement: CrossMarginPositionSettlement,
+    },
+    PendingTriggered {
+        order_id: String,
+    },
+    PendingRejected {
+        order_id: String,
+        reason: CrossMarginPendingPositionExecuteReason,
+    },
+    BalanceChanged {
+        account_id: String,
+        delta: f64,
+        balance: f64,
+        process_id: String,
+    },
+    MarginCall {
+        account_id: String,
+        margin_level: f64,
+    },
+    StopOut {
+        account_id: String,
+        margin_level: f64,
+    },
+}
+
+// Keeps every event in memory, intended for tests.
+pub struct CrossMarginRecordingListener {
+    events: Mutex<Vec<CrossMarginRecordedEvent>>,
+}
+
+impl CrossMarginRecordingListener {
+    pub fn new() -> Self {
+        Self {
+            events: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn get_events(&self) -> Vec<CrossMarginRecordedEvent> {
+        return self.events.lock().unwrap().clone();
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+
+    fn record(&self, event: CrossMarginRecordedEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl<A, AP, PP> CrossMarginEventsListener<A, AP, PP> for CrossMarginRecordingListener
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    fn on_position_opened(&self, position: &AP) {
+        self.record(CrossMarginRecordedEvent::PositionOpened {
+            position_id: position.get_id().to_string(),
+        });
+    }
+
+    fn on_position_updated(&self, position: &AP) {
+        self.record(CrossMarginRecordedEvent::PositionUpdated {
+            position_id: position.get_id().to_string(),
+        });
+    }
+
+    fn on_position_closed(
+        &self,
+        position: &AP,
+        reason: &CrossMarginCloseReason,
+        settlement: &CrossMarginPositionSettlement,
+    ) {
+        self.record(CrossMarginRecordedEvent::PositionClosed {
+            position_id: position.get_id().to_string(),
+            reason: reason.clone(),
+            settlement: settlement.clone(),
+        });
+    }
+
+    fn on_pending_triggered(&self, order: &PP) {
+        self.record(CrossMarginRecordedEvent::PendingTriggered {
+            order_id: order.get_id().to_string(),
+        });
+    }
+
+    fn on_pending_rejected(&self, order: &PP, reason: &CrossMarginPendingPositionExecuteReason) {
+        self.record(CrossMarginRecordedEvent::PendingRejected {
+            order_id: order.get_id().to_string(),
+            reason: reason.clone(),
+        });
+    }
+
+    fn on_balance_changed(&self, account: &A, delta: f64, process_id: &str) {
+        self.record(CrossMarginRecordedEvent::BalanceChanged {
+            account_id: account.get_id().to_string(),
+            delta,
+            balance: account.get_balance(),
+            process_id: process_id.to_string(),
+        });
+    }
+
+    fn on_margin_call(&self, account: &A, margin_level: f64) {
+        self.record(CrossMarginRecordedEvent::MarginCall {
+            account_id: account.get_id().to_string(),
+            margin_level,
+        });
+    }
+
+    fn on_stop_out(&self, account: &A, margin_level: f64) {
+        self.record(CrossMarginRecordedEvent::StopOut {
+            account_id: account.get_id().to_string(),
+            margin_level,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        test_utils::{bid_ask, create_caches, eurusd, TestAccount, TestActivePosition},
+        CrossMarginPositionSide,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_records_position_lifecycle() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        let listener = Arc::new(CrossMarginRecordingListener::new());
+        caches.add_listener(listener.clone());
+
+        let position = TestActivePosition::new(
+            "position",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1,
+        );
+        caches.add_active_position(position, "open").await.unwrap();
+        caches
+            .handle_bid_ask(bid_ask(&instrument, 1.2, 1.2002), "tick")
+            .await;
+        caches
+            .remove_active_position("position", "close", None)
+            .await
+            .unwrap();
+
+        let events = listener.get_events();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            &events[0],
+            CrossMarginRecordedEvent::PositionOpened { position_id } if position_id == "position"
+        ));
+        assert!(matches!(
+            &events[1],
+            CrossMarginRecordedEvent::PositionUpdated { .. }
+        ));
+        // callbacks see the balance already settled
+        assert!(matches!(
+            &events[2],
+            CrossMarginRecordedEvent::BalanceChanged { process_id, .. } if process_id == "close"
+        ));
+        assert!(matches!(
+            &events[3],
+            CrossMarginRecordedEvent::PositionClosed {
+                reason: CrossMarginCloseReason::ClientCommand,
+                ..
+            }
+        ));
+
+        // closing is not remembered as a deposit of the same process
+        let account = caches
+            .update_account_balance("acc", 100.0, "close", false, None)
+            .await
+            .unwrap();
+        assert!((account.balance - 1000.0 - 10.0 - 100.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+
+use crate::{
+    exposure::{
+        CrossMarginCurrencyExposure, CrossMarginExposure, CrossMarginExposureBucket,
+        CrossMarginExposureContribution,
+    },
+    positions::CrossMarginActivePosition,
+    CrossMarginBidAskCache, CrossMarginError,
+};
+
+// Buckets are updated per position change, reports convert them to the reporting currency on read.
+pub struct CrossMarginExposureCache {
+    // POSITION_ID - CONTRIBUTION
+    contributions: HashMap<String, CrossMarginExposureContribution>,
+    // (TRADING_GROUP, INSTRUMENT_ID) - BUCKET
+    buckets: HashMap<(String, String), CrossMarginExposureBucket>,
+}
+
+impl CrossMarginExposureCache {
+    pub fn new() -> Self {
+        Self {
+            contributions: HashMap::new(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn update_position(
+        &mut self,
+        position: &impl CrossMarginActivePosition,
+        trading_group: &str,
+    ) {
+        self.remove_position(position.get_id());
+
+        let contribution = CrossMarginExposureContribution::new(position, trading_group);
+        let bucket = self
+            .buckets
+            .entry((
+                contribution.trading_group.clone(),
+                contribution.instrument_id.clone(),
+            ))
+            .or_insert_with(|| CrossMarginExposureBucket {
+                trading_group: contribution.trading_group.clone(),
+                instrument_id: contribution.instrument_id.clone(),
+                base: contribution.base.clone(),
+                quote: contribution.quote.clone(),
+                ..Default::default()
+            });
+
+        bucket.apply(&contribution, 1.0);
+        bucket.positions_count += 1;
+
+        self.contributions
+            .insert(position.get_id().to_string(), contribution);
+    }
+
+    pub fn remove_position(&mut self, position_id: &str) {
+        let Some(contribution) = self.contributions.remove(position_id) else {
+            return;
+        };
+
+        let key = (
+            contribution.trading_group.clone(),
+            contribution.instrument_id.clone(),
+        );
+
+        if let Some(bucket) = self.buckets.get_mut(&key) {
+            bucket.apply(&contribution, -1.0);
+            bucket.positions_count -= 1;
+
+            if bucket.positions_count == 0 {
+                self.buckets.remove(&key);
+            }
+        }
+    }
+
+    pub fn get_buckets(&self) -> Vec<&CrossMarginExposureBucket> {
+        let mut result: Vec<_> = self.buckets.values().collect();
+        result.sort_by(|x, y| {
+            (&x.trading_group, &x.instrument_id).cmp(&(&y.trading_group, &y.instrument_id))
+        });
+
+        return result;
+    }
+
+    pub fn get_instruments_exposure(
+        &self,
+        prices_cache: &CrossMarginBidAskCache,
+        reporting_currency: &str,
+    ) -> Result<Vec<CrossMarginExposure>, CrossMarginError> {
+        return self.group_exposure(prices_cache, reporting_currency, |x| &x.instrument_id);
+    }
+
+    pub fn get_trading_groups_exposure(
+        &self,
+        prices_cache: &CrossMarginBidAskCache,
+        reporting_currency: &str,
+    ) -> Result<Vec<CrossMarginExposure>, CrossMarginError> {
+        return self.group_exposure(prices_cache, reporting_currency, |x| &x.trading_group);
+    }
+
+    pub fn get_currencies_exposure(
+        &self,
+        prices_cache: &CrossMarginBidAskCache,
+        reporting_currency: &str,
+    ) -> Result<Vec<CrossMarginCurrencyExposure>, CrossMarginError> {
+        // CURRENCY - (NET, GROSS, PL)
+        let mut amounts: HashMap<&str, (f64, f64, f64)> = HashMap::new();
+
+        for bucket in self.buckets.values() {
+            let base = amounts.entry(&bucket.base).or_insert((0.0, 0.0, 0.0));
+            base.0 += bucket.buy_volume - bucket.sell_volume;
+            base.1 += bucket.buy_volume + bucket.sell_volume;
+
+            let pl = convert_pl(prices_cache, bucket, reporting_currency)?;
+            let quote = amounts.entry(&bucket.quote).or_insert((0.0, 0.0, 0.0));
+            quote.0 -= bucket.buy_quote_amount - bucket.sell_quote_amount;
+            quote.1 += bucket.buy_quote_amount + bucket.sell_quote_amount;
+            quote.2 += pl;
+        }
+
+        let mut result = vec![];
+
+        for (currency, (net_amount, gross_amount, unrealized_pl)) in amounts {
+            let rate = get_conversion_rate(prices_cache, currency, reporting_currency)?;
+
+            result.push(CrossMarginCurrencyExposure {
+                currency: currency.to_string(),
+                net_amount,
+                gross_amount,
+                net_notional: net_amount * rate,
+                gross_notional: gross_amount * rate,
+                unrealized_pl,
+            });
+        }
+
+        result.sort_by(|x, y| x.currency.cmp(&y.currency));
+
+        return Ok(result);
+    }
+
+    fn group_exposure(
+        &self,
+        prices_cache: &CrossMarginBidAskCache,
+        reporting_currency: &str,
+        get_id: impl Fn(&CrossMarginExposureBucket) -> &String,
+    ) -> Result<Vec<CrossMarginExposure>, CrossMarginError> {
+        let mut result: HashMap<&String, CrossMarginExposure> = HashMap::new();
+
+        for bucket in self.buckets.values() {
+            let rate = get_conversion_rate(prices_cache, &bucket.base, reporting_currency)?;
+            let pl = convert_pl(prices_cache, bucket, reporting_currency)?;
+            let id = get_id(bucket);
+
+            let exposure = result.entry(id).or_insert_with(|| CrossMarginExposure {
+                id: id.clone(),
+                buy_lots: 0.0,
+                sell_lots: 0.0,
+                net_lots: 0.0,
+                gross_lots: 0.0,
+                net_notional: 0.0,
+                gross_notional: 0.0,
+                unrealized_pl: 0.0,
+            });
+
+            exposure.buy_lots += bucket.buy_lots;
+            exposure.sell_lots += bucket.sell_lots;
+            exposure.net_lots += bucket.buy_lots - bucket.sell_lots;
+            exposure.gross_lots += bucket.buy_lots + bucket.sell_lots;
+            exposure.net_notional += (bucket.buy_volume - bucket.sell_volume) * rate;
+            exposure.gross_notional += (bucket.buy_volume + bucket.sell_volume) * rate;
+            exposure.unrealized_pl += pl;
+        }
+
+        let mut result: Vec<_> = result.into_values().collect();
+        result.sort_by(|x, y| x.id.cmp(&y.id));
+
+        return Ok(result);
+    }
+}
+
+fn convert_pl(
+    prices_cache: &CrossMarginBidAskCache,
+    bucket: &CrossMarginExposureBucket,
+    reporting_currency: &str,
+) -> Result<f64, CrossMarginError> {
+    let mut result = 0.0;
+
+    for (collateral, pl) in &bucket.pl {
+        result += pl * get_conversion_rate(prices_cache, collateral, reporting_currency)?;
+    }
+
+    return Ok(result);
+}
+
+// Mid price is used, reporting is not a trade.
+fn get_conversion_rate(
+    prices_cache: &CrossMarginBidAskCache,
+    from: &str,
+    to: &str,
+) -> Result<f64, CrossMarginError> {
+    let bid_ask = prices_cache
+        .get_price(from, to)
+        .ok_or(CrossMarginError::AssetNotFound(format!(
+            "{}-{} for exposure NOT FOUND",
+            from, to
+        )))?;
+
+    return Ok((bid_ask.bid + bid_ask.ask) / 2.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache_aggregate::initialize_bid_ask_cache,
+        exposure::CrossMarginExposureCache,
+        test_utils::{bid_ask, create_caches, eurusd, TestAccount, TestActivePosition},
+        CrossMarginCacheInstrument, CrossMarginError, CrossMarginPositionSide,
+    };
+
+    fn gbpusd() -> CrossMarginCacheInstrument {
+        CrossMarginCacheInstrument {
+            id: "GBPUSD".to_string(),
+            base: "GBP".to_string(),
+            quote: "USD".to_string(),
+        }
+    }
+
+    fn position(
+        id: &str,
+        instrument: &CrossMarginCacheInstrument,
+        side: CrossMarginPositionSide,
+        lots: f64,
+    ) -> TestActivePosition {
+        return TestActivePosition::new(id, "acc", instrument, side, lots, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_exposure_is_sorted_by_id() {
+        let (eurusd, gbpusd) = (eurusd(), gbpusd());
+        let prices_cache = initialize_bid_ask_cache(
+            vec![eurusd.clone(), gbpusd.clone()],
+            vec!["USD".to_string()],
+            vec![bid_ask(&eurusd, 1.1, 1.1), bid_ask(&gbpusd, 1.3, 1.3)],
+        )
+        .await;
+
+        let mut cache = CrossMarginExposureCache::new();
+        cache.update_position(
+            &position("1", &gbpusd, CrossMarginPositionSide::Buy, 1.0),
+            "vip",
+        );
+        cache.update_position(
+            &position("2", &eurusd, CrossMarginPositionSide::Buy, 1.0),
+            "default",
+        );
+        cache.update_position(
+            &position("3", &gbpusd, CrossMarginPositionSide::Sell, 1.0),
+            "default",
+        );
+
+        let instruments: Vec<_> = cache
+            .get_instruments_exposure(&prices_cache, "USD")
+            .unwrap()
+            .into_iter()
+            .map(|x| x.id)
+            .collect();
+        assert_eq!(instruments, vec!["EURUSD", "GBPUSD"]);
+
+        let groups: Vec<_> = cache
+            .get_trading_groups_exposure(&prices_cache, "USD")
+            .unwrap()
+            .into_iter()
+            .map(|x| x.id)
+            .collect();
+        assert_eq!(groups, vec!["default", "vip"]);
+
+        let currencies: Vec<_> = cache
+            .get_currencies_exposure(&prices_cache, "USD")
+            .unwrap()
+            .into_iter()
+            .map(|x| x.currency)
+            .collect();
+        assert_eq!(currencies, vec!["EUR", "GBP", "USD"]);
+
+        let buckets: Vec<_> = cache
+            .get_buckets()
+            .into_iter()
+            .map(|x| (x.trading_group.as_str(), x.instrument_id.as_str()))
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![
+                ("default", "EURUSD"),
+                ("default", "GBPUSD"),
+                ("vip", "GBPUSD")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_position_update_replaces_its_contribution() {
+        let instrument = eurusd();
+        let mut cache = CrossMarginExposureCache::new();
+        let mut buy = position("buy", &instrument, CrossMarginPositionSide::Buy, 10.0);
+
+        cache.update_position(&buy, "default");
+        buy.lots_amount = 4.0;
+        cache.update_position(&buy, "default");
+
+        let buckets = cache.get_buckets();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].positions_count, 1);
+        assert_eq!(buckets[0].buy_lots, 4.0);
+
+        // moving to another group leaves no empty bucket behind
+        cache.update_position(&buy, "vip");
+        let buckets = cache.get_buckets();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].trading_group, "vip");
+
+        cache.remove_position("unknown");
+        cache.remove_position("buy");
+        assert!(cache.get_buckets().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exposure_without_conversion_rate_fails() {
+        let instrument = eurusd();
+        let prices_cache = initialize_bid_ask_cache(
+            vec![instrument.clone()],
+            vec!["USD".to_string()],
+            vec![bid_ask(&instrument, 1.1, 1.1)],
+        )
+        .await;
+
+        let mut cache = CrossMarginExposureCache::new();
+        cache.update_position(
+            &position("buy", &instrument, CrossMarginPositionSide::Buy, 1.0),
+            "default",
+        );
+
+        let result = cache.get_instruments_exposure(&prices_cache, "JPY");
+        assert!(matches!(result, Err(CrossMarginError::AssetNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_exposure_follows_positions_and_ticks() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 100_000.0, 100.0)]).await;
+
+        for (id, side, lots) in [
+            ("buy", CrossMarginPositionSide::Buy, 3000.0),
+            ("sell", CrossMarginPositionSide::Sell, 1000.0),
+        ] {
+            caches
+                .add_active_position(
+                    TestActivePosition::new(id, "acc", &instrument, side, lots, 1.1),
+                    "open",
+                )
+                .await
+                .unwrap();
+        }
+
+        caches
+            .handle_bid_ask(bid_ask(&instrument, 1.2, 1.2), "tick")
+            .await;
+
+        let exposure = caches
+            .exposure_cache
+            .get_instruments_exposure(&caches.prices_cache, "USD")
+            .unwrap();
+        assert_eq!(exposure.len(), 1);
+        assert_eq!(exposure[0].net_lots, 2000.0);
+        assert_eq!(exposure[0].gross_lots, 4000.0);
+        assert!((exposure[0].net_notional - 2400.0).abs() < 1e-9);
+        assert!((exposure[0].unrealized_pl - 200.0).abs() < 1e-9);
+
+        let currencies = caches
+            .exposure_cache
+            .get_currencies_exposure(&caches.prices_cache, "USD")
+            .unwrap();
+        let usd = currencies.iter().find(|x| x.currency == "USD").unwrap();
+        assert!((usd.net_amount + 2400.0).abs() < 1e-9);
+
+        caches
+            .remove_active_position("buy", "close", None)
+            .await
+            .unwrap();
+        caches
+            .remove_active_position("sell", "close", None)
+            .await
+            .unwrap();
+        assert!(caches.exposure_cache.get_buckets().is_empty());
+    }
+}
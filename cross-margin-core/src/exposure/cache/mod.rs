@@ -0,0 +1,3 @@
+mod exposure_cache;
+
+pub use exposure_cache::*;
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{positions::CrossMarginActivePosition, CrossMarginPositionSide};
+
+// Signed contribution of one position, buy is positive and sell is negative.
+#[derive(Debug, Clone)]
+pub struct CrossMarginExposureContribution {
+    pub trading_group: String,
+    pub instrument_id: String,
+    pub base: String,
+    pub quote: String,
+    pub collateral: String,
+    pub side: CrossMarginPositionSide,
+    pub lots_amount: f64,
+    // Base currency amount.
+    pub volume: f64,
+    // Quote currency amount at the active price.
+    pub quote_amount: f64,
+    pub pl: f64,
+}
+
+impl CrossMarginExposureContribution {
+    pub fn new(position: &impl CrossMarginActivePosition, trading_group: &str) -> Self {
+        let volume = position.get_lots_size() * position.get_lots_amount();
+
+        Self {
+            trading_group: trading_group.to_string(),
+            instrument_id: position.get_instrument_id().to_string(),
+            base: position.get_base().to_string(),
+            quote: position.get_quote().to_string(),
+            collateral: position.get_collateral().to_string(),
+            side: position.get_side().clone(),
+            lots_amount: position.get_lots_amount(),
+            volume,
+            quote_amount: volume * position.get_active_price(),
+            pl: position.get_pl(),
+        }
+    }
+}
+
+// Sums of contributions of one trading group in one instrument.
+#[derive(Debug, Clone, Default)]
+pub struct CrossMarginExposureBucket {
+    pub trading_group: String,
+    pub instrument_id: String,
+    pub base: String,
+    pub quote: String,
+    pub positions_count: usize,
+    pub buy_lots: f64,
+    pub sell_lots: f64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    pub buy_quote_amount: f64,
+    pub sell_quote_amount: f64,
+    // COLLATERAL - PL
+    pub pl: HashMap<String, f64>,
+}
+
+impl CrossMarginExposureBucket {
+    pub fn apply(&mut self, contribution: &CrossMarginExposureContribution, sign: f64) {
+        match contribution.side {
+            CrossMarginPositionSide::Buy => {
+                self.buy_lots += sign * contribution.lots_amount;
+                self.buy_volume += sign * contribution.volume;
+                self.buy_quote_amount += sign * contribution.quote_amount;
+            }
+            CrossMarginPositionSide::Sell => {
+                self.sell_lots += sign * contribution.lots_amount;
+                self.sell_volume += sign * contribution.volume;
+                self.sell_quote_amount += sign * contribution.quote_amount;
+            }
+        }
+
+        *self
+            .pl
+            .entry(contribution.collateral.clone())
+            .or_insert(0.0) += sign * contribution.pl;
+    }
+}
+
+// Lots are summed across instruments for trading group rows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginExposure {
+    pub id: String,
+    pub buy_lots: f64,
+    pub sell_lots: f64,
+    pub net_lots: f64,
+    pub gross_lots: f64,
+    pub net_notional: f64,
+    pub gross_notional: f64,
+    pub unrealized_pl: f64,
+}
+
+// Amounts are in the currency itself, PnL is of the positions quoted in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginCurrencyExposure {
+    pub currency: String,
+    pub net_amount: f64,
+    pub gross_amount: f64,
+    pub net_notional: f64,
+    pub gross_notional: f64,
+    pub unrealized_pl: f64,
+}
@@ -0,0 +1,3 @@
+mod exposure;
+
+pub use exposure::*;
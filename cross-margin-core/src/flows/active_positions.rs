@@ -1,11 +1,14 @@
 use crate::{
-    positions::CrossMarginActivePosition, CrossMarginBidAskCache, CrossMarginError,
-    CrossMarginPositionSide,
+    flows::apply_spread_markup, instruments::CrossMarginInstrumentsCache,
+    positions::CrossMarginActivePosition, trading_groups::CrossMarginSpreadMarkup,
+    CrossMarginBidAsk, CrossMarginBidAskCache, CrossMarginError, CrossMarginPositionSide,
 };
 
 pub fn update_position_rates(
     active_position: &mut impl CrossMarginActivePosition,
     cache: &CrossMarginBidAskCache,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    spread_markup: Option<&CrossMarginSpreadMarkup>,
 ) -> Result<(), CrossMarginError> {
     let profit_bid_ask = cache
         .get_price(
@@ -26,6 +29,7 @@ pub fn update_position_rates(
             active_position.get_id()
         )),
     )?;
+    let asset_price = apply_spread_markup(instruments_cache, spread_markup, &asset_price);
 
     let profit_rate = match active_position.get_pl() > 0.0 {
         true => profit_bid_ask.bid,
@@ -34,10 +38,27 @@ pub fn update_position_rates(
 
     active_position.update_profit_price(profit_bid_ask.as_ref().clone(), profit_rate);
     active_position.update_asset_price(
-        asset_price.as_ref().clone(),
+        asset_price.clone(),
         asset_price.get_close_price(active_position.get_side()),
     );
 
+    active_position.update_pl(calculate_pl(active_position));
+
+    return Ok(());
+}
+
+// Rates the position at a close price touched within a batch, e.g. by a skipped tick triggering
+// its stop. The position is closed at this price.
+pub fn update_position_close_price(
+    active_position: &mut impl CrossMarginActivePosition,
+    bid_ask: CrossMarginBidAsk,
+    close_price: f64,
+) {
+    active_position.update_asset_price(bid_ask, close_price);
+    active_position.update_pl(calculate_pl(active_position));
+}
+
+fn calculate_pl(active_position: &impl CrossMarginActivePosition) -> f64 {
     let open_side = active_position.get_open_price()
         * active_position.get_lots_size()
         * active_position.get_lots_amount();
@@ -45,12 +66,8 @@ pub fn update_position_rates(
         * active_position.get_lots_size()
         * active_position.get_lots_amount();
 
-    let pl = match active_position.get_side() {
+    return match active_position.get_side() {
         &CrossMarginPositionSide::Buy => close_side - open_side,
         &CrossMarginPositionSide::Sell => open_side - close_side,
     } * active_position.get_profit_price();
-
-    active_position.update_pl(pl);
-
-    return Ok(());
 }
@@ -0,0 +1,118 @@
+use crate::{
+    instruments::CrossMarginInstrumentsCache,
+    trading_groups::{CrossMarginSpreadMarkup, CrossMarginTradingGroupsCache},
+    CrossMarginAccount, CrossMarginBidAsk, CrossMarginError,
+};
+
+// Raw feed price stays in the prices cache, accounts see it with their trading group markup.
+pub fn get_account_bid_ask(
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    account: &impl CrossMarginAccount,
+    bid_ask: &CrossMarginBidAsk,
+) -> Result<CrossMarginBidAsk, CrossMarginError> {
+    return Ok(apply_spread_markup(
+        instruments_cache,
+        trading_groups_cache
+            .get_account_group(account)?
+            .spread_markup
+            .as_ref(),
+        bid_ask,
+    ));
+}
+
+pub fn apply_spread_markup(
+    instruments_cache: &CrossMarginInstrumentsCache,
+    spread_markup: Option<&CrossMarginSpreadMarkup>,
+    bid_ask: &CrossMarginBidAsk,
+) -> CrossMarginBidAsk {
+    let Some(spread_markup) = spread_markup else {
+        return bid_ask.clone();
+    };
+
+    let tick_size = instruments_cache
+        .get_spec(&bid_ask.asset_pair)
+        .map(|x| x.get_tick_size());
+
+    return spread_markup.apply(bid_ask, tick_size);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{bid_ask, create_caches, eurusd, TestAccount, TestActivePosition},
+        trading_groups::{
+            CrossMarginSpreadMarkup, CrossMarginSpreadMarkupType, CrossMarginTradingGroup,
+        },
+        CrossMarginInstrumentSpec, CrossMarginPositionSide,
+    };
+
+    #[tokio::test]
+    async fn test_group_markup_applies_to_account_prices() {
+        let instrument = eurusd();
+        let mut vip = TestAccount::new("vip", 1000.0, 100.0);
+        vip.trading_group = "vip".to_string();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0), vip]).await;
+        caches
+            .trading_groups_cache
+            .update_group(CrossMarginTradingGroup::new("vip"));
+
+        caches
+            .instruments_cache
+            .update_spec(CrossMarginInstrumentSpec {
+                id: instrument.id.clone(),
+                contract_size: 1.0,
+                min_lots: 0.0,
+                max_lots: 1000.0,
+                lot_step: 0.0,
+                digits: 5,
+                max_leverage: None,
+                stops_level: 0,
+            });
+        let mut group = CrossMarginTradingGroup::new("default");
+        group.spread_markup = Some(CrossMarginSpreadMarkup {
+            markup_type: CrossMarginSpreadMarkupType::Points,
+            bid: 10.0,
+            ask: 20.0,
+        });
+        caches.trading_groups_cache.update_group(group);
+
+        let account_price = caches.get_account_bid_ask("acc", &instrument.id).unwrap();
+        assert!((account_price.bid - 1.0999).abs() < 1e-9);
+        assert!((account_price.ask - 1.1004).abs() < 1e-9);
+
+        let raw = caches.get_account_bid_ask("vip", &instrument.id).unwrap();
+        assert_eq!(raw.bid, 1.1);
+        assert_eq!(raw.ask, 1.1002);
+
+        for account_id in ["acc", "vip"] {
+            caches
+                .add_active_position(
+                    TestActivePosition::new(
+                        account_id,
+                        account_id,
+                        &instrument,
+                        CrossMarginPositionSide::Buy,
+                        1.0,
+                        1.1002,
+                    ),
+                    "open",
+                )
+                .await
+                .unwrap();
+        }
+
+        caches
+            .handle_bid_ask(bid_ask(&instrument, 1.1002, 1.1004), "tick")
+            .await;
+
+        let marked_up = caches.active_positions_cache.get_by_id("acc").unwrap();
+        let raw = caches.active_positions_cache.get_by_id("vip").unwrap();
+        assert!((marked_up.active_price - 1.1001).abs() < 1e-9);
+        assert_eq!(raw.active_price, 1.1002);
+        assert_eq!(
+            caches.prices_cache.get_by_id(&instrument.id).unwrap().bid,
+            1.1002
+        );
+    }
+}
@@ -1,14 +1,16 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::{
     cache_aggregate::CrossMarginCaches,
-    flows::{get_position_close_reason, update_position_rates},
-    positions::{
-        CrossMarginActivePosition, CrossMarginPendingPosition,
-        CrossMarginPositionsOneOfBulkQueryBuilder,
+    flows::{
+        get_position_close_reason, get_position_range_close_reason, update_position_close_price,
+        update_position_rates,
     },
-    CrossMarginAccount, CrossMarginBidAsk, CrossMarginCloseReason,
+    positions::{CrossMarginActivePosition, CrossMarginPendingPosition},
+    CrossMarginAccount, CrossMarginCloseReason, CrossMarginCoalescedBidAsk,
+    CrossMarginPositionSide,
 };
 
-
 #[derive(Debug, Clone)]
 pub struct UpdatePositionsDto {
     pub trader_id: String,
@@ -23,12 +25,47 @@ pub fn update_active_positions_rates<
     W: CrossMarginPendingPosition,
 >(
     caches: &mut CrossMarginCaches<T, F, W>,
-    new_bid_ask: &CrossMarginBidAsk,
+    new_prices: &[CrossMarginCoalescedBidAsk],
 ) -> Vec<UpdatePositionsDto> {
-    let search = vec![new_bid_ask.base.clone(), new_bid_ask.quote.clone()];
+    let mut affected_positions = HashSet::new();
+    // only batches with skipped ticks are checked against the extremes
+    let mut coalesced_prices = HashMap::new();
+
+    for coalesced in new_prices {
+        affected_positions.extend(
+            caches
+                .price_dependency_index
+                .get_positions(&coalesced.latest.asset_pair),
+        );
+
+        if coalesced.ticks_count > 1 {
+            coalesced_prices.insert(coalesced.latest.asset_pair.as_str(), coalesced);
+        }
+    }
+    let now = caches.clock.now();
+    let sl_tp_on_closed_market = caches.settings.sl_tp_on_closed_market;
 
     let update_function = |position: &mut F| {
-        if let Err(err) = update_position_rates(position, &caches.prices_cache) {
+        // positions of accounts without a known group are rated with raw prices, their margin
+        // can't be calculated so they are skipped by the stop out check
+        let spread_markup = caches
+            .accounts_cache
+            .get_account(position.get_account_id())
+            .and_then(|account| {
+                caches
+                    .trading_groups_cache
+                    .get_account_group(account)
+                    .ok()?
+                    .spread_markup
+                    .as_ref()
+            });
+
+        if let Err(err) = update_position_rates(
+            position,
+            &caches.prices_cache,
+            &caches.instruments_cache,
+            spread_markup,
+        ) {
             panic!(
                 "Error to update position rates: {}. Err: {:?}",
                 position.get_id(),
@@ -36,19 +73,276 @@ pub fn update_active_positions_rates<
             )
         }
 
+        let is_close_allowed = sl_tp_on_closed_market
+            || caches
+                .instruments_cache
+                .is_market_open(position.get_instrument_id(), now);
+
+        let close_position_reason = match is_close_allowed {
+            true => get_position_close_reason(position).or_else(|| {
+                let coalesced = coalesced_prices.get(position.get_instrument_id())?;
+                let (reason, close_price) = get_position_range_close_reason(position, coalesced)?;
+
+                // the stop is filled at its level, not at the latest price
+                let mut bid_ask = coalesced.latest.clone();
+                match position.get_side() {
+                    CrossMarginPositionSide::Buy => bid_ask.bid = close_price,
+                    CrossMarginPositionSide::Sell => bid_ask.ask = close_price,
+                }
+                update_position_close_price(position, bid_ask, close_price);
+
+                return Some(reason);
+            }),
+            false => None,
+        };
+
         return Some(UpdatePositionsDto {
             trader_id: position.get_trader_id().to_string(),
             account_id: position.get_account_id().to_string(),
             position_id: position.get_id().to_string(),
-            close_position_reason: get_position_close_reason(position),
+            close_position_reason,
         });
     };
 
-    return caches.active_positions_cache.bulk_update_positions(
-        CrossMarginPositionsOneOfBulkQueryBuilder::new()
-            .with_base(search.clone())
-            .with_quote(search.clone())
-            .with_collateral(search.clone()),
-        update_function,
-    );
+    return caches
+        .active_positions_cache
+        .update_positions_by_ids(affected_positions, update_function);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{
+        test_utils::{
+            bid_ask, create_caches, default_trading_groups, eurusd, TestAccount,
+            TestActivePosition, TestCaches,
+        },
+        CrossMarginCacheInstrument, CrossMarginManualClock, CrossMarginPositionSide,
+        CrossMarginTradingSchedule, CrossMarginTradingTimeRange, CrossMarginWeeklySession,
+    };
+
+    // 2024-01-06 12:00 UTC, Saturday
+    const SATURDAY: i64 = 1_704_542_400_000_000;
+
+    async fn close_on_weekend(sl_tp_on_closed_market: bool) -> usize {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.clock = Arc::new(CrossMarginManualClock::new(DateTimeAsMicroseconds::new(
+            SATURDAY,
+        )));
+        caches.settings.sl_tp_on_closed_market = sl_tp_on_closed_market;
+
+        let mut position = TestActivePosition::new(
+            "position",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1,
+        );
+        position.sl_price = Some(1.05);
+        caches.add_active_position(position, "open").await.unwrap();
+
+        caches
+            .instruments_cache
+            .update_schedule(CrossMarginTradingSchedule {
+                instrument_id: instrument.id.clone(),
+                sessions: (0..5)
+                    .map(|day_of_week| CrossMarginWeeklySession {
+                        day_of_week,
+                        range: CrossMarginTradingTimeRange {
+                            from_minute: 0,
+                            to_minute: 24 * 60,
+                        },
+                    })
+                    .collect(),
+                overrides: vec![],
+            });
+
+        let result = caches
+            .handle_bid_ask(bid_ask(&instrument, 1.0, 1.0002), "tick")
+            .await;
+
+        return result.closed_positions.len();
+    }
+
+    #[tokio::test]
+    async fn test_sl_is_not_executed_on_closed_market() {
+        assert_eq!(close_on_weekend(false).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sl_is_executed_on_closed_market_when_allowed() {
+        assert_eq!(close_on_weekend(true).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_cross_leg_tick_updates_dependent_positions() {
+        let eurusd = eurusd();
+        let usdjpy = CrossMarginCacheInstrument {
+            id: "USDJPY".to_string(),
+            base: "USD".to_string(),
+            quote: "JPY".to_string(),
+        };
+
+        let mut caches: TestCaches = crate::CrossMarginCachesBuilder::new(
+            vec![TestAccount::new("acc", 100_000.0, 100.0)],
+            vec![],
+            vec![],
+            vec![eurusd.clone(), usdjpy.clone()],
+            vec!["EUR".to_string()],
+            vec![
+                bid_ask(&eurusd, 1.1, 1.1002),
+                bid_ask(&usdjpy, 150.0, 150.02),
+            ],
+        )
+        .with_trading_groups_cache(default_trading_groups())
+        .build()
+        .await
+        .unwrap();
+
+        // JPY-EUR profit price is a cross of USDJPY and EURUSD
+        let mut position = TestActivePosition::new(
+            "position",
+            "acc",
+            &usdjpy,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            150.02,
+        );
+        position.collateral = "EUR".to_string();
+        caches.add_active_position(position, "open").await.unwrap();
+
+        assert!(caches
+            .price_dependency_index
+            .get_accounts(&eurusd.id)
+            .contains("acc"));
+
+        caches
+            .handle_bid_ask(bid_ask(&eurusd, 1.2, 1.2002), "tick")
+            .await;
+
+        let position = caches.active_positions_cache.get_by_id("position").unwrap();
+        // losing position is rated at the JPY-EUR ask, 1 / (USDJPY bid * EURUSD bid)
+        assert!((position.profit_price - 1.0 / (150.0 * 1.2)).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn test_batch_closes_on_skipped_extremes() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+
+        let mut sl_position = TestActivePosition::new(
+            "sl",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1,
+        );
+        sl_position.sl_price = Some(1.09);
+        caches
+            .add_active_position(sl_position, "open")
+            .await
+            .unwrap();
+
+        let mut tp_position = TestActivePosition::new(
+            "tp",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Sell,
+            100.0,
+            1.1,
+        );
+        tp_position.tp_price = Some(1.0903);
+        caches
+            .add_active_position(tp_position, "open")
+            .await
+            .unwrap();
+
+        let mut kept_position = TestActivePosition::new(
+            "kept",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1,
+        );
+        kept_position.sl_price = Some(1.08);
+        caches
+            .add_active_position(kept_position, "open")
+            .await
+            .unwrap();
+
+        let result = caches
+            .handle_bid_ask_batch(
+                vec![
+                    bid_ask(&instrument, 1.095, 1.0952),
+                    bid_ask(&instrument, 1.089, 1.0892),
+                    bid_ask(&instrument, 1.1, 1.1002),
+                ],
+                "batch",
+            )
+            .await;
+
+        let mut closed: Vec<_> = result
+            .closed_positions
+            .iter()
+            .map(|(position, reason, settlement)| {
+                (position.id.clone(), reason.clone(), settlement.clone())
+            })
+            .collect();
+        closed.sort_by(|x, y| x.0.cmp(&y.0));
+
+        // stops crossed by skipped ticks are filled at their level, not at the extreme
+        assert_eq!(closed.len(), 2);
+        assert_eq!(closed[0].0, "sl");
+        assert!(matches!(closed[0].1, crate::CrossMarginCloseReason::Sl));
+        assert_eq!(closed[0].2.close_price, 1.09);
+        assert!((closed[0].2.pl - (1.09 - 1.1) * 100.0).abs() < 1e-9);
+        assert_eq!(closed[1].0, "tp");
+        assert!(matches!(closed[1].1, crate::CrossMarginCloseReason::Tp));
+        assert_eq!(closed[1].2.close_price, 1.0903);
+        assert!((closed[1].2.pl - (1.1 - 1.0903) * 100.0).abs() < 1e-9);
+
+        let kept = caches.active_positions_cache.get_by_id("kept").unwrap();
+        assert_eq!(kept.active_price, 1.1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_closes_profit_sl_at_its_level() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+
+        let mut position = TestActivePosition::new(
+            "position",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1,
+        );
+        position.sl_profit = Some(-1.0);
+        caches.add_active_position(position, "open").await.unwrap();
+
+        // the latest price is in profit, the loss is seen by a skipped tick only
+        let result = caches
+            .handle_bid_ask_batch(
+                vec![
+                    bid_ask(&instrument, 1.089, 1.0892),
+                    bid_ask(&instrument, 1.101, 1.1012),
+                ],
+                "batch",
+            )
+            .await;
+
+        assert_eq!(result.closed_positions.len(), 1);
+        let (_, reason, settlement) = &result.closed_positions[0];
+        assert!(matches!(reason, crate::CrossMarginCloseReason::Sl));
+        assert!((settlement.close_price - 1.09).abs() < 1e-9);
+        assert!((settlement.pl + 1.0).abs() < 1e-9);
+    }
 }
@@ -1,7 +1,9 @@
 mod handle_active_positions_bid_ask;
 mod process_update_positions;
 mod process_pending_new_price;
+mod process_rollover;
 
 pub use handle_active_positions_bid_ask::*;
 pub use process_update_positions::*;
-pub use process_pending_new_price::*;
\ No newline at end of file
+pub use process_pending_new_price::*;
+pub use process_rollover::*;
\ No newline at end of file
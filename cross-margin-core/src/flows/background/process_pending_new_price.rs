@@ -1,72 +1,587 @@
+use std::collections::HashMap;
+
 use crate::{
-    cache_aggregate::CrossMarginCaches,
-    flows::is_pending_ready_to_execute,
-    is_enough_balance_to_open_position_sync,
+    cache_aggregate::{CrossMarginCaches, CrossMarginPendingMarginPolicy},
+    flows::{
+        calculate_open_position_cost, get_account_bid_ask, get_pending_trigger_bid_ask,
+        CrossMarginOpenPositionCost,
+    },
+    instruments::CrossMarginInstrumentSpec,
     positions::{
         CrossMarginActivePosition, CrossMarginPendingPosition,
-        CrossMarginPendingPositionExecuteReason, CrossMarginPositionsCacheQueryBuilder,
+        CrossMarginPendingPositionExecuteReason, CrossMarginPendingTriggerEvent,
+        CrossMarginPendingTriggerOutcome, CrossMarginPositionsCacheQueryBuilder,
     },
-    CrossMarginAccount, CrossMarginBidAsk,
+    CrossMarginAccount, CrossMarginAccountTradingState, CrossMarginBidAsk,
+    CrossMarginCoalescedBidAsk, CrossMarginError,
 };
 
 pub struct ExecutePendingOrdersResult<P: CrossMarginPendingPosition> {
     pub failed_orders: Vec<(P, CrossMarginPendingPositionExecuteReason)>,
-    pub executed_orders: Vec<P>,
+    // Executed orders with the account price triggering them.
+    pub executed_orders: Vec<(P, CrossMarginBidAsk)>,
+    pub margin_events: Vec<CrossMarginPendingTriggerEvent>,
 }
 
+const AFFORDABLE_LOTS_ATTEMPTS: usize = 3;
+
+// Failed orders are removed, executed orders stay in the pending cache until their position is
+// opened. Partially filled orders are returned resized. Orders are triggered by the latest price
+// or by the extremes of the skipped ticks. Committed margin is shared by all instruments of the
+// batch, as executed orders are opened after all of them are checked.
 pub async fn remove_orders_ready_to_execute<
     T: CrossMarginAccount,
     F: CrossMarginActivePosition,
     W: CrossMarginPendingPosition,
 >(
     cache: &mut CrossMarginCaches<T, F, W>,
-    bid_ask: &CrossMarginBidAsk,
+    coalesced: &CrossMarginCoalescedBidAsk,
+    committed_margin: &mut HashMap<String, f64>,
 ) -> ExecutePendingOrdersResult<W> {
+    let bid_ask = &coalesced.latest;
     let pending_cache = &mut cache.pending_positions_cache;
     let active_cache = &mut cache.active_positions_cache;
     let account_cache = &cache.accounts_cache;
     let prices_cache = &cache.prices_cache;
+    let instruments_cache = &cache.instruments_cache;
+    let trading_groups_cache = &cache.trading_groups_cache;
+    let margin_reservations = &cache.margin_reservations;
+    let kept_pending_orders = &cache.kept_pending_orders;
+    let now = cache.clock.now();
+    let pending_on_closed_market = cache.settings.pending_on_closed_market;
+    let pending_margin_policy = cache.settings.pending_margin_policy;
+    let mut margin_events = vec![];
+    let mut kept_orders_to_clear = vec![];
+    let mut executed_orders = vec![];
 
     let removed_orders = pending_cache.query_and_select_remove(
         CrossMarginPositionsCacheQueryBuilder::new()
             .with_base(&bid_ask.base)
             .with_quote(&bid_ask.quote),
         |pending| {
-            if is_pending_ready_to_execute(pending, bid_ask) {
-                let is_ready_to_open = is_enough_balance_to_open_position_sync(
-                    account_cache,
-                    active_cache,
-                    prices_cache,
-                    pending.get_account_id(),
-                    pending.get_lots_size(),
-                    pending.get_lots_amount(),
-                    pending.get_base(),
-                    pending.get_instrument_id()
-                );
-
-                let Ok(is_ready_to_open) = is_ready_to_open else {
+            if !pending_on_closed_market
+                && !instruments_cache.is_market_open(pending.get_instrument_id(), now)
+            {
+                return None;
+            }
+
+            let account = account_cache.get_account(pending.get_account_id());
+            let account_bid_ask = match account {
+                Some(account) => {
+                    match get_account_bid_ask(
+                        trading_groups_cache,
+                        instruments_cache,
+                        account,
+                        bid_ask,
+                    ) {
+                        Ok(account_bid_ask) => account_bid_ask,
+                        Err(_) => return Some(CrossMarginPendingPositionExecuteReason::Rejected),
+                    }
+                }
+                None => bid_ask.clone(),
+            };
+
+            if let Some(trigger_bid_ask) =
+                get_pending_trigger_bid_ask(pending, coalesced, &account_bid_ask)
+            {
+                let Some(account) = account else {
+                    return Some(CrossMarginPendingPositionExecuteReason::Rejected);
+                };
+
+                match account.get_trading_state() {
+                    CrossMarginAccountTradingState::Active => {}
+                    CrossMarginAccountTradingState::CloseOnly => {
+                        return Some(CrossMarginPendingPositionExecuteReason::CloseOnly)
+                    }
+                    CrossMarginAccountTradingState::Disabled => {
+                        return Some(CrossMarginPendingPositionExecuteReason::TradingDisabled)
+                    }
+                    CrossMarginAccountTradingState::Frozen => return None,
+                }
+
+                // the order own reservation is converted into the position margin,
+                // orders executed earlier in the batch already consume their margin
+                let own_reservation = margin_reservations
+                    .get_reservation(pending.get_id())
+                    .map(|x| x.margin)
+                    .unwrap_or(0.0);
+                let reserved_margin = margin_reservations
+                    .get_reserved_margin(pending.get_account_id())
+                    - own_reservation
+                    + committed_margin
+                        .get(pending.get_account_id())
+                        .copied()
+                        .unwrap_or(0.0);
+
+                let get_cost = |lots_amount: f64| {
+                    return calculate_open_position_cost(
+                        account_cache,
+                        active_cache,
+                        prices_cache,
+                        instruments_cache,
+                        trading_groups_cache,
+                        pending.get_account_id(),
+                        pending.get_lots_size(),
+                        lots_amount,
+                        pending.get_base(),
+                        pending.get_instrument_id(),
+                        reserved_margin,
+                    );
+                };
+
+                let Ok(cost) = get_cost(pending.get_lots_amount()) else {
                     return Some(CrossMarginPendingPositionExecuteReason::Rejected);
                 };
 
-                if is_ready_to_open {
-                    return Some(CrossMarginPendingPositionExecuteReason::Executed);
+                if cost.is_enough() {
+                    *committed_margin
+                        .entry(pending.get_account_id().to_string())
+                        .or_insert(0.0) += cost.get_total() - own_reservation;
+                    executed_orders.push((pending.clone(), trigger_bid_ask));
+                    return None;
                 }
+
+                let filled_lots = match (
+                    pending_margin_policy,
+                    instruments_cache.get_spec(pending.get_instrument_id()),
+                ) {
+                    // without a spec there is no lot step to fill with
+                    (CrossMarginPendingMarginPolicy::PartialFill, Some(spec)) => {
+                        match find_affordable_lots(&get_cost, spec, pending.get_lots_amount()) {
+                            Some((filled_lots, filled_cost)) => {
+                                let mut order = pending.clone();
+                                match order.update_lots_amount(filled_lots) {
+                                    true => {
+                                        *committed_margin
+                                            .entry(pending.get_account_id().to_string())
+                                            .or_insert(0.0) +=
+                                            filled_cost.get_total() - own_reservation;
+                                        executed_orders.push((order, trigger_bid_ask.clone()));
+                                        filled_lots
+                                    }
+                                    false => 0.0,
+                                }
+                            }
+                            None => 0.0,
+                        }
+                    }
+                    _ => 0.0,
+                };
+
+                let outcome = match pending_margin_policy {
+                    CrossMarginPendingMarginPolicy::KeepAndRetry => {
+                        CrossMarginPendingTriggerOutcome::Kept
+                    }
+                    _ if filled_lots > 0.0 => CrossMarginPendingTriggerOutcome::PartiallyFilled,
+                    _ => CrossMarginPendingTriggerOutcome::Rejected,
+                };
+
+                margin_events.push(CrossMarginPendingTriggerEvent {
+                    order_id: pending.get_id().to_string(),
+                    account_id: pending.get_account_id().to_string(),
+                    instrument_id: pending.get_instrument_id().to_string(),
+                    trigger_price: trigger_bid_ask.get_open_price(pending.get_side()),
+                    requested_lots: pending.get_lots_amount(),
+                    filled_lots,
+                    outcome: outcome.clone(),
+                });
+
+                return match outcome {
+                    CrossMarginPendingTriggerOutcome::Rejected => {
+                        Some(CrossMarginPendingPositionExecuteReason::NotEnoughMargin)
+                    }
+                    _ => None,
+                };
             };
+
+            // the order left the kept state, it is reported again when kept next time
+            if kept_pending_orders.contains(pending.get_id()) {
+                kept_orders_to_clear.push(pending.get_id().to_string());
+            }
+
             return None;
         },
     );
 
     let mut result = ExecutePendingOrdersResult {
         failed_orders: vec![],
-        executed_orders: vec![],
+        executed_orders,
+        margin_events,
     };
 
-    for (order, reason) in removed_orders {
-        match reason {
-            CrossMarginPendingPositionExecuteReason::Executed => result.executed_orders.push(order),
-            _ => result.failed_orders.push((order, reason)),
+    // kept orders are reported once, until they leave the kept state
+    result.margin_events.retain(|x| match x.outcome {
+        CrossMarginPendingTriggerOutcome::Kept => {
+            cache.kept_pending_orders.insert(x.order_id.clone())
         }
+        _ => true,
+    });
+
+    for order_id in kept_orders_to_clear {
+        cache.kept_pending_orders.remove(&order_id);
+    }
+
+    for (order, reason) in removed_orders {
+        cache.margin_reservations.release(order.get_id());
+        cache.kept_pending_orders.remove(order.get_id());
+        result.failed_orders.push((order, reason));
     }
 
     return result;
 }
+
+// Largest lots amount passing the margin check, rounded down to the lot step. Margin and
+// commission are solved as linear in the lots amount, leverage tiers and commission limits
+// make the cost non linear, so the estimate is checked and solved again from it.
+fn find_affordable_lots(
+    get_cost: impl Fn(f64) -> Result<CrossMarginOpenPositionCost, CrossMarginError>,
+    spec: &CrossMarginInstrumentSpec,
+    lots_amount: f64,
+) -> Option<(f64, CrossMarginOpenPositionCost)> {
+    let fixed_cost = get_cost(0.0).ok()?.get_total();
+    let mut lots_amount = lots_amount;
+
+    for _ in 0..AFFORDABLE_LOTS_ATTEMPTS {
+        let cost = get_cost(lots_amount).ok()?;
+        let lot_cost = (cost.get_total() - fixed_cost) / lots_amount;
+
+        if lot_cost <= 0.0 {
+            return None;
+        }
+
+        let affordable = ((cost.free_margin - fixed_cost) / lot_cost).min(lots_amount);
+        let affordable = spec.round_lots_down(affordable);
+
+        if affordable < spec.min_lots || affordable <= 0.0 {
+            return None;
+        }
+
+        let cost = get_cost(affordable).ok()?;
+
+        if cost.is_enough() {
+            return Some((affordable, cost));
+        }
+
+        lots_amount = affordable;
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        instruments::CrossMarginInstrumentSpec,
+        test_utils::{
+            bid_ask, create_caches, default_trading_groups, eurusd, TestAccount,
+            TestActivePosition, TestCaches, TestPendingPosition,
+        },
+        CrossMarginAccountTradingState, CrossMarginCacheHandleBidAskResult,
+        CrossMarginCacheInstrument, CrossMarginCachesBuilder, CrossMarginPendingMarginPolicy,
+        CrossMarginPendingPositionExecuteReason, CrossMarginPendingPositionType,
+        CrossMarginPendingTriggerOutcome,
+    };
+
+    async fn trigger_buy_limit(
+        trading_state: CrossMarginAccountTradingState,
+    ) -> (usize, Vec<CrossMarginPendingPositionExecuteReason>, usize) {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+
+        caches
+            .add_pending_position(
+                TestPendingPosition::new(
+                    "order",
+                    "acc",
+                    &instrument,
+                    CrossMarginPendingPositionType::BuyLimit,
+                    100.0,
+                    1.05,
+                ),
+                "place",
+            )
+            .await
+            .unwrap();
+
+        caches
+            .accounts_cache
+            .update_trading_state("acc", trading_state, "state", None)
+            .await
+            .unwrap();
+
+        let result = caches
+            .handle_bid_ask(bid_ask(&instrument, 1.0, 1.0002), "tick")
+            .await;
+
+        return (
+            result.executed_orders.len(),
+            result.failed_orders.into_iter().map(|(_, x)| x).collect(),
+            caches.pending_positions_cache.positions.len(),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_active_account_executes_pending() {
+        let (executed, failed, left) =
+            trigger_buy_limit(CrossMarginAccountTradingState::Active).await;
+
+        assert_eq!(executed, 1);
+        assert!(failed.is_empty());
+        assert_eq!(left, 0);
+    }
+
+    #[tokio::test]
+    async fn test_close_only_account_rejects_pending() {
+        let (executed, failed, left) =
+            trigger_buy_limit(CrossMarginAccountTradingState::CloseOnly).await;
+
+        assert_eq!(executed, 0);
+        assert!(matches!(
+            failed.as_slice(),
+            [CrossMarginPendingPositionExecuteReason::CloseOnly]
+        ));
+        assert_eq!(left, 0);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_account_rejects_pending() {
+        let (executed, failed, left) =
+            trigger_buy_limit(CrossMarginAccountTradingState::Disabled).await;
+
+        assert_eq!(executed, 0);
+        assert!(matches!(
+            failed.as_slice(),
+            [CrossMarginPendingPositionExecuteReason::TradingDisabled]
+        ));
+        assert_eq!(left, 0);
+    }
+
+    #[tokio::test]
+    async fn test_frozen_account_keeps_pending() {
+        let (executed, failed, left) =
+            trigger_buy_limit(CrossMarginAccountTradingState::Frozen).await;
+
+        assert_eq!(executed, 0);
+        assert!(failed.is_empty());
+        assert_eq!(left, 1);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_account_can_not_open() {
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches
+            .accounts_cache
+            .update_trading_disabled("acc", true, "state", None)
+            .await
+            .unwrap();
+
+        let result = caches
+            .is_enough_balance_to_open_position("acc", 1.0, 10.0, "EUR", "EURUSD")
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(crate::CrossMarginError::TradingNotAllowed(
+                CrossMarginAccountTradingState::Disabled
+            ))
+        ));
+    }
+
+    async fn trigger_underfunded(
+        policy: CrossMarginPendingMarginPolicy,
+    ) -> CrossMarginCacheHandleBidAskResult<TestActivePosition, TestPendingPosition> {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.pending_margin_policy = policy;
+        caches
+            .instruments_cache
+            .update_spec(CrossMarginInstrumentSpec {
+                id: instrument.id.clone(),
+                contract_size: 1.0,
+                min_lots: 1000.0,
+                max_lots: 1_000_000.0,
+                lot_step: 1000.0,
+                digits: 5,
+                max_leverage: None,
+                stops_level: 0,
+            });
+
+        caches
+            .add_pending_position(
+                TestPendingPosition::new(
+                    "order",
+                    "acc",
+                    &instrument,
+                    CrossMarginPendingPositionType::BuyLimit,
+                    200_000.0,
+                    1.05,
+                ),
+                "place",
+            )
+            .await
+            .unwrap();
+
+        return caches
+            .handle_bid_ask(bid_ask(&instrument, 1.0, 1.0002), "tick")
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_underfunded_pending_is_kept_and_reported() {
+        let result = trigger_underfunded(CrossMarginPendingMarginPolicy::KeepAndRetry).await;
+
+        assert!(result.executed_orders.is_empty());
+        assert!(result.failed_orders.is_empty());
+        assert!(matches!(
+            result.pending_margin_events[0].outcome,
+            CrossMarginPendingTriggerOutcome::Kept
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_underfunded_pending_is_rejected() {
+        let result = trigger_underfunded(CrossMarginPendingMarginPolicy::Reject).await;
+
+        assert!(result.executed_orders.is_empty());
+        assert!(matches!(
+            result.failed_orders.as_slice(),
+            [(_, CrossMarginPendingPositionExecuteReason::NotEnoughMargin)]
+        ));
+        assert!(matches!(
+            result.pending_margin_events[0].outcome,
+            CrossMarginPendingTriggerOutcome::Rejected
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_underfunded_pending_is_partially_filled() {
+        let result = trigger_underfunded(CrossMarginPendingMarginPolicy::PartialFill).await;
+
+        // 1000 free margin at 1.0002 and leverage 100 affords 99_980 lots, rounded to the lot step
+        assert_eq!(result.executed_orders[0].lots_amount, 99_000.0);
+        assert_eq!(result.pending_margin_events[0].filled_lots, 99_000.0);
+        assert_eq!(result.pending_margin_events[0].requested_lots, 200_000.0);
+    }
+
+    #[tokio::test]
+    async fn test_kept_pending_is_reported_once_per_state_change() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+
+        caches
+            .add_pending_position(
+                TestPendingPosition::new(
+                    "order",
+                    "acc",
+                    &instrument,
+                    CrossMarginPendingPositionType::BuyLimit,
+                    200_000.0,
+                    1.05,
+                ),
+                "place",
+            )
+            .await
+            .unwrap();
+
+        let mut events = vec![];
+        for ask in [1.0002, 1.0003, 1.1002, 1.0002] {
+            let result = caches
+                .handle_bid_ask(bid_ask(&instrument, ask - 0.0002, ask), "tick")
+                .await;
+            events.push(result.pending_margin_events.len());
+        }
+
+        // kept, still kept, price left the trigger, kept again
+        assert_eq!(events, vec![1, 0, 0, 1]);
+        assert_eq!(caches.pending_positions_cache.positions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_orders_triggered_in_same_tick_share_free_margin() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.pending_margin_policy = CrossMarginPendingMarginPolicy::Reject;
+
+        // each order needs about 600 margin, both together don't fit
+        for id in ["first", "second"] {
+            caches
+                .add_pending_position(
+                    TestPendingPosition::new(
+                        id,
+                        "acc",
+                        &instrument,
+                        CrossMarginPendingPositionType::BuyLimit,
+                        60_000.0,
+                        1.05,
+                    ),
+                    "place",
+                )
+                .await
+                .unwrap();
+        }
+
+        let result = caches
+            .handle_bid_ask(bid_ask(&instrument, 1.0, 1.0002), "tick")
+            .await;
+
+        assert_eq!(result.executed_orders.len(), 1);
+        assert!(matches!(
+            result.failed_orders.as_slice(),
+            [(_, CrossMarginPendingPositionExecuteReason::NotEnoughMargin)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_orders_triggered_in_same_batch_share_free_margin() {
+        let eurusd = eurusd();
+        let gbpusd = CrossMarginCacheInstrument {
+            id: "GBPUSD".to_string(),
+            base: "GBP".to_string(),
+            quote: "USD".to_string(),
+        };
+        let mut caches: TestCaches = CrossMarginCachesBuilder::new(
+            vec![TestAccount::new("acc", 1000.0, 100.0)],
+            vec![],
+            vec![],
+            vec![eurusd.clone(), gbpusd.clone()],
+            vec!["USD".to_string()],
+            vec![bid_ask(&eurusd, 1.1, 1.1002), bid_ask(&gbpusd, 1.1, 1.1002)],
+        )
+        .with_trading_groups_cache(default_trading_groups())
+        .build()
+        .await
+        .unwrap();
+        caches.settings.pending_margin_policy = CrossMarginPendingMarginPolicy::Reject;
+
+        // each order needs about 600 margin, both together don't fit
+        for instrument in [&eurusd, &gbpusd] {
+            caches
+                .add_pending_position(
+                    TestPendingPosition::new(
+                        &instrument.id,
+                        "acc",
+                        instrument,
+                        CrossMarginPendingPositionType::BuyLimit,
+                        60_000.0,
+                        1.05,
+                    ),
+                    "place",
+                )
+                .await
+                .unwrap();
+        }
+
+        let result = caches
+            .handle_bid_ask_batch(
+                vec![bid_ask(&eurusd, 1.0, 1.0002), bid_ask(&gbpusd, 1.0, 1.0002)],
+                "batch",
+            )
+            .await;
+
+        assert_eq!(result.executed_orders.len(), 1);
+        assert!(matches!(
+            result.failed_orders.as_slice(),
+            [(_, CrossMarginPendingPositionExecuteReason::NotEnoughMargin)]
+        ));
+    }
+}
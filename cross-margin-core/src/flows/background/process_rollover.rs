@@ -0,0 +1,326 @@
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{
+    cache_aggregate::{CrossMarginCaches, DEFAULT_ROLLOVER_MINUTE},
+    flows::{calculate_position_swap, process_positions_update},
+    positions::{
+        CrossMarginActivePosition, CrossMarginPendingPosition, CrossMarginPositionSettlement,
+    },
+    CrossMarginAccount, CrossMarginCloseReason,
+};
+
+use super::UpdatePositionsDto;
+
+const DAY_MICROSECONDS: i64 = 24 * 60 * 60 * 1_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginSwapAccrual {
+    pub trader_id: String,
+    pub account_id: String,
+    pub position_id: String,
+    pub amount: f64,
+    pub rollovers_count: usize,
+}
+
+pub struct ProcessRolloverResult<AP: CrossMarginActivePosition> {
+    pub accruals: Vec<CrossMarginSwapAccrual>,
+    pub closed_positions: Vec<(AP, CrossMarginCloseReason, CrossMarginPositionSettlement)>,
+}
+
+// First call only remembers the latest rollover moment, so restarted engine without restored
+// `last_rollover` doesn't charge swaps twice.
+pub async fn process_rollover<
+    T: CrossMarginAccount,
+    F: CrossMarginActivePosition,
+    W: CrossMarginPendingPosition,
+>(
+    cache: &mut CrossMarginCaches<T, F, W>,
+    process_id: &str,
+) -> ProcessRolloverResult<F> {
+    let mut result = ProcessRolloverResult {
+        accruals: vec![],
+        closed_positions: vec![],
+    };
+
+    let now = cache.clock.now();
+    let latest_rollover = get_latest_rollover(
+        now,
+        cache
+            .settings
+            .rollover_minute
+            .unwrap_or(DEFAULT_ROLLOVER_MINUTE),
+    );
+
+    let Some(last_rollover) = cache.last_rollover else {
+        cache.last_rollover = Some(latest_rollover);
+        return result;
+    };
+
+    let mut rollovers = vec![];
+    let mut rollover = latest_rollover;
+
+    while rollover.unix_microseconds > last_rollover.unix_microseconds {
+        rollovers.push(rollover);
+        rollover = DateTimeAsMicroseconds::new(rollover.unix_microseconds - DAY_MICROSECONDS);
+    }
+
+    if rollovers.is_empty() {
+        return result;
+    }
+
+    cache.last_rollover = Some(latest_rollover);
+
+    let rollover_days: Vec<(i64, u32)> = rollovers
+        .iter()
+        .map(|x| {
+            (
+                x.unix_microseconds,
+                x.to_chrono_utc().weekday().num_days_from_monday(),
+            )
+        })
+        .collect();
+    let instruments_cache = &cache.instruments_cache;
+
+    result.accruals = cache
+        .active_positions_cache
+        .update_all_positions(|position| {
+            let swap = instruments_cache.get_swap(position.get_instrument_id())?;
+            let spec = instruments_cache.get_spec(position.get_instrument_id());
+
+            // catch up rollovers before the position was opened are not charged
+            let open_date = position.get_open_date().unix_microseconds;
+            let days_of_week: Vec<u32> = rollover_days
+                .iter()
+                .filter(|(rollover, _)| *rollover > open_date)
+                .map(|(_, day_of_week)| *day_of_week)
+                .collect();
+
+            let amount = days_of_week
+                .iter()
+                .map(|day_of_week| calculate_position_swap(position, swap, spec, *day_of_week))
+                .sum::<f64>();
+
+            if amount == 0.0 {
+                return None;
+            }
+
+            position.add_swap(amount);
+            position.set_version(position.get_version() + 1);
+
+            return Some(CrossMarginSwapAccrual {
+                trader_id: position.get_trader_id().to_string(),
+                account_id: position.get_account_id().to_string(),
+                position_id: position.get_id().to_string(),
+                amount,
+                rollovers_count: days_of_week.len(),
+            });
+        });
+
+    // swaps change equity, cached summaries are stale before the stop out check
+    for accrual in result.accruals.iter() {
+        cache
+            .accounts_margin_cache
+            .invalidate_account(&accrual.account_id);
+    }
+
+    let updated_positions = result
+        .accruals
+        .iter()
+        .map(|x| UpdatePositionsDto {
+            trader_id: x.trader_id.clone(),
+            account_id: x.account_id.clone(),
+            position_id: x.position_id.clone(),
+            close_position_reason: None,
+        })
+        .collect();
+
+    result.closed_positions = process_positions_update(cache, updated_positions, process_id).await;
+
+    return result;
+}
+
+fn get_latest_rollover(
+    now: DateTimeAsMicroseconds,
+    rollover_minute: u32,
+) -> DateTimeAsMicroseconds {
+    let day_start = now.unix_microseconds - now.unix_microseconds.rem_euclid(DAY_MICROSECONDS);
+    let rollover = day_start + rollover_minute as i64 * 60 * 1_000_000;
+
+    if rollover > now.unix_microseconds {
+        return DateTimeAsMicroseconds::new(rollover - DAY_MICROSECONDS);
+    }
+
+    return DateTimeAsMicroseconds::new(rollover);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{
+        test_utils::{create_caches, eurusd, TestAccount, TestActivePosition},
+        CrossMarginCloseReason, CrossMarginInstrumentSwap, CrossMarginManualClock,
+        CrossMarginPositionSide, CrossMarginSwapType,
+    };
+
+    // 2024-01-03, Wednesday
+    const WEDNESDAY: i64 = 1_704_240_000;
+    const HOUR: i64 = 3600;
+
+    fn date(seconds: i64) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(seconds * 1_000_000)
+    }
+
+    #[tokio::test]
+    async fn test_rollover_accrues_and_realizes_swaps() {
+        let instrument = eurusd();
+        let clock = Arc::new(CrossMarginManualClock::new(date(WEDNESDAY + 20 * HOUR)));
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.clock = clock.clone();
+        caches
+            .instruments_cache
+            .update_swap(CrossMarginInstrumentSwap {
+                instrument_id: instrument.id.clone(),
+                swap_type: CrossMarginSwapType::Percentage,
+                long: -3.6,
+                short: 1.0,
+                triple_swap_day: Some(2),
+                charge_weekends: false,
+            });
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "position",
+                    "acc",
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    1000.0,
+                    1.0,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+
+        assert!(caches
+            .process_rollover("rollover")
+            .await
+            .accruals
+            .is_empty());
+
+        clock.set(date(WEDNESDAY + 21 * HOUR + HOUR / 2));
+        let result = caches.process_rollover("rollover").await;
+        assert_eq!(result.accruals.len(), 1);
+        assert!((result.accruals[0].amount + 0.3).abs() < 1e-9);
+
+        assert!(caches
+            .process_rollover("rollover")
+            .await
+            .accruals
+            .is_empty());
+
+        clock.set(date(WEDNESDAY + 24 * HOUR + 22 * HOUR));
+        let result = caches.process_rollover("rollover").await;
+        assert!((result.accruals[0].amount + 0.1).abs() < 1e-9);
+
+        let (position, account, settlement) = caches
+            .remove_active_position("position", "close", None)
+            .await
+            .unwrap();
+
+        assert!((position.swaps + 0.4).abs() < 1e-9);
+        assert!((settlement.swaps + 0.4).abs() < 1e-9);
+        assert!((account.balance - 1000.0 - settlement.get_total()).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_rollovers_before_open_are_not_charged() {
+        let instrument = eurusd();
+        let clock = Arc::new(CrossMarginManualClock::new(date(WEDNESDAY + 20 * HOUR)));
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.clock = clock.clone();
+        caches
+            .instruments_cache
+            .update_swap(CrossMarginInstrumentSwap {
+                instrument_id: instrument.id.clone(),
+                swap_type: CrossMarginSwapType::Percentage,
+                long: -3.6,
+                short: 1.0,
+                triple_swap_day: Some(2),
+                charge_weekends: false,
+            });
+        caches.process_rollover("rollover").await;
+
+        for (id, open_date) in [("old", date(0)), ("new", date(WEDNESDAY + 36 * HOUR))] {
+            let mut position = TestActivePosition::new(
+                id,
+                "acc",
+                &instrument,
+                CrossMarginPositionSide::Buy,
+                1000.0,
+                1.0,
+            );
+            position.open_date = open_date;
+            caches.add_active_position(position, "open").await.unwrap();
+        }
+
+        // Wednesday and Thursday rollovers are caught up, the new position exists since Thursday
+        clock.set(date(WEDNESDAY + 24 * HOUR + 22 * HOUR));
+        let mut accruals = caches.process_rollover("rollover").await.accruals;
+        accruals.sort_by(|x, y| x.position_id.cmp(&y.position_id));
+
+        assert_eq!(accruals[0].position_id, "new");
+        assert_eq!(accruals[0].rollovers_count, 1);
+        assert!((accruals[0].amount + 0.1).abs() < 1e-9);
+        assert_eq!(accruals[1].position_id, "old");
+        assert_eq!(accruals[1].rollovers_count, 2);
+        assert!((accruals[1].amount + 0.4).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_rollover_swap_stops_out_account() {
+        let instrument = eurusd();
+        let clock = Arc::new(CrossMarginManualClock::new(date(WEDNESDAY + 20 * HOUR)));
+        let mut caches = create_caches(vec![TestAccount::new("acc", 10.0, 100.0)]).await;
+        caches.clock = clock.clone();
+        caches
+            .instruments_cache
+            .update_swap(CrossMarginInstrumentSwap {
+                instrument_id: instrument.id.clone(),
+                swap_type: CrossMarginSwapType::Percentage,
+                long: -360.0,
+                short: 1.0,
+                triple_swap_day: Some(2),
+                charge_weekends: false,
+            });
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "position",
+                    "acc",
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    1000.0,
+                    1.1,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+        caches.process_rollover("rollover").await;
+        assert!(caches.get_account_summary("acc").unwrap().margin_level > 50.0);
+
+        clock.set(date(WEDNESDAY + 24 * HOUR + 22 * HOUR));
+        let result = caches.process_rollover("rollover").await;
+
+        assert_eq!(result.closed_positions.len(), 1);
+        assert!(matches!(
+            result.closed_positions[0].1,
+            CrossMarginCloseReason::StopOut
+        ));
+    }
+}
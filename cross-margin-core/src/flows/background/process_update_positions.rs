@@ -2,9 +2,8 @@ use std::collections::HashSet;
 
 use crate::{
     cache_aggregate::CrossMarginCaches,
-    flows::is_account_stop_out_hit,
     positions::{
-        CrossMarginActivePosition, CrossMarginPendingPosition,
+        CrossMarginActivePosition, CrossMarginPendingPosition, CrossMarginPositionSettlement,
         CrossMarginPositionsCacheQueryBuilder,
     },
     CrossMarginAccount, CrossMarginCloseReason,
@@ -20,7 +19,7 @@ pub async fn process_positions_update<
     cache: &mut CrossMarginCaches<T, F, W>,
     updated_positions: Vec<UpdatePositionsDto>,
     process_id: &str,
-) -> Vec<(F, CrossMarginCloseReason)> {
+) -> Vec<(F, CrossMarginCloseReason, CrossMarginPositionSettlement)> {
     let mut positions_to_close = vec![];
     let mut updated_accounts = HashSet::new();
 
@@ -35,28 +34,63 @@ pub async fn process_positions_update<
         .remove_active_positions(positions_to_close.as_slice(), process_id)
         .await;
 
-    let so_accounts_with_max_loss = updated_accounts.iter().filter_map(|x| {
-        let account = cache.accounts_cache.get_account(x)?;
+    let now = cache.clock.now();
+    let stop_out_on_closed_market = cache.settings.stop_out_on_closed_market;
 
-        let account_positions = cache
-            .active_positions_cache
-            .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(&x));
-
-        if is_account_stop_out_hit(account, &account_positions) {
-            if let Some(max_loss_position) = account_positions
-                .iter()
-                .min_by(|x, y| x.get_pl().partial_cmp(&y.get_pl()).unwrap())
-            {
-                return Some((account, max_loss_position.to_owned()));
-            }
+    let mut so_positions_to_close: Vec<(String, CrossMarginCloseReason)> = vec![];
+
+    for account_id in updated_accounts.iter() {
+        let Ok(summary) = cache.get_account_summary(account_id) else {
+            continue;
+        };
+
+        let Some(account) = cache.accounts_cache.get_account(account_id) else {
+            continue;
+        };
+
+        let (Ok(margin_call), Ok(stop_out)) = (
+            cache.trading_groups_cache.get_margin_call(account),
+            cache.trading_groups_cache.get_stop_out(account),
+        ) else {
+            continue;
         };
 
-        return None;
-    });
+        // margin call is reported once when the account enters it
+        let is_margin_call = summary.margin_level > 0.0 && summary.margin_level <= margin_call;
+
+        if !is_margin_call {
+            cache.margin_call_accounts.remove(account_id);
+        } else if cache.margin_call_accounts.insert(account_id.clone()) {
+            let account = cache.accounts_cache.get_account(account_id).unwrap();
+            cache.notify(|x| x.on_margin_call(account, summary.margin_level));
+        }
 
-    let so_positions_to_close: Vec<(String, CrossMarginCloseReason)> = so_accounts_with_max_loss
-        .map(|(_, ap)| (ap.get_id().to_string(), CrossMarginCloseReason::StopOut))
-        .collect();
+        if !summary.is_stop_out_hit(stop_out) {
+            continue;
+        }
+
+        let account_positions = cache
+            .active_positions_cache
+            .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id));
+
+        if let Some(max_loss_position) = account_positions
+            .iter()
+            .filter(|x| {
+                stop_out_on_closed_market
+                    || cache
+                        .instruments_cache
+                        .is_market_open(x.get_instrument_id(), now)
+            })
+            .min_by(|x, y| x.get_pl().partial_cmp(&y.get_pl()).unwrap())
+        {
+            let account = cache.accounts_cache.get_account(account_id).unwrap();
+            cache.notify(|x| x.on_stop_out(account, summary.margin_level));
+            so_positions_to_close.push((
+                max_loss_position.get_id().to_string(),
+                CrossMarginCloseReason::StopOut,
+            ));
+        }
+    }
 
     let so_removed_positions = cache
         .remove_active_positions(&so_positions_to_close, process_id)
@@ -66,3 +100,26 @@ pub async fn process_positions_update<
 
     return removed_positions;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{bid_ask, create_netted_caches, eurusd};
+
+    #[tokio::test]
+    async fn test_fully_netted_account_is_not_stopped_out() {
+        let instrument = eurusd();
+        let mut caches = create_netted_caches(10.0).await;
+
+        for bid in [1.1, 1.09, 1.12] {
+            let result = caches
+                .handle_bid_ask(bid_ask(&instrument, bid, bid + 0.0002), "tick")
+                .await;
+            assert!(result.closed_positions.is_empty());
+        }
+
+        let summary = caches.get_account_summary("acc").unwrap();
+        assert_eq!(summary.margin, 0.0);
+        assert_eq!(summary.margin_level, 0.0);
+        assert_eq!(caches.active_positions_cache.positions.len(), 2);
+    }
+}
@@ -1,30 +1,79 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{positions::CrossMarginActivePosition, CrossMarginAccount};
+use crate::{
+    instruments::CrossMarginInstrumentsCache, positions::CrossMarginActivePosition,
+    trading_groups::CrossMarginTradingGroupsCache, CrossMarginAccount, CrossMarginError,
+};
 
 use super::calculate_margin;
 
+// Margin below is treated as zero, such accounts have zero margin level.
+pub const MIN_ACCOUNT_MARGIN: f64 = 0.0001;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccountCalculationResult {
     pub margin: f64,
     pub equity: f64,
     pub free_margin: f64,
     pub margin_level: f64,
+    pub reserved_margin: f64,
+}
+
+impl AccountCalculationResult {
+    // Positions pl includes swaps.
+    pub fn new(balance: f64, margin: f64, positions_pl: f64, reserved_margin: f64) -> Self {
+        let equity = balance + positions_pl;
+
+        Self {
+            margin,
+            equity,
+            // pending orders reservation lowers free margin only, margin level is not affected
+            free_margin: equity - margin - reserved_margin,
+            margin_level: match margin < MIN_ACCOUNT_MARGIN {
+                true => 0.0,
+                false => equity / margin * 100.0,
+            },
+            reserved_margin,
+        }
+    }
+
+    // Accounts without margin, e.g. fully netted, can't be stopped out.
+    pub fn is_stop_out_hit(&self, stop_out: f64) -> bool {
+        return self.margin >= MIN_ACCOUNT_MARGIN && self.margin_level <= stop_out;
+    }
 }
 
 pub fn calculate_account_data(
     account: &impl CrossMarginAccount,
     positions: &Vec<&impl CrossMarginActivePosition>,
-) -> AccountCalculationResult {
-    let margin = calculate_margin(account, positions);
-    let equity = account.get_balance() + positions.iter().map(|x| x.get_pl()).sum::<f64>();
-    AccountCalculationResult {
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<AccountCalculationResult, CrossMarginError> {
+    return calculate_account_data_with_reservations(
+        account,
+        positions,
+        instruments_cache,
+        trading_groups_cache,
+        0.0,
+    );
+}
+
+pub fn calculate_account_data_with_reservations(
+    account: &impl CrossMarginAccount,
+    positions: &Vec<&impl CrossMarginActivePosition>,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+    reserved_margin: f64,
+) -> Result<AccountCalculationResult, CrossMarginError> {
+    let margin = calculate_margin(account, positions, instruments_cache, trading_groups_cache)?;
+
+    return Ok(AccountCalculationResult::new(
+        account.get_balance(),
         margin,
-        equity,
-        free_margin: equity - margin,
-        margin_level: match margin < 0.0001 {
-            true => 0.0,
-            false => equity / margin * 100.0,
-        },
-    }
+        positions
+            .iter()
+            .map(|x| x.get_pl() + x.get_swaps())
+            .sum::<f64>(),
+        reserved_margin,
+    ));
 }
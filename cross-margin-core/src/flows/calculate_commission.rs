@@ -0,0 +1,168 @@
+use crate::{
+    commissions::{
+        CrossMarginCommissionBreakdown, CrossMarginCommissionRule, CrossMarginCommissionSide,
+        CrossMarginCommissionType,
+    },
+    trading_groups::CrossMarginTradingGroupsCache,
+    CrossMarginAccount, CrossMarginBidAskCache, CrossMarginError, CrossMarginPositionSide,
+};
+
+pub fn calculate_account_commission(
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+    prices_cache: &CrossMarginBidAskCache,
+    account: &impl CrossMarginAccount,
+    instrument_id: &str,
+    quote: &str,
+    lots_size: f64,
+    lots_amount: f64,
+    price: f64,
+    side: CrossMarginCommissionSide,
+) -> Result<Option<CrossMarginCommissionBreakdown>, CrossMarginError> {
+    let Some(rule) = trading_groups_cache
+        .get_account_group(account)?
+        .get_commission_rule(instrument_id)
+    else {
+        return Ok(None);
+    };
+
+    let breakdown = calculate_commission(
+        rule,
+        side,
+        account.get_currency(),
+        quote,
+        lots_size,
+        lots_amount,
+        price,
+        prices_cache,
+    )?;
+
+    return Ok(Some(breakdown));
+}
+
+pub fn calculate_commission(
+    rule: &CrossMarginCommissionRule,
+    side: CrossMarginCommissionSide,
+    account_currency: &str,
+    quote: &str,
+    lots_size: f64,
+    lots_amount: f64,
+    price: f64,
+    prices_cache: &CrossMarginBidAskCache,
+) -> Result<CrossMarginCommissionBreakdown, CrossMarginError> {
+    let quote_rate = get_conversion_rate(prices_cache, quote, account_currency)?;
+    let fee_rate = get_conversion_rate(prices_cache, &rule.currency, account_currency)?;
+
+    let rate = rule.get_rate(side);
+    let notional = lots_size * lots_amount * price * quote_rate;
+
+    let calculated_fee = match rule.commission_type {
+        CrossMarginCommissionType::PerLot => rate * lots_amount * fee_rate,
+        CrossMarginCommissionType::NotionalPercentage => notional * rate / 100.0,
+    };
+    let min_fee = rule.min_fee * fee_rate;
+
+    return Ok(CrossMarginCommissionBreakdown {
+        side,
+        commission_type: rule.commission_type,
+        rate,
+        lots_amount,
+        notional,
+        calculated_fee,
+        min_fee,
+        charged_fee: calculated_fee.max(min_fee),
+    });
+}
+
+fn get_conversion_rate(
+    prices_cache: &CrossMarginBidAskCache,
+    from: &str,
+    to: &str,
+) -> Result<f64, CrossMarginError> {
+    let bid_ask = prices_cache
+        .get_price(from, to)
+        .ok_or(CrossMarginError::AssetNotFound(format!(
+            "{}-{} for commission NOT FOUND",
+            from, to
+        )))?;
+
+    return Ok(bid_ask.get_open_price(&CrossMarginPositionSide::Buy));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commissions::{CrossMarginCommissionRule, CrossMarginCommissionType},
+        test_utils::{create_caches, eurusd, TestAccount, TestActivePosition},
+        trading_groups::CrossMarginTradingGroup,
+        CrossMarginPositionSide,
+    };
+
+    fn per_lot_group(min_fee: f64) -> CrossMarginTradingGroup {
+        let mut group = CrossMarginTradingGroup::new("default");
+        group.commissions.push(CrossMarginCommissionRule {
+            instrument_id: None,
+            commission_type: CrossMarginCommissionType::PerLot,
+            open_rate: 3.0,
+            close_rate: 2.0,
+            min_fee,
+            currency: "USD".to_string(),
+        });
+
+        return group;
+    }
+
+    #[tokio::test]
+    async fn test_commission_charged_on_open_and_close() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.trading_groups_cache.update_group(per_lot_group(0.0));
+
+        let position = TestActivePosition::new(
+            "pos",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            2.0,
+            1.1002,
+        );
+        let open_commission = caches
+            .add_active_position(position, "open")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(open_commission.charged_fee, 6.0);
+        assert_eq!(
+            caches.accounts_cache.get_account("acc").unwrap().balance,
+            994.0
+        );
+
+        let (_, account, settlement) = caches
+            .remove_active_position("pos", "close", None)
+            .await
+            .unwrap();
+        assert_eq!(settlement.get_commission(), 4.0);
+        assert_eq!(account.balance, 990.0);
+    }
+
+    #[tokio::test]
+    async fn test_min_fee_counts_in_balance_check() {
+        let mut caches = create_caches(vec![TestAccount::new("acc", 10.0, 100.0)]).await;
+
+        // 0.05 lot at 1.1002 needs 0.00055 margin
+        let is_enough = caches
+            .is_enough_balance_to_open_position("acc", 1.0, 0.05, "EUR", "EURUSD")
+            .await
+            .unwrap();
+        assert!(is_enough);
+
+        caches
+            .trading_groups_cache
+            .update_group(per_lot_group(10.0));
+
+        let is_enough = caches
+            .is_enough_balance_to_open_position("acc", 1.0, 0.05, "EUR", "EURUSD")
+            .await
+            .unwrap();
+        assert!(!is_enough);
+    }
+}
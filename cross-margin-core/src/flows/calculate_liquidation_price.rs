@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    flows::{
+        calculate_account_data, update_position_rates, AccountCalculationResult, MIN_ACCOUNT_MARGIN,
+    },
+    instruments::CrossMarginInstrumentsCache,
+    positions::CrossMarginActivePosition,
+    trading_groups::CrossMarginTradingGroupsCache,
+    CrossMarginAccount, CrossMarginBidAsk, CrossMarginBidAskCache, CrossMarginError,
+};
+
+const LIQUIDATION_SEARCH_MIN_MULTIPLIER: f64 = 0.000001;
+const LIQUIDATION_SEARCH_MAX_MULTIPLIER: f64 = 1000.0;
+const LIQUIDATION_SEARCH_ITERATIONS: usize = 100;
+
+// Prices are instrument bids, spread of the current tick is kept for the ask side.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginLiquidationPrice {
+    pub account_id: String,
+    pub instrument_id: String,
+    pub stop_out: f64,
+    pub current_price: f64,
+    // None when no price of the instrument alone can bring the account to stop out.
+    pub price: Option<f64>,
+    // Filled only for accounts with positions in the requested instrument only.
+    pub positions: Vec<CrossMarginPositionLiquidationPrice>,
+}
+
+// Estimate as if the position was the only one on the account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginPositionLiquidationPrice {
+    pub position_id: String,
+    pub price: Option<f64>,
+}
+
+pub fn calculate_liquidation_price<A: CrossMarginAccount, AP: CrossMarginActivePosition>(
+    account: &A,
+    account_positions: &Vec<&AP>,
+    prices_cache: &CrossMarginBidAskCache,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+    instrument_id: &str,
+) -> Result<CrossMarginLiquidationPrice, CrossMarginError> {
+    let source = prices_cache
+        .get_by_id(instrument_id)
+        .ok_or(CrossMarginError::AssetNotFound(format!(
+            "{} NOT FOUND",
+            instrument_id
+        )))?;
+
+    let mut simulation = LiquidationSimulation {
+        account,
+        prices_cache: prices_cache.clone(),
+        instruments_cache,
+        trading_groups_cache,
+        source: source.as_ref().clone(),
+        stop_out: trading_groups_cache.get_stop_out(account)?,
+    };
+
+    let mut positions: Vec<AP> = account_positions.iter().map(|x| (*x).clone()).collect();
+    let price = simulation.solve(&mut positions)?;
+
+    let mut positions_prices = vec![];
+    let is_single_instrument = account_positions
+        .iter()
+        .all(|x| x.get_instrument_id() == instrument_id);
+
+    if is_single_instrument && account_positions.len() > 1 {
+        for position in account_positions {
+            let mut positions = vec![(*position).clone()];
+            positions_prices.push(CrossMarginPositionLiquidationPrice {
+                position_id: position.get_id().to_string(),
+                price: simulation.solve(&mut positions)?,
+            });
+        }
+    } else if is_single_instrument {
+        for position in account_positions {
+            positions_prices.push(CrossMarginPositionLiquidationPrice {
+                position_id: position.get_id().to_string(),
+                price,
+            });
+        }
+    }
+
+    return Ok(CrossMarginLiquidationPrice {
+        account_id: account.get_id().to_string(),
+        instrument_id: instrument_id.to_string(),
+        stop_out: simulation.stop_out,
+        current_price: source.bid,
+        price,
+        positions: positions_prices,
+    });
+}
+
+struct LiquidationSimulation<'a, A: CrossMarginAccount> {
+    account: &'a A,
+    prices_cache: CrossMarginBidAskCache,
+    instruments_cache: &'a CrossMarginInstrumentsCache,
+    trading_groups_cache: &'a CrossMarginTradingGroupsCache,
+    source: CrossMarginBidAsk,
+    stop_out: f64,
+}
+
+impl<'a, A: CrossMarginAccount> LiquidationSimulation<'a, A> {
+    // Nearest price on either side of the current one where margin level reaches stop out.
+    fn solve(
+        &mut self,
+        positions: &mut [impl CrossMarginActivePosition],
+    ) -> Result<Option<f64>, CrossMarginError> {
+        let current = self.source.bid;
+        let current_data = self.calculate_at_price(positions, current)?;
+
+        // Accounts without margin (e.g. fully netted) have no stop out to reach.
+        if current_data.margin < MIN_ACCOUNT_MARGIN {
+            return Ok(None);
+        }
+
+        if current_data.is_stop_out_hit(self.stop_out) {
+            return Ok(Some(current));
+        }
+
+        let mut result: Option<f64> = None;
+
+        for bound in [
+            current * LIQUIDATION_SEARCH_MIN_MULTIPLIER,
+            current * LIQUIDATION_SEARCH_MAX_MULTIPLIER,
+        ] {
+            if !self.is_stop_out_hit(positions, bound)? {
+                continue;
+            }
+
+            let mut safe = current;
+            let mut liquidated = bound;
+
+            for _ in 0..LIQUIDATION_SEARCH_ITERATIONS {
+                let middle = (safe + liquidated) / 2.0;
+
+                match self.is_stop_out_hit(positions, middle)? {
+                    true => liquidated = middle,
+                    false => safe = middle,
+                }
+            }
+
+            let is_closer = match result {
+                Some(price) => (liquidated - current).abs() < (price - current).abs(),
+                None => true,
+            };
+
+            if is_closer {
+                result = Some(liquidated);
+            }
+        }
+
+        return Ok(result);
+    }
+
+    fn is_stop_out_hit(
+        &mut self,
+        positions: &mut [impl CrossMarginActivePosition],
+        bid: f64,
+    ) -> Result<bool, CrossMarginError> {
+        return Ok(self
+            .calculate_at_price(positions, bid)?
+            .is_stop_out_hit(self.stop_out));
+    }
+
+    fn calculate_at_price(
+        &mut self,
+        positions: &mut [impl CrossMarginActivePosition],
+        bid: f64,
+    ) -> Result<AccountCalculationResult, CrossMarginError> {
+        let mut price = self.source.clone();
+        price.ask = bid + (self.source.ask - self.source.bid);
+        price.bid = bid;
+        self.prices_cache.handle_new_with_crosses(price);
+
+        let spread_markup = self
+            .trading_groups_cache
+            .get_account_group(self.account)?
+            .spread_markup
+            .as_ref();
+
+        for position in positions.iter_mut() {
+            update_position_rates(
+                position,
+                &self.prices_cache,
+                self.instruments_cache,
+                spread_markup,
+            )?;
+        }
+
+        return calculate_account_data(
+            self.account,
+            &positions.iter().collect(),
+            self.instruments_cache,
+            self.trading_groups_cache,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{
+            create_caches, create_netted_caches, eurusd, TestAccount, TestActivePosition,
+        },
+        CrossMarginPositionSide,
+    };
+
+    #[tokio::test]
+    async fn test_liquidation_price_of_single_position() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+
+        // margin 100_000 / 100 = 1000, stop out 50% is hit at equity 500, i.e. 0.005 move down
+        caches
+            .add_active_position(
+                TestActivePosition::new(
+                    "pos",
+                    "acc",
+                    &instrument,
+                    CrossMarginPositionSide::Buy,
+                    100_000.0,
+                    1.1002,
+                ),
+                "open",
+            )
+            .await
+            .unwrap();
+
+        let result = caches
+            .calculate_liquidation_price("acc", &instrument.id)
+            .unwrap();
+
+        let price = result.price.unwrap();
+        assert!((price - 1.0952).abs() < 1e-6);
+        assert_eq!(result.positions.len(), 1);
+        assert_eq!(result.positions[0].price, Some(price));
+        assert_eq!(
+            caches.prices_cache.get_by_id(&instrument.id).unwrap().bid,
+            1.1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fully_netted_account_has_no_liquidation_price() {
+        let instrument = eurusd();
+        let caches = create_netted_caches(10.0).await;
+
+        let result = caches
+            .calculate_liquidation_price("acc", &instrument.id)
+            .unwrap();
+
+        assert_eq!(result.price, None);
+        // each position alone carries margin and has its own level
+        assert_eq!(result.positions.len(), 2);
+        assert!(result.positions.iter().all(|x| x.price.is_some()));
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{
+    instruments::{
+        CrossMarginInstrumentSpec, CrossMarginInstrumentSwap, CrossMarginSwapType,
+        SWAP_DAYS_IN_YEAR,
+    },
+    positions::CrossMarginActivePosition,
+};
+
+// Swap for one rollover in position collateral. Without instrument spec a point equals one
+// quote unit.
+pub fn calculate_position_swap(
+    position: &impl CrossMarginActivePosition,
+    swap: &CrossMarginInstrumentSwap,
+    spec: Option<&CrossMarginInstrumentSpec>,
+    day_of_week: u32,
+) -> f64 {
+    let volume = position.get_lots_size() * position.get_lots_amount();
+    let rate = swap.get_rate(position.get_side());
+
+    let quote_amount = match swap.swap_type {
+        CrossMarginSwapType::Points => {
+            let point = spec.map(|x| x.get_tick_size()).unwrap_or(1.0);
+            rate * point * volume
+        }
+        CrossMarginSwapType::Percentage => {
+            volume * position.get_active_price() * rate / 100.0 / SWAP_DAYS_IN_YEAR
+        }
+    };
+
+    return quote_amount * position.get_profit_price() * swap.get_multiplier(day_of_week);
+}
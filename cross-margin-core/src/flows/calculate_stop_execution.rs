@@ -0,0 +1,144 @@
+use crate::{
+    positions::{CrossMarginActivePosition, CrossMarginStopExecution},
+    trading_groups::{CrossMarginGapFillPolicy, CrossMarginTradingGroup},
+    CrossMarginCloseReason, CrossMarginPositionSide,
+};
+
+// Only price levels are handled, SL/TP set in profit are filled at market.
+pub fn calculate_stop_execution(
+    position: &impl CrossMarginActivePosition,
+    close_reason: &CrossMarginCloseReason,
+    trading_group: &CrossMarginTradingGroup,
+) -> Option<CrossMarginStopExecution> {
+    let (requested_price, policy) = match close_reason {
+        CrossMarginCloseReason::Sl if position.get_sl_profit().is_none() => {
+            (position.get_sl_price()?, trading_group.sl_gap_policy)
+        }
+        CrossMarginCloseReason::Tp if position.get_tp_profit().is_none() => {
+            (position.get_tp_price()?, trading_group.tp_gap_policy)
+        }
+        _ => return None,
+    };
+
+    let market_price = position.get_active_price();
+    // price moving up is in favor of a buy position
+    let side_sign = match position.get_side() {
+        CrossMarginPositionSide::Buy => 1.0,
+        CrossMarginPositionSide::Sell => -1.0,
+    };
+
+    let execution_price = match policy {
+        CrossMarginGapFillPolicy::Market => market_price,
+        CrossMarginGapFillPolicy::Level => requested_price,
+        CrossMarginGapFillPolicy::BestPrice => {
+            match (market_price - requested_price) * side_sign >= 0.0 {
+                true => market_price,
+                false => requested_price,
+            }
+        }
+    };
+
+    let pl_adjustment = (execution_price - market_price)
+        * side_sign
+        * position.get_lots_size()
+        * position.get_lots_amount()
+        * position.get_profit_price();
+
+    return Some(CrossMarginStopExecution {
+        requested_price,
+        market_price,
+        execution_price,
+        slippage: (execution_price - requested_price) * side_sign,
+        pl_adjustment,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{bid_ask, create_caches, eurusd, TestAccount, TestActivePosition},
+        trading_groups::{CrossMarginGapFillPolicy, CrossMarginTradingGroup},
+        CrossMarginCloseReason, CrossMarginPositionSide,
+    };
+
+    use super::calculate_stop_execution;
+
+    #[test]
+    fn test_gap_fill_policies() {
+        let mut position = TestActivePosition::new(
+            "buy",
+            "acc",
+            &eurusd(),
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1,
+        );
+        position.sl_price = Some(1.05);
+        position.tp_price = Some(1.15);
+        let mut group = CrossMarginTradingGroup::new("default");
+
+        position.active_price = 1.0;
+        let execution =
+            calculate_stop_execution(&position, &CrossMarginCloseReason::Sl, &group).unwrap();
+        assert_eq!(execution.execution_price, 1.0);
+        assert!((execution.slippage + 0.05).abs() < 1e-9);
+        assert_eq!(execution.pl_adjustment, 0.0);
+
+        group.sl_gap_policy = CrossMarginGapFillPolicy::Level;
+        let execution =
+            calculate_stop_execution(&position, &CrossMarginCloseReason::Sl, &group).unwrap();
+        assert_eq!(execution.execution_price, 1.05);
+        assert!((execution.pl_adjustment - 5.0).abs() < 1e-9);
+
+        position.active_price = 1.2;
+        group.tp_gap_policy = CrossMarginGapFillPolicy::BestPrice;
+        let execution =
+            calculate_stop_execution(&position, &CrossMarginCloseReason::Tp, &group).unwrap();
+        assert_eq!(execution.execution_price, 1.2);
+        assert!((execution.slippage - 0.05).abs() < 1e-9);
+
+        group.tp_gap_policy = CrossMarginGapFillPolicy::Level;
+        let execution =
+            calculate_stop_execution(&position, &CrossMarginCloseReason::Tp, &group).unwrap();
+        assert_eq!(execution.execution_price, 1.15);
+        assert!((execution.pl_adjustment + 5.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_guaranteed_stop_settlement() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        let mut group = CrossMarginTradingGroup::new("default");
+        group.leverage = 100.0;
+        group.sl_gap_policy = CrossMarginGapFillPolicy::Level;
+        caches.trading_groups_cache.update_group(group);
+
+        let mut position = TestActivePosition::new(
+            "buy",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1002,
+        );
+        position.sl_price = Some(1.05);
+        caches.add_active_position(position, "open").await.unwrap();
+
+        let result = caches
+            .handle_bid_ask(bid_ask(&instrument, 1.0, 1.0002), "tick")
+            .await;
+
+        let (position, _, settlement) = &result.closed_positions[0];
+        let execution = settlement.stop_execution.as_ref().unwrap();
+        assert_eq!(execution.execution_price, 1.05);
+        assert!((settlement.pl - (1.05 - 1.1002) * 100.0).abs() < 1e-9);
+        assert_eq!(position.pl, settlement.pl);
+
+        let balance = caches.accounts_cache.get_account("acc").unwrap().balance;
+        assert!((balance - 1000.0 - settlement.pl).abs() < 1e-9);
+
+        let stats = caches.slippage_stats.get(&instrument.id).unwrap();
+        assert_eq!(stats.sl_count, 1);
+        assert_eq!(stats.slipped_count, 0);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    positions::CrossMarginActivePosition, CrossMarginCloseReason, CrossMarginPositionSide,
+    positions::CrossMarginActivePosition, CrossMarginCloseReason, CrossMarginCoalescedBidAsk,
+    CrossMarginPositionSide,
 };
 
 pub fn get_position_close_reason<T: CrossMarginActivePosition>(
@@ -17,31 +18,104 @@ pub fn get_position_close_reason<T: CrossMarginActivePosition>(
 }
 
 fn is_sl_triggered(position: &impl CrossMarginActivePosition) -> bool {
+    return is_sl_touched(position, position.get_active_price());
+}
+
+fn is_tp_triggered(position: &impl CrossMarginActivePosition) -> bool {
+    return is_tp_touched(position, position.get_active_price());
+}
+
+fn is_sl_touched(position: &impl CrossMarginActivePosition, close_price: f64) -> bool {
     if let Some(sl) = position.get_sl_profit() {
-        return position.get_pl() <= sl;
+        return get_pl_at_close_price(position, close_price) <= sl;
     }
 
     if let Some(sl) = position.get_sl_price() {
         return match &position.get_side() {
-            CrossMarginPositionSide::Buy => sl >= position.get_active_price(),
-            CrossMarginPositionSide::Sell => sl <= position.get_active_price(),
+            CrossMarginPositionSide::Buy => sl >= close_price,
+            CrossMarginPositionSide::Sell => sl <= close_price,
         };
     }
 
     return false;
 }
 
-fn is_tp_triggered(position: &impl CrossMarginActivePosition) -> bool {
+fn is_tp_touched(position: &impl CrossMarginActivePosition, close_price: f64) -> bool {
     if let Some(tp) = position.get_tp_profit() {
-        return position.get_pl() >= tp;
+        return get_pl_at_close_price(position, close_price) >= tp;
     }
 
     if let Some(tp) = position.get_tp_price() {
         return match position.get_side() {
-            CrossMarginPositionSide::Buy => tp <= position.get_active_price(),
-            CrossMarginPositionSide::Sell => tp >= position.get_active_price(),
+            CrossMarginPositionSide::Buy => tp <= close_price,
+            CrossMarginPositionSide::Sell => tp >= close_price,
         };
     }
 
     return false;
 }
+
+// Position pl rated at another close price with the current profit price.
+fn get_pl_at_close_price(position: &impl CrossMarginActivePosition, close_price: f64) -> f64 {
+    return position.get_pl()
+        + (close_price - position.get_active_price()) * get_pl_per_price_unit(position);
+}
+
+// Close price at which the position pl reaches the target, the inverse of the above.
+fn get_close_price_at_pl(position: &impl CrossMarginActivePosition, pl: f64) -> f64 {
+    return position.get_active_price()
+        + (pl - position.get_pl()) / get_pl_per_price_unit(position);
+}
+
+fn get_pl_per_price_unit(position: &impl CrossMarginActivePosition) -> f64 {
+    let side_sign = match position.get_side() {
+        CrossMarginPositionSide::Buy => 1.0,
+        CrossMarginPositionSide::Sell => -1.0,
+    };
+
+    return side_sign
+        * position.get_lots_size()
+        * position.get_lots_amount()
+        * position.get_profit_price();
+}
+
+// Stops touched by the skipped ticks of a batch with the price to fill them at. The order of the
+// skipped ticks is unknown, so the stop is filled at its level: the latest price didn't touch it,
+// so the level was crossed inside the batch. Sl is checked against the adverse extreme and tp
+// against the favorable one, sl wins when both were touched.
+pub fn get_position_range_close_reason<T: CrossMarginActivePosition>(
+    position: &T,
+    coalesced: &CrossMarginCoalescedBidAsk,
+) -> Option<(CrossMarginCloseReason, f64)> {
+    let side = position.get_side();
+    let (low, high) = coalesced.get_close_price_range(side);
+
+    // active price can include the spread markup
+    let shift = position.get_active_price() - coalesced.latest.get_close_price(side);
+    let (low, high) = (low + shift, high + shift);
+
+    let (adverse, favorable) = match side {
+        CrossMarginPositionSide::Buy => (low, high),
+        CrossMarginPositionSide::Sell => (high, low),
+    };
+
+    if is_sl_touched(position, adverse) {
+        let level = match position.get_sl_profit() {
+            Some(sl) => get_close_price_at_pl(position, sl),
+            None => position.get_sl_price()?,
+        };
+
+        return Some((CrossMarginCloseReason::Sl, level));
+    }
+
+    if is_tp_touched(position, favorable) {
+        let level = match position.get_tp_profit() {
+            Some(tp) => get_close_price_at_pl(position, tp),
+            None => position.get_tp_price()?,
+        };
+
+        return Some((CrossMarginCloseReason::Tp, level));
+    }
+
+    return None;
+}
@@ -1,11 +1,19 @@
-use crate::{positions::CrossMarginActivePosition, CrossMarginAccount};
+use crate::{
+    instruments::CrossMarginInstrumentsCache, positions::CrossMarginActivePosition,
+    trading_groups::CrossMarginTradingGroupsCache, CrossMarginAccount, CrossMarginError,
+};
 
 pub fn is_account_stop_out_hit(
     account: &impl CrossMarginAccount,
     account_positions: &Vec<&impl CrossMarginActivePosition>,
-) -> bool {
-    let account_props = account
-        .calculate_account_margin_props(account_positions);
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<bool, CrossMarginError> {
+    let account_props = account.calculate_account_margin_props(
+        account_positions,
+        instruments_cache,
+        trading_groups_cache,
+    )?;
 
-    return account_props.margin_level <= account.get_stop_out();
+    return Ok(account_props.is_stop_out_hit(trading_groups_cache.get_stop_out(account)?));
 }
@@ -1,7 +1,15 @@
+use serde::{Deserialize, Serialize};
 use service_sdk::my_telemetry::MyTelemetryContext;
 
 use crate::{
+    commissions::CrossMarginCommissionSide,
+    flows::{
+        calculate_account_commission, get_account_bid_ask, get_instrument_notional,
+        validate_account_open_allowed,
+    },
+    instruments::CrossMarginInstrumentsCache,
     positions::{CrossMarginActivePosition, CrossMarginPositionsCacheQueryBuilder, PositionsCache},
+    trading_groups::CrossMarginTradingGroupsCache,
     AccountsCache, CrossMarginAccount, CrossMarginBidAskCache, CrossMarginError,
     CrossMarginPositionSide,
 };
@@ -13,36 +21,32 @@ pub async fn is_enough_balance_to_open_position<
     accounts_cache: &AccountsCache<A>,
     active_positions_cache: &PositionsCache<AP>,
     prices_cache: &CrossMarginBidAskCache,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
     account_id: &str,
     lots_size: f64,
     lots_amount: f64,
     base: &str,
-    instrument_id: &str
+    instrument_id: &str,
 ) -> Result<bool, CrossMarginError> {
+    let cost = calculate_open_position_cost(
+        accounts_cache,
+        active_positions_cache,
+        prices_cache,
+        instruments_cache,
+        trading_groups_cache,
+        account_id,
+        lots_size,
+        lots_amount,
+        base,
+        instrument_id,
+        0.0,
+    )?;
+
     let account = accounts_cache
         .get_account(account_id)
         .ok_or(CrossMarginError::AccountNotFound)?;
 
-    let account_positions = active_positions_cache
-        .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id));
-
-    let account_props = account.calculate_account_margin_props(&account_positions);
-    let margin_bid_ask = prices_cache.get_price(base, account.get_currency()).ok_or(
-        CrossMarginError::AssetNotFound(format!(
-            "{}-{} for account {} NOT FOUND",
-            base,
-            account.get_currency(),
-            account_id
-        )),
-    )?;
-
-    let instrument_leverage = account.get_instruments_leverages().get(instrument_id).map(|x| x.clone()).unwrap_or(account.get_leverage());
-
-    let target_leverage = instrument_leverage.min(account.get_leverage());
-
-    let new_position_margin = lots_size * lots_amount / target_leverage
-        * margin_bid_ask.get_open_price(&CrossMarginPositionSide::Buy);
-
     trade_log::trade_log!(
         account.get_trader_id(),
         account.get_id(),
@@ -51,54 +55,125 @@ pub async fn is_enough_balance_to_open_position<
         "Validation is enough balance to open position",
         MyTelemetryContext::new().clone(),
         "account" = &account,
-        "account_positions" = &account_positions,
-        "account_props" = &account_props,
-        "margin_bid_ask" = margin_bid_ask.as_ref(),
-        "new_position_margin" = &new_position_margin,
-        "target_leverage" = &target_leverage
+        "cost" = &cost
     );
 
-    return Ok(account_props.free_margin >= new_position_margin);
+    return Ok(cost.is_enough());
 }
 
+// Free margin left for a new position and what opening it would cost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginOpenPositionCost {
+    pub free_margin: f64,
+    pub margin: f64,
+    pub commission: f64,
+}
 
+impl CrossMarginOpenPositionCost {
+    pub fn get_total(&self) -> f64 {
+        return self.margin + self.commission;
+    }
 
-pub fn is_enough_balance_to_open_position_sync<
-    A: CrossMarginAccount,
-    AP: CrossMarginActivePosition,
->(
+    pub fn is_enough(&self) -> bool {
+        return self.free_margin >= self.get_total();
+    }
+}
+
+// Margin reserved by the account pending orders is not available for the new position.
+pub fn calculate_open_position_cost<A: CrossMarginAccount, AP: CrossMarginActivePosition>(
     accounts_cache: &AccountsCache<A>,
     active_positions_cache: &PositionsCache<AP>,
     prices_cache: &CrossMarginBidAskCache,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
     account_id: &str,
     lots_size: f64,
     lots_amount: f64,
     base: &str,
-    instrument_id: &str
-) -> Result<bool, CrossMarginError> {
+    instrument_id: &str,
+    reserved_margin: f64,
+) -> Result<CrossMarginOpenPositionCost, CrossMarginError> {
     let account = accounts_cache
         .get_account(account_id)
         .ok_or(CrossMarginError::AccountNotFound)?;
 
+    validate_account_open_allowed(account)?;
+
     let account_positions = active_positions_cache
         .query_positions(CrossMarginPositionsCacheQueryBuilder::new().with_account(account_id));
 
-    let account_props = account.calculate_account_margin_props(&account_positions);
+    let account_props = account.calculate_account_margin_props_with_reservations(
+        &account_positions,
+        instruments_cache,
+        trading_groups_cache,
+        reserved_margin,
+    )?;
     let margin_bid_ask = prices_cache.get_price(base, account.get_currency()).ok_or(
         CrossMarginError::AssetNotFound(format!(
             "{}-{} for account {} NOT FOUND",
             base,
             account.get_currency(),
-            account_id
+            account.get_id()
         )),
     )?;
 
-    let instrument_leverage = account.get_instruments_leverages().get(instrument_id).map(|x| x.clone()).unwrap_or(account.get_leverage());
-
-    let target_leverage = instrument_leverage.min(account.get_leverage());
+    let account_leverage = trading_groups_cache.get_leverage(account)?;
+    let instrument_leverage = trading_groups_cache
+        .get_instrument_leverage(account, instrument_id)?
+        .unwrap_or(account_leverage);
+
+    let target_leverage = instruments_cache
+        .get_max_leverage(instrument_id, instrument_leverage.min(account_leverage));
+
+    let new_position_notional =
+        lots_size * lots_amount * margin_bid_ask.get_open_price(&CrossMarginPositionSide::Buy);
+
+    let new_position_margin = match instruments_cache.get_leverage_tiers(instrument_id) {
+        Some(tiers) => {
+            let instrument_positions: Vec<_> = account_positions
+                .iter()
+                .filter(|x| x.get_instrument_id() == instrument_id)
+                .copied()
+                .collect();
+            let (buy_notional, sell_notional) = get_instrument_notional(&instrument_positions);
+            let exposure = tiers.get_exposure(buy_notional, sell_notional);
+
+            // side is unknown here, so the new position is treated as increasing the exposure
+            tiers.get_margin(exposure + new_position_notional, target_leverage)
+                - tiers.get_margin(exposure, target_leverage)
+        }
+        None => new_position_notional / target_leverage,
+    };
+
+    let instrument_price =
+        prices_cache
+            .get_by_id(instrument_id)
+            .ok_or(CrossMarginError::AssetNotFound(format!(
+                "{} NOT FOUND",
+                instrument_id
+            )))?;
+    let instrument_price = get_account_bid_ask(
+        trading_groups_cache,
+        instruments_cache,
+        account,
+        &instrument_price,
+    )?;
 
-    let new_position_margin = lots_size * lots_amount / target_leverage
-        * margin_bid_ask.get_open_price(&CrossMarginPositionSide::Buy);
+    let commission = calculate_account_commission(
+        trading_groups_cache,
+        prices_cache,
+        account,
+        instrument_id,
+        &instrument_price.quote,
+        lots_size,
+        lots_amount,
+        instrument_price.get_open_price(&CrossMarginPositionSide::Buy),
+        CrossMarginCommissionSide::Open,
+    )?;
 
-    return Ok(account_props.free_margin >= new_position_margin);
-}
\ No newline at end of file
+    return Ok(CrossMarginOpenPositionCost {
+        free_margin: account_props.free_margin,
+        margin: new_position_margin,
+        commission: commission.map(|x| x.charged_fee).unwrap_or(0.0),
+    });
+}
@@ -1,4 +1,4 @@
-use crate::{positions::CrossMarginPendingPosition, CrossMarginBidAsk};
+use crate::{positions::CrossMarginPendingPosition, CrossMarginBidAsk, CrossMarginCoalescedBidAsk};
 
 pub fn is_pending_ready_to_execute<T: CrossMarginPendingPosition>(
     position: &T,
@@ -23,3 +23,31 @@ pub fn is_pending_ready_to_execute<T: CrossMarginPendingPosition>(
         }
     }
 }
+
+// Account price triggering the order within a batch, the latest price is preferred, otherwise the
+// extreme of the skipped ticks touching the order price.
+pub fn get_pending_trigger_bid_ask<T: CrossMarginPendingPosition>(
+    position: &T,
+    coalesced: &CrossMarginCoalescedBidAsk,
+    account_bid_ask: &CrossMarginBidAsk,
+) -> Option<CrossMarginBidAsk> {
+    if is_pending_ready_to_execute(position, account_bid_ask) {
+        return Some(account_bid_ask.clone());
+    }
+
+    // spread markup shifts the extremes as it shifts the latest price
+    let bid_shift = account_bid_ask.bid - coalesced.latest.bid;
+    let ask_shift = account_bid_ask.ask - coalesced.latest.ask;
+
+    return [
+        (coalesced.min_bid, coalesced.min_ask),
+        (coalesced.max_bid, coalesced.max_ask),
+    ]
+    .into_iter()
+    .map(|(bid, ask)| CrossMarginBidAsk {
+        bid: bid + bid_shift,
+        ask: ask + ask_shift,
+        ..account_bid_ask.clone()
+    })
+    .find(|bid_ask| is_pending_ready_to_execute(position, bid_ask));
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use crate::{
+    accounts::CrossMarginInstrumentMarginState, flows::calculate_instrument_margin,
+    instruments::CrossMarginInstrumentsCache, positions::CrossMarginActivePosition,
+    trading_groups::CrossMarginTradingGroupsCache, CrossMarginAccount, CrossMarginError,
+};
+
+// Positions must belong to the instrument, None when there are no positions.
+pub fn calculate_instrument_margin_state(
+    account: &impl CrossMarginAccount,
+    instrument: &str,
+    positions: &Vec<&impl CrossMarginActivePosition>,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<Option<CrossMarginInstrumentMarginState>, CrossMarginError> {
+    if positions.is_empty() {
+        return Ok(None);
+    }
+
+    return Ok(Some(CrossMarginInstrumentMarginState {
+        margin: calculate_instrument_margin(
+            account,
+            instrument,
+            positions,
+            instruments_cache,
+            trading_groups_cache,
+        )?,
+        pl: positions.iter().map(|x| x.get_pl()).sum(),
+        swaps: positions.iter().map(|x| x.get_swaps()).sum(),
+    }));
+}
+
+pub fn calculate_account_margin_states(
+    account: &impl CrossMarginAccount,
+    positions: &Vec<&impl CrossMarginActivePosition>,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<HashMap<String, CrossMarginInstrumentMarginState>, CrossMarginError> {
+    let mut grouped_positions = HashMap::new();
+
+    for position in positions {
+        grouped_positions
+            .entry(position.get_instrument_id())
+            .or_insert(Vec::new())
+            .push(*position);
+    }
+
+    let mut states = HashMap::new();
+
+    for (instrument, positions) in grouped_positions {
+        if let Some(state) = calculate_instrument_margin_state(
+            account,
+            instrument,
+            &positions,
+            instruments_cache,
+            trading_groups_cache,
+        )? {
+            states.insert(instrument.to_string(), state);
+        }
+    }
+
+    return Ok(states);
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{trading_groups::CrossMarginHedgeMode, CrossMarginPositionSide};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginPositionMarginBreakdown {
+    pub position_id: String,
+    pub side: CrossMarginPositionSide,
+    pub hedged_lots: f64,
+    pub unhedged_lots: f64,
+    pub leverage: f64,
+    pub margin_rate: f64,
+    pub hedged_margin: f64,
+    pub unhedged_margin: f64,
+    pub margin: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginInstrumentMarginBreakdown {
+    pub instrument_id: String,
+    pub hedge_mode: CrossMarginHedgeMode,
+    pub leverage: f64,
+    pub hedged_lots: f64,
+    pub unhedged_lots: f64,
+    pub margin: f64,
+    pub positions: Vec<CrossMarginPositionMarginBreakdown>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginAccountMarginBreakdown {
+    pub account_id: String,
+    pub margin: f64,
+    pub instruments: Vec<CrossMarginInstrumentMarginBreakdown>,
+}
@@ -1,46 +1,147 @@
 use std::collections::HashMap;
 
-use crate::{positions::CrossMarginActivePosition, CrossMarginAccount, CrossMarginPositionSide};
-
-pub struct MarginCalculationDto {
-    pub leverage: f64,
-    pub lots_amount: f64,
-    pub contract_size: f64,
-    pub margin_rate: f64,
-    pub side: CrossMarginPositionSide,
-}
+use crate::{
+    flows::{
+        CrossMarginAccountMarginBreakdown, CrossMarginInstrumentMarginBreakdown,
+        CrossMarginPositionMarginBreakdown,
+    },
+    instruments::CrossMarginInstrumentsCache,
+    positions::CrossMarginActivePosition,
+    trading_groups::{CrossMarginHedgeMode, CrossMarginTradingGroupsCache},
+    CrossMarginAccount, CrossMarginError, CrossMarginPositionSide,
+};
 
 pub fn calculate_margin(
     account: &impl CrossMarginAccount,
     positions: &Vec<&impl CrossMarginActivePosition>,
-) -> f64 {
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<f64, CrossMarginError> {
+    return Ok(calculate_margin_breakdown(
+        account,
+        positions,
+        instruments_cache,
+        trading_groups_cache,
+    )?
+    .margin);
+}
+
+// Account margin split by instrument and position, the total is the sum of the contributions.
+pub fn calculate_margin_breakdown(
+    account: &impl CrossMarginAccount,
+    positions: &Vec<&impl CrossMarginActivePosition>,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<CrossMarginAccountMarginBreakdown, CrossMarginError> {
     let mut grouped_positions = HashMap::new();
-    let mut margin = 0.0;
 
     for position in positions {
         grouped_positions
             .entry(position.get_instrument_id())
             .or_insert(Vec::new())
-            .push(position);
+            .push(*position);
     }
 
-    for (instrument, positions) in grouped_positions {
-        margin += calculate_specific_instrument_margin(
-            &positions,
-            account.get_leverage(),
-            account.get_instruments_leverages().get(instrument),
-        )
-        ;
+    let mut instruments = grouped_positions
+        .into_iter()
+        .map(|(instrument, positions)| {
+            calculate_instrument_margin_breakdown(
+                account,
+                instrument,
+                &positions,
+                instruments_cache,
+                trading_groups_cache,
+            )
+        })
+        .collect::<Result<Vec<CrossMarginInstrumentMarginBreakdown>, CrossMarginError>>()?;
+    instruments.sort_by(|x, y| x.instrument_id.cmp(&y.instrument_id));
+
+    return Ok(CrossMarginAccountMarginBreakdown {
+        account_id: account.get_id().to_string(),
+        margin: instruments.iter().map(|x| x.margin).sum(),
+        instruments,
+    });
+}
+
+// Positions must belong to the instrument.
+pub fn calculate_instrument_margin(
+    account: &impl CrossMarginAccount,
+    instrument: &str,
+    positions: &Vec<&impl CrossMarginActivePosition>,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<f64, CrossMarginError> {
+    return Ok(calculate_instrument_margin_breakdown(
+        account,
+        instrument,
+        positions,
+        instruments_cache,
+        trading_groups_cache,
+    )?
+    .margin);
+}
+
+// Positions must belong to the instrument.
+pub fn calculate_instrument_margin_breakdown(
+    account: &impl CrossMarginAccount,
+    instrument: &str,
+    positions: &Vec<&impl CrossMarginActivePosition>,
+    instruments_cache: &CrossMarginInstrumentsCache,
+    trading_groups_cache: &CrossMarginTradingGroupsCache,
+) -> Result<CrossMarginInstrumentMarginBreakdown, CrossMarginError> {
+    let account_leverage = trading_groups_cache.get_leverage(account)?;
+    let hedge_mode = trading_groups_cache.get_hedge_mode(account)?;
+
+    let mut leverage = instruments_cache.get_max_leverage(instrument, account_leverage);
+
+    if let Some(instrument_leverage) =
+        trading_groups_cache.get_instrument_leverage(account, instrument)?
+    {
+        leverage = instrument_leverage.min(leverage);
+    }
+
+    if let Some(tiers) = instruments_cache.get_leverage_tiers(instrument) {
+        let (buy_notional, sell_notional) = get_instrument_notional(positions);
+        leverage =
+            tiers.get_effective_leverage(tiers.get_exposure(buy_notional, sell_notional), leverage);
+    }
+
+    let positions_breakdown = calculate_specific_instrument_margin(positions, leverage, hedge_mode);
+
+    return Ok(CrossMarginInstrumentMarginBreakdown {
+        instrument_id: instrument.to_string(),
+        hedge_mode,
+        leverage,
+        hedged_lots: positions_breakdown.iter().map(|x| x.hedged_lots).sum(),
+        unhedged_lots: positions_breakdown.iter().map(|x| x.unhedged_lots).sum(),
+        margin: positions_breakdown.iter().map(|x| x.margin).sum(),
+        positions: positions_breakdown,
+    });
+}
+
+// Buy and sell notional in account currency.
+pub fn get_instrument_notional(positions: &Vec<&impl CrossMarginActivePosition>) -> (f64, f64) {
+    let mut buy_notional = 0.0;
+    let mut sell_notional = 0.0;
+
+    for position in positions {
+        let notional =
+            position.get_lots_size() * position.get_lots_amount() * position.get_margin_price();
+
+        match position.get_side() {
+            CrossMarginPositionSide::Buy => buy_notional += notional,
+            CrossMarginPositionSide::Sell => sell_notional += notional,
+        }
     }
 
-    return margin;
+    return (buy_notional, sell_notional);
 }
 
 fn calculate_specific_instrument_margin(
-    positions: &Vec<&&impl CrossMarginActivePosition>,
-    account_leverage: f64,
-    instrument_leverage: Option<&f64>,
-) -> f64 {
+    positions: &Vec<&impl CrossMarginActivePosition>,
+    leverage: f64,
+    hedge_mode: CrossMarginHedgeMode,
+) -> Vec<CrossMarginPositionMarginBreakdown> {
     let mut buy_lots_amount = 0.0;
     let mut sell_lots_amount = 0.0;
 
@@ -51,105 +152,215 @@ fn calculate_specific_instrument_margin(
         }
     }
 
-    let leverage = match instrument_leverage {
-        Some(x) => x.min(account_leverage),
-        None => account_leverage,
+    let is_hedge = buy_lots_amount > 0.0
+        && sell_lots_amount > 0.0
+        && hedge_mode != CrossMarginHedgeMode::Gross;
+
+    let mut buy_hedge_amount = match is_hedge {
+        true => buy_lots_amount.min(sell_lots_amount),
+        false => 0.0,
     };
+    let mut sell_hedge_amount = buy_hedge_amount.clone();
 
-    let is_hedge = buy_lots_amount > 0.0 && sell_lots_amount > 0.0;
+    // hedged lots are charged at the average margin rate of the instrument positions
+    let avg_margin_rate =
+        positions.iter().map(|x| x.get_margin_price()).sum::<f64>() / positions.len() as f64;
 
-    if !is_hedge {
-        return positions
-            .iter()
-            .map(|x| x.get_lots_size() * x.get_lots_amount() / leverage * x.get_margin_price())
-            .sum();
-    }
+    let mut result = Vec::with_capacity(positions.len());
 
-    let mut buy_hedge_amount = buy_lots_amount.min(sell_lots_amount);
-    let mut sell_hedge_amount = buy_hedge_amount.clone();
+    for position in positions {
+        let hedge_amount = match position.get_side() {
+            &CrossMarginPositionSide::Buy => &mut buy_hedge_amount,
+            &CrossMarginPositionSide::Sell => &mut sell_hedge_amount,
+        };
 
-    let mut hedged_positions = vec![];
-    let mut not_hedged_positions = vec![];
+        let hedged_lots = hedge_amount.min(position.get_lots_amount());
+        *hedge_amount = *hedge_amount - hedged_lots;
+        let unhedged_lots = position.get_lots_amount() - hedged_lots;
 
-    for position in positions {
-        match position.get_side() {
-            &CrossMarginPositionSide::Buy => {
-                if buy_hedge_amount < position.get_lots_amount() {
-                    hedged_positions.push(MarginCalculationDto {
-                        leverage: leverage,
-                        lots_amount: buy_hedge_amount,
-                        contract_size: position.get_lots_size(),
-                        margin_rate: position.get_margin_price(),
-                        side: position.get_side().clone(),
-                    });
-                    not_hedged_positions.push(MarginCalculationDto {
-                        leverage: leverage,
-                        lots_amount: position.get_lots_amount() - buy_hedge_amount,
-                        contract_size: position.get_lots_size(),
-                        margin_rate: position.get_margin_price(),
-                        side: position.get_side().clone(),
-                    });
-                    buy_hedge_amount = 0.0;
-                    continue;
-                }
-
-                buy_hedge_amount = buy_hedge_amount - position.get_lots_amount();
-                hedged_positions.push(MarginCalculationDto {
-                    leverage: leverage,
-                    lots_amount: position.get_lots_amount(),
-                    contract_size: position.get_lots_size(),
-                    margin_rate: position.get_margin_price(),
-                    side: position.get_side().clone(),
-                });
-            }
-            &CrossMarginPositionSide::Sell => {
-                if sell_hedge_amount < position.get_lots_amount() {
-                    hedged_positions.push(MarginCalculationDto {
-                        leverage,
-                        lots_amount: sell_hedge_amount,
-                        contract_size: position.get_lots_size(),
-                        margin_rate: position.get_margin_price(),
-                        side: position.get_side().clone(),
-                    });
-                    not_hedged_positions.push(MarginCalculationDto {
-                        leverage,
-                        lots_amount: position.get_lots_amount() - sell_hedge_amount,
-                        contract_size: position.get_lots_size(),
-                        margin_rate: position.get_margin_price(),
-                        side: position.get_side().clone(),
-                    });
-                    sell_hedge_amount = 0.0;
-                    continue;
-                }
-
-                sell_hedge_amount = sell_hedge_amount - position.get_lots_amount();
-                hedged_positions.push(MarginCalculationDto {
-                    leverage: leverage,
-                    lots_amount: position.get_lots_amount(),
-                    contract_size: position.get_lots_size(),
-                    margin_rate: position.get_margin_price(),
-                    side: position.get_side().clone(),
-                });
+        let hedged_margin = match hedge_mode {
+            CrossMarginHedgeMode::Netting => 0.0,
+            _ => {
+                position.get_lots_size() * hedged_lots / leverage * avg_margin_rate
+                    / positions.len() as f64
             }
-        }
-    }
+        };
+        let unhedged_margin =
+            position.get_lots_size() * unhedged_lots / leverage * position.get_margin_price();
 
-    let hedged_margin = calculate_hedged_margin(&hedged_positions);
-    let not_hedged_margin = not_hedged_positions
-        .iter()
-        .map(|x| x.contract_size * x.lots_amount / x.leverage * x.margin_rate)
-        .sum::<f64>();
+        result.push(CrossMarginPositionMarginBreakdown {
+            position_id: position.get_id().to_string(),
+            side: position.get_side().clone(),
+            hedged_lots,
+            unhedged_lots,
+            leverage,
+            margin_rate: position.get_margin_price(),
+            hedged_margin,
+            unhedged_margin,
+            margin: hedged_margin + unhedged_margin,
+        });
+    }
 
-    return hedged_margin + not_hedged_margin;
+    return result;
 }
 
-fn calculate_hedged_margin(positions: &Vec<MarginCalculationDto>) -> f64 {
-    let avg_margin_rate =
-        positions.iter().map(|x| x.margin_rate).sum::<f64>() / positions.len() as f64;
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{eurusd, TestAccount, TestActivePosition},
+        trading_groups::{
+            CrossMarginHedgeMode, CrossMarginTradingGroup, CrossMarginTradingGroupsCache,
+        },
+        CrossMarginInstrumentsCache, CrossMarginLeverageTier, CrossMarginLeverageTiers,
+        CrossMarginPositionSide, CrossMarginTierExposureMode,
+    };
+
+    use super::{calculate_margin, calculate_margin_breakdown};
+
+    #[test]
+    fn test_margin_by_hedge_mode() {
+        let instrument = eurusd();
+        let account = TestAccount::new("acc", 1000.0, 100.0);
+        let buy = TestActivePosition::new(
+            "buy",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            300.0,
+            1.1,
+        );
+        let sell = TestActivePosition::new(
+            "sell",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Sell,
+            100.0,
+            1.1,
+        );
+        let positions = vec![&buy, &sell];
+        let instruments_cache = CrossMarginInstrumentsCache::new(vec![]);
+
+        let margin = |hedge_mode| {
+            let mut group = CrossMarginTradingGroup::new("default");
+            group.hedge_mode = hedge_mode;
+            let trading_groups_cache = CrossMarginTradingGroupsCache::new(vec![group]);
+            return calculate_margin(
+                &account,
+                &positions,
+                &instruments_cache,
+                &trading_groups_cache,
+            )
+            .unwrap();
+        };
 
-    return positions
-        .iter()
-        .map(|x| x.contract_size * x.lots_amount / x.leverage * avg_margin_rate)
-        .sum::<f64>()
-        / positions.len() as f64;
+        assert_eq!(margin(CrossMarginHedgeMode::Gross), 4.0);
+        assert_eq!(margin(CrossMarginHedgeMode::Netting), 2.0);
+        assert_eq!(margin(CrossMarginHedgeMode::Hedging), 3.0);
+    }
+
+    #[test]
+    fn test_margin_with_leverage_tiers() {
+        let instrument = eurusd();
+        let account = TestAccount::new("acc", 100_000.0, 1000.0);
+        let buy = TestActivePosition::new(
+            "buy",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            2_000_000.0,
+            1.1,
+        );
+        let positions = vec![&buy];
+        let trading_groups_cache =
+            CrossMarginTradingGroupsCache::new(vec![CrossMarginTradingGroup::new("default")]);
+        let mut instruments_cache = CrossMarginInstrumentsCache::new(vec![]);
+        instruments_cache.update_leverage_tiers(CrossMarginLeverageTiers {
+            instrument_id: instrument.id.clone(),
+            exposure_mode: CrossMarginTierExposureMode::Net,
+            tiers: vec![
+                CrossMarginLeverageTier {
+                    notional_to: Some(1_000_000.0),
+                    leverage: 500.0,
+                },
+                CrossMarginLeverageTier {
+                    notional_to: None,
+                    leverage: 100.0,
+                },
+            ],
+        });
+
+        let margin = calculate_margin(
+            &account,
+            &positions,
+            &instruments_cache,
+            &trading_groups_cache,
+        )
+        .unwrap();
+
+        assert!((margin - 12_000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_margin_breakdown_adds_up() {
+        let instrument = eurusd();
+        let account = TestAccount::new("acc", 1000.0, 100.0);
+        let buy = TestActivePosition::new(
+            "buy",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Buy,
+            300.0,
+            1.1,
+        );
+        let mut sell = TestActivePosition::new(
+            "sell",
+            "acc",
+            &instrument,
+            CrossMarginPositionSide::Sell,
+            100.0,
+            1.1,
+        );
+        sell.margin_price = 1.3;
+        let positions = vec![&buy, &sell];
+        let instruments_cache = CrossMarginInstrumentsCache::new(vec![]);
+        let trading_groups_cache =
+            CrossMarginTradingGroupsCache::new(vec![CrossMarginTradingGroup::new("default")]);
+
+        let breakdown = calculate_margin_breakdown(
+            &account,
+            &positions,
+            &instruments_cache,
+            &trading_groups_cache,
+        )
+        .unwrap();
+
+        assert_eq!(
+            breakdown.margin,
+            calculate_margin(
+                &account,
+                &positions,
+                &instruments_cache,
+                &trading_groups_cache
+            )
+            .unwrap()
+        );
+        assert_eq!(breakdown.instruments.len(), 1);
+
+        let instrument = &breakdown.instruments[0];
+        assert_eq!(instrument.hedged_lots, 200.0);
+        assert_eq!(instrument.unhedged_lots, 200.0);
+        assert_eq!(
+            instrument.margin,
+            instrument.positions.iter().map(|x| x.margin).sum::<f64>()
+        );
+
+        let buy = instrument
+            .positions
+            .iter()
+            .find(|x| x.position_id == "buy")
+            .unwrap();
+        assert_eq!(buy.hedged_lots, 100.0);
+        assert_eq!(buy.unhedged_lots, 200.0);
+        assert!((buy.unhedged_margin - 2.0).abs() < 1e-9);
+    }
 }
@@ -1,3 +1,7 @@
+mod account_margin_state;
+mod margin_breakdown;
 mod margin_calculation;
 
-pub use margin_calculation::*;
\ No newline at end of file
+pub use account_margin_state::*;
+pub use margin_breakdown::*;
+pub use margin_calculation::*;
@@ -1,17 +1,33 @@
 mod margin;
 mod background;
 mod active_positions;
+mod apply_spread_markup;
 mod calculate_account_data;
+mod calculate_commission;
+mod calculate_liquidation_price;
+mod calculate_position_swap;
+mod calculate_stop_execution;
 mod is_account_stop_out_hit;
 mod get_position_close_reason;
 mod is_pending_ready_to_execute;
 mod is_enough_balance_to_open_position;
+mod simulate_price_shocks;
+mod validate_account_trading_state;
+mod validate_order;
 
 pub use margin::*;
 pub use background::*;
 pub use active_positions::*;
+pub use apply_spread_markup::*;
 pub use calculate_account_data::*;
+pub use calculate_commission::*;
+pub use calculate_liquidation_price::*;
+pub use calculate_position_swap::*;
+pub use calculate_stop_execution::*;
 pub use is_account_stop_out_hit::*;
 pub use get_position_close_reason::*;
 pub use is_pending_ready_to_execute::*;
-pub use is_enough_balance_to_open_position::*;
\ No newline at end of file
+pub use is_enough_balance_to_open_position::*;
+pub use simulate_price_shocks::*;
+pub use validate_account_trading_state::*;
+pub use validate_order::*;
\ No newline at end of file
@@ -0,0 +1,274 @@
+use std::collections::{BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache_aggregate::CrossMarginCaches,
+    flows::{calculate_account_data, update_position_rates},
+    positions::{
+        CrossMarginActivePosition, CrossMarginPendingPosition,
+        CrossMarginPositionsCacheQueryBuilder, CrossMarginPositionsOneOfBulkQueryBuilder,
+    },
+    CrossMarginAccount, CrossMarginBidAskCache, CrossMarginError,
+};
+
+// Percent is positive for the instrument price or the currency going up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CrossMarginPriceShock {
+    Instrument { instrument_id: String, percent: f64 },
+    // Moves the currency against everything: pairs where it is base go up, where it is quote go down.
+    Currency { currency: String, percent: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginShockAccountResult {
+    pub trader_id: String,
+    pub account_id: String,
+    pub margin_level_before: f64,
+    pub margin_level_after: f64,
+    pub equity_after: f64,
+    pub is_stop_out: bool,
+    // Positions closed by stop out in the max loss first order, until the margin level recovers.
+    pub liquidated_positions: Vec<String>,
+}
+
+pub fn simulate_price_shocks<
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+>(
+    caches: &CrossMarginCaches<A, AP, PP>,
+    shocks: &[CrossMarginPriceShock],
+) -> Result<Vec<CrossMarginShockAccountResult>, CrossMarginError> {
+    let mut prices_cache = caches.prices_cache.clone();
+    let shocked_assets = apply_price_shocks(&mut prices_cache, shocks);
+
+    if shocked_assets.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let shocked_assets: Vec<String> = shocked_assets.into_iter().collect();
+    // Ordered set, so results come sorted by account id.
+    let affected_accounts: BTreeSet<String> = caches
+        .active_positions_cache
+        .bulk_query_positions(
+            CrossMarginPositionsOneOfBulkQueryBuilder::new()
+                .with_base(shocked_assets.clone())
+                .with_quote(shocked_assets.clone())
+                .with_collateral(shocked_assets),
+        )
+        .into_iter()
+        .map(|x| x.get_account_id().to_string())
+        .collect();
+
+    let stop_out_on_closed_market = caches.settings.stop_out_on_closed_market;
+    let now = caches.clock.now();
+    let mut result = vec![];
+
+    for account_id in affected_accounts {
+        let Some(account) = caches.accounts_cache.get_account(&account_id) else {
+            continue;
+        };
+
+        let account_positions = caches.active_positions_cache.query_positions(
+            CrossMarginPositionsCacheQueryBuilder::new().with_account(&account_id),
+        );
+
+        let margin_level_before = calculate_account_data(
+            account,
+            &account_positions,
+            &caches.instruments_cache,
+            &caches.trading_groups_cache,
+        )?
+        .margin_level;
+
+        let spread_markup = caches
+            .trading_groups_cache
+            .get_account_group(account)?
+            .spread_markup
+            .as_ref();
+
+        let mut account = account.clone();
+        let mut positions: Vec<AP> = account_positions.into_iter().cloned().collect();
+
+        let stop_out = caches.trading_groups_cache.get_stop_out(&account)?;
+        let mut liquidated_positions = vec![];
+
+        let account_data = loop {
+            // Remaining positions are re-rated before every stop out check.
+            for position in positions.iter_mut() {
+                update_position_rates(
+                    position,
+                    &prices_cache,
+                    &caches.instruments_cache,
+                    spread_markup,
+                )?;
+            }
+
+            let account_data = calculate_account_data(
+                &account,
+                &positions.iter().collect(),
+                &caches.instruments_cache,
+                &caches.trading_groups_cache,
+            )?;
+
+            if !account_data.is_stop_out_hit(stop_out) {
+                break account_data;
+            }
+
+            let max_loss_position = positions
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| {
+                    stop_out_on_closed_market
+                        || caches
+                            .instruments_cache
+                            .is_market_open(x.get_instrument_id(), now)
+                })
+                .min_by(|(_, x), (_, y)| x.get_pl().partial_cmp(&y.get_pl()).unwrap())
+                .map(|(index, _)| index);
+
+            let Some(index) = max_loss_position else {
+                break account_data;
+            };
+
+            let position = positions.remove(index);
+            account.update_balance(position.get_pl() + position.get_swaps());
+            liquidated_positions.push(position.get_id().to_string());
+        };
+
+        result.push(CrossMarginShockAccountResult {
+            trader_id: account.get_trader_id().to_string(),
+            account_id,
+            margin_level_before,
+            margin_level_after: account_data.margin_level,
+            equity_after: account_data.equity,
+            is_stop_out: !liquidated_positions.is_empty(),
+            liquidated_positions,
+        });
+    }
+
+    return Ok(result);
+}
+
+// Returns currencies of the shocked pairs.
+fn apply_price_shocks(
+    prices_cache: &mut CrossMarginBidAskCache,
+    shocks: &[CrossMarginPriceShock],
+) -> HashSet<String> {
+    let mut shocked_assets = HashSet::new();
+
+    for price in prices_cache.get_all() {
+        let mut multiplier = 1.0;
+
+        for shock in shocks {
+            match shock {
+                CrossMarginPriceShock::Instrument {
+                    instrument_id,
+                    percent,
+                } => {
+                    if &price.asset_pair == instrument_id {
+                        multiplier *= 1.0 + percent / 100.0;
+                    }
+                }
+                CrossMarginPriceShock::Currency { currency, percent } => {
+                    if &price.base == currency {
+                        multiplier *= 1.0 + percent / 100.0;
+                    }
+
+                    if &price.quote == currency {
+                        multiplier /= 1.0 + percent / 100.0;
+                    }
+                }
+            }
+        }
+
+        if multiplier == 1.0 {
+            continue;
+        }
+
+        let mut shocked_price = price.as_ref().clone();
+        shocked_price.bid *= multiplier;
+        shocked_price.ask *= multiplier;
+
+        shocked_assets.insert(shocked_price.base.clone());
+        shocked_assets.insert(shocked_price.quote.clone());
+        prices_cache.handle_new_with_crosses(shocked_price);
+    }
+
+    return shocked_assets;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{
+            create_caches, create_netted_caches, eurusd, TestAccount, TestActivePosition,
+        },
+        CrossMarginPositionSide, CrossMarginPriceShock,
+    };
+
+    #[tokio::test]
+    async fn test_shock_liquidates_without_live_changes() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![
+            TestAccount::new("small", 1000.0, 100.0),
+            TestAccount::new("big", 100_000.0, 100.0),
+        ])
+        .await;
+
+        for account_id in ["small", "big"] {
+            caches
+                .add_active_position(
+                    TestActivePosition::new(
+                        account_id,
+                        account_id,
+                        &instrument,
+                        CrossMarginPositionSide::Buy,
+                        100_000.0,
+                        1.1002,
+                    ),
+                    "open",
+                )
+                .await
+                .unwrap();
+        }
+
+        let result = caches
+            .simulate_price_shocks(&[CrossMarginPriceShock::Currency {
+                currency: "EUR".to_string(),
+                percent: -1.0,
+            }])
+            .unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].account_id, "big");
+        assert_eq!(result[1].account_id, "small");
+        assert!(!result[0].is_stop_out);
+        assert!(result[1].is_stop_out);
+        assert_eq!(result[1].liquidated_positions, vec!["small".to_string()]);
+
+        assert_eq!(
+            caches.prices_cache.get_by_id(&instrument.id).unwrap().bid,
+            1.1
+        );
+        assert!(caches.active_positions_cache.get_by_id("small").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_shock_keeps_fully_netted_account() {
+        let instrument = eurusd();
+        let caches = create_netted_caches(10.0).await;
+
+        let result = caches
+            .simulate_price_shocks(&[CrossMarginPriceShock::Instrument {
+                instrument_id: instrument.id.clone(),
+                percent: -5.0,
+            }])
+            .unwrap();
+
+        assert_eq!(result.len(), 1);
+        assert!(!result[0].is_stop_out);
+        assert!(result[0].liquidated_positions.is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+use crate::{CrossMarginAccount, CrossMarginError};
+
+pub fn validate_account_open_allowed(
+    account: &impl CrossMarginAccount,
+) -> Result<(), CrossMarginError> {
+    let trading_state = account.get_trading_state();
+
+    if trading_state.is_open_allowed() {
+        return Ok(());
+    }
+
+    return Err(CrossMarginError::TradingNotAllowed(trading_state));
+}
+
+// Client closes and SL/TP changes, forced closes don't depend on the trading state.
+pub fn validate_account_close_allowed(
+    account: &impl CrossMarginAccount,
+) -> Result<(), CrossMarginError> {
+    let trading_state = account.get_trading_state();
+
+    if trading_state.is_client_close_allowed() {
+        return Ok(());
+    }
+
+    return Err(CrossMarginError::TradingNotAllowed(trading_state));
+}
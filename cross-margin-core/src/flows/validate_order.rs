@@ -0,0 +1,358 @@
+use crate::{
+    instruments::{
+        CrossMarginInstrumentSpec, CrossMarginInstrumentsCache, CrossMarginOrderViolation,
+    },
+    positions::{CrossMarginPendingPosition, CrossMarginPosition},
+    CrossMarginError, CrossMarginPositionSide,
+};
+
+// Instruments without registered spec are not validated.
+pub fn validate_active_position_order(
+    instruments_cache: &CrossMarginInstrumentsCache,
+    position: &impl CrossMarginPosition,
+    market_price: f64,
+) -> Result<(), CrossMarginError> {
+    let Some(spec) = instruments_cache.get_spec(position.get_instrument_id()) else {
+        return Ok(());
+    };
+
+    let mut violations = vec![];
+    validate_volume(
+        spec,
+        position.get_lots_size(),
+        position.get_lots_amount(),
+        &mut violations,
+    );
+    validate_sl_tp(
+        spec,
+        position.get_side(),
+        market_price,
+        position.get_sl_price(),
+        position.get_tp_price(),
+        &mut violations,
+    );
+
+    return into_result(violations);
+}
+
+pub fn validate_pending_position_order(
+    instruments_cache: &CrossMarginInstrumentsCache,
+    position: &impl CrossMarginPendingPosition,
+    market_price: f64,
+) -> Result<(), CrossMarginError> {
+    let Some(spec) = instruments_cache.get_spec(position.get_instrument_id()) else {
+        return Ok(());
+    };
+
+    let mut violations = vec![];
+    let desired_price = position.get_desired_price();
+
+    validate_volume(
+        spec,
+        position.get_lots_size(),
+        position.get_lots_amount(),
+        &mut violations,
+    );
+
+    if !spec.is_valid_price_tick(desired_price) {
+        violations.push(CrossMarginOrderViolation::PriceNotMultipleOfTick {
+            tick: spec.get_tick_size(),
+            price: desired_price,
+        });
+    }
+
+    let distance = (desired_price - market_price).abs();
+    if distance < spec.get_min_stop_distance() {
+        violations.push(CrossMarginOrderViolation::PendingPriceTooClose {
+            min_distance: spec.get_min_stop_distance(),
+            distance,
+        });
+    }
+
+    validate_sl_tp(
+        spec,
+        position.get_side(),
+        desired_price,
+        position.get_sl_price(),
+        position.get_tp_price(),
+        &mut violations,
+    );
+
+    return into_result(violations);
+}
+
+pub fn validate_sl_tp_update(
+    instruments_cache: &CrossMarginInstrumentsCache,
+    instrument_id: &str,
+    side: &CrossMarginPositionSide,
+    market_price: f64,
+    sl_price: Option<f64>,
+    tp_price: Option<f64>,
+) -> Result<(), CrossMarginError> {
+    let Some(spec) = instruments_cache.get_spec(instrument_id) else {
+        return Ok(());
+    };
+
+    let mut violations = vec![];
+    validate_sl_tp(
+        spec,
+        side,
+        market_price,
+        sl_price,
+        tp_price,
+        &mut violations,
+    );
+
+    return into_result(violations);
+}
+
+fn validate_volume(
+    spec: &CrossMarginInstrumentSpec,
+    lots_size: f64,
+    lots_amount: f64,
+    violations: &mut Vec<CrossMarginOrderViolation>,
+) {
+    if (spec.contract_size - lots_size).abs() > f64::EPSILON * spec.contract_size.abs().max(1.0) {
+        violations.push(CrossMarginOrderViolation::ContractSizeMismatch {
+            expected: spec.contract_size,
+            actual: lots_size,
+        });
+    }
+
+    if lots_amount < spec.min_lots {
+        violations.push(CrossMarginOrderViolation::VolumeBelowMin {
+            min: spec.min_lots,
+            actual: lots_amount,
+        });
+    }
+
+    if lots_amount > spec.max_lots {
+        violations.push(CrossMarginOrderViolation::VolumeAboveMax {
+            max: spec.max_lots,
+            actual: lots_amount,
+        });
+    }
+
+    if !spec.is_valid_lot_step(lots_amount) {
+        violations.push(CrossMarginOrderViolation::VolumeNotMultipleOfStep {
+            step: spec.lot_step,
+            actual: lots_amount,
+        });
+    }
+}
+
+fn validate_sl_tp(
+    spec: &CrossMarginInstrumentSpec,
+    side: &CrossMarginPositionSide,
+    reference_price: f64,
+    sl_price: Option<f64>,
+    tp_price: Option<f64>,
+    violations: &mut Vec<CrossMarginOrderViolation>,
+) {
+    let min_distance = spec.get_min_stop_distance();
+
+    if let Some(sl) = sl_price {
+        let distance = match side {
+            CrossMarginPositionSide::Buy => reference_price - sl,
+            CrossMarginPositionSide::Sell => sl - reference_price,
+        };
+
+        if distance <= 0.0 {
+            violations.push(CrossMarginOrderViolation::SlWrongSide {
+                reference_price,
+                sl,
+            });
+        } else if distance < min_distance {
+            violations.push(CrossMarginOrderViolation::SlTooClose {
+                min_distance,
+                distance,
+            });
+        }
+
+        if !spec.is_valid_price_tick(sl) {
+            violations.push(CrossMarginOrderViolation::PriceNotMultipleOfTick {
+                tick: spec.get_tick_size(),
+                price: sl,
+            });
+        }
+    }
+
+    if let Some(tp) = tp_price {
+        let distance = match side {
+            CrossMarginPositionSide::Buy => tp - reference_price,
+            CrossMarginPositionSide::Sell => reference_price - tp,
+        };
+
+        if distance <= 0.0 {
+            violations.push(CrossMarginOrderViolation::TpWrongSide {
+                reference_price,
+                tp,
+            });
+        } else if distance < min_distance {
+            violations.push(CrossMarginOrderViolation::TpTooClose {
+                min_distance,
+                distance,
+            });
+        }
+
+        if !spec.is_valid_price_tick(tp) {
+            violations.push(CrossMarginOrderViolation::PriceNotMultipleOfTick {
+                tick: spec.get_tick_size(),
+                price: tp,
+            });
+        }
+    }
+}
+
+fn into_result(violations: Vec<CrossMarginOrderViolation>) -> Result<(), CrossMarginError> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    return Err(CrossMarginError::InvalidOrder(violations));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{eurusd, TestActivePosition, TestPendingPosition},
+        CrossMarginPendingPositionType,
+    };
+
+    use super::*;
+
+    fn create_instruments_cache() -> CrossMarginInstrumentsCache {
+        CrossMarginInstrumentsCache::new(vec![CrossMarginInstrumentSpec {
+            id: "EURUSD".to_string(),
+            contract_size: 1.0,
+            min_lots: 0.01,
+            max_lots: 100.0,
+            lot_step: 0.01,
+            digits: 5,
+            max_leverage: Some(100.0),
+            stops_level: 10,
+        }])
+    }
+
+    fn get_violations(result: Result<(), CrossMarginError>) -> Vec<CrossMarginOrderViolation> {
+        match result {
+            Ok(_) => vec![],
+            Err(CrossMarginError::InvalidOrder(violations)) => violations,
+            Err(err) => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_valid_position() {
+        let cache = create_instruments_cache();
+        let mut position = TestActivePosition::new(
+            "id",
+            "acc",
+            &eurusd(),
+            CrossMarginPositionSide::Buy,
+            1.25,
+            1.1,
+        );
+        position.sl_price = Some(1.09);
+        position.tp_price = Some(1.11);
+
+        assert!(validate_active_position_order(&cache, &position, 1.1).is_ok());
+    }
+
+    #[test]
+    fn test_volume_violations() {
+        let cache = create_instruments_cache();
+        let mut position = TestActivePosition::new(
+            "id",
+            "acc",
+            &eurusd(),
+            CrossMarginPositionSide::Buy,
+            0.015,
+            1.1,
+        );
+        position.lots_size = 1000.0;
+
+        let violations = get_violations(validate_active_position_order(&cache, &position, 1.1));
+
+        assert_eq!(
+            violations,
+            vec![
+                CrossMarginOrderViolation::ContractSizeMismatch {
+                    expected: 1.0,
+                    actual: 1000.0
+                },
+                CrossMarginOrderViolation::VolumeNotMultipleOfStep {
+                    step: 0.01,
+                    actual: 0.015
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sl_tp_violations() {
+        let cache = create_instruments_cache();
+        let mut position = TestActivePosition::new(
+            "id",
+            "acc",
+            &eurusd(),
+            CrossMarginPositionSide::Sell,
+            1.0,
+            1.1,
+        );
+        position.sl_price = Some(1.09);
+        position.tp_price = Some(1.09995);
+
+        let violations = get_violations(validate_active_position_order(&cache, &position, 1.1));
+
+        assert_eq!(violations.len(), 2);
+        assert!(matches!(
+            violations[0],
+            CrossMarginOrderViolation::SlWrongSide { .. }
+        ));
+        assert!(matches!(
+            violations[1],
+            CrossMarginOrderViolation::TpTooClose { .. }
+        ));
+    }
+
+    #[test]
+    fn test_pending_price_violations() {
+        let cache = create_instruments_cache();
+        let pending = TestPendingPosition::new(
+            "id",
+            "acc",
+            &eurusd(),
+            CrossMarginPendingPositionType::BuyLimit,
+            1.0,
+            1.099995,
+        );
+
+        let violations = get_violations(validate_pending_position_order(&cache, &pending, 1.1));
+
+        assert_eq!(violations.len(), 2);
+        assert!(matches!(
+            violations[0],
+            CrossMarginOrderViolation::PriceNotMultipleOfTick { .. }
+        ));
+        assert!(matches!(
+            violations[1],
+            CrossMarginOrderViolation::PendingPriceTooClose { .. }
+        ));
+    }
+
+    #[test]
+    fn test_instrument_without_spec_is_not_validated() {
+        let cache = CrossMarginInstrumentsCache::new(vec![]);
+        let position = TestActivePosition::new(
+            "id",
+            "acc",
+            &eurusd(),
+            CrossMarginPositionSide::Buy,
+            0.0001,
+            1.1,
+        );
+
+        assert!(validate_active_position_order(&cache, &position, 1.1).is_ok());
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::instruments::{
+    CrossMarginInstrumentSpec, CrossMarginInstrumentSwap, CrossMarginLeverageTiers,
+    CrossMarginTradingSchedule,
+};
+
+#[derive(Clone)]
+pub struct CrossMarginInstrumentsCache {
+    specs: HashMap<String, CrossMarginInstrumentSpec>,
+    schedules: HashMap<String, CrossMarginTradingSchedule>,
+    swaps: HashMap<String, CrossMarginInstrumentSwap>,
+    leverage_tiers: HashMap<String, CrossMarginLeverageTiers>,
+    // Bumped on changes which affect margin: specs and leverage tiers.
+    margin_revision: u64,
+}
+
+impl CrossMarginInstrumentsCache {
+    pub fn new(specs: Vec<CrossMarginInstrumentSpec>) -> Self {
+        Self {
+            specs: specs.into_iter().map(|x| (x.id.clone(), x)).collect(),
+            schedules: HashMap::new(),
+            swaps: HashMap::new(),
+            leverage_tiers: HashMap::new(),
+            margin_revision: 0,
+        }
+    }
+
+    pub fn get_spec(&self, instrument_id: &str) -> Option<&CrossMarginInstrumentSpec> {
+        return self.specs.get(instrument_id);
+    }
+
+    pub fn update_spec(&mut self, spec: CrossMarginInstrumentSpec) {
+        self.margin_revision += 1;
+        self.specs.insert(spec.id.clone(), spec);
+    }
+
+    pub fn remove_spec(&mut self, instrument_id: &str) -> Option<CrossMarginInstrumentSpec> {
+        self.margin_revision += 1;
+        return self.specs.remove(instrument_id);
+    }
+
+    pub fn get_all(&self) -> Vec<&CrossMarginInstrumentSpec> {
+        return self.specs.values().collect();
+    }
+
+    // Instrument cap is applied on top of the account leverage, instruments without spec are not capped.
+    pub fn get_max_leverage(&self, instrument_id: &str, leverage: f64) -> f64 {
+        match self.specs.get(instrument_id) {
+            Some(spec) => spec.get_leverage(leverage),
+            None => leverage,
+        }
+    }
+
+    pub fn get_schedule(&self, instrument_id: &str) -> Option<&CrossMarginTradingSchedule> {
+        return self.schedules.get(instrument_id);
+    }
+
+    pub fn update_schedule(&mut self, schedule: CrossMarginTradingSchedule) {
+        self.schedules
+            .insert(schedule.instrument_id.clone(), schedule);
+    }
+
+    pub fn remove_schedule(&mut self, instrument_id: &str) -> Option<CrossMarginTradingSchedule> {
+        return self.schedules.remove(instrument_id);
+    }
+
+    pub fn get_swap(&self, instrument_id: &str) -> Option<&CrossMarginInstrumentSwap> {
+        return self.swaps.get(instrument_id);
+    }
+
+    pub fn update_swap(&mut self, swap: CrossMarginInstrumentSwap) {
+        self.swaps.insert(swap.instrument_id.clone(), swap);
+    }
+
+    pub fn remove_swap(&mut self, instrument_id: &str) -> Option<CrossMarginInstrumentSwap> {
+        return self.swaps.remove(instrument_id);
+    }
+
+    pub fn get_leverage_tiers(&self, instrument_id: &str) -> Option<&CrossMarginLeverageTiers> {
+        return self.leverage_tiers.get(instrument_id);
+    }
+
+    pub fn update_leverage_tiers(&mut self, tiers: CrossMarginLeverageTiers) {
+        self.margin_revision += 1;
+        self.leverage_tiers
+            .insert(tiers.instrument_id.clone(), tiers);
+    }
+
+    pub fn remove_leverage_tiers(
+        &mut self,
+        instrument_id: &str,
+    ) -> Option<CrossMarginLeverageTiers> {
+        self.margin_revision += 1;
+        return self.leverage_tiers.remove(instrument_id);
+    }
+
+    pub fn get_margin_revision(&self) -> u64 {
+        return self.margin_revision;
+    }
+
+    // Instruments without schedule are traded around the clock.
+    pub fn is_market_open(&self, instrument_id: &str, now: DateTimeAsMicroseconds) -> bool {
+        match self.schedules.get(instrument_id) {
+            Some(schedule) => schedule.is_open(now),
+            None => true,
+        }
+    }
+}
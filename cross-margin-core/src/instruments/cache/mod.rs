@@ -0,0 +1,3 @@
+mod instruments_cache;
+
+pub use instruments_cache::*;
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginInstrumentSpec {
+    pub id: String,
+    pub contract_size: f64,
+    pub min_lots: f64,
+    pub max_lots: f64,
+    pub lot_step: f64,
+    pub digits: u32,
+    pub max_leverage: Option<f64>,
+    // Minimal distance between the reference price and SL/TP or pending price, in points.
+    pub stops_level: u32,
+}
+
+impl CrossMarginInstrumentSpec {
+    pub fn get_tick_size(&self) -> f64 {
+        return 10f64.powi(-(self.digits as i32));
+    }
+
+    pub fn get_min_stop_distance(&self) -> f64 {
+        return self.stops_level as f64 * self.get_tick_size();
+    }
+
+    pub fn is_valid_lot_step(&self, lots_amount: f64) -> bool {
+        if self.lot_step <= 0.0 {
+            return true;
+        }
+
+        return is_multiple_of(lots_amount, self.lot_step);
+    }
+
+    pub fn round_lots_down(&self, lots_amount: f64) -> f64 {
+        if self.lot_step <= 0.0 {
+            return lots_amount;
+        }
+
+        return (lots_amount / self.lot_step + 1e-9).floor() * self.lot_step;
+    }
+
+    pub fn is_valid_price_tick(&self, price: f64) -> bool {
+        return is_multiple_of(price, self.get_tick_size());
+    }
+
+    pub fn get_leverage(&self, leverage: f64) -> f64 {
+        match self.max_leverage {
+            Some(max_leverage) => leverage.min(max_leverage),
+            None => leverage,
+        }
+    }
+}
+
+fn is_multiple_of(value: f64, step: f64) -> bool {
+    let steps = value / step;
+
+    return (steps - steps.round()).abs() <= 1e-9 * steps.abs().max(1.0);
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CrossMarginOrderViolation {
+    ContractSizeMismatch { expected: f64, actual: f64 },
+    VolumeBelowMin { min: f64, actual: f64 },
+    VolumeAboveMax { max: f64, actual: f64 },
+    VolumeNotMultipleOfStep { step: f64, actual: f64 },
+    PriceNotMultipleOfTick { tick: f64, price: f64 },
+    PendingPriceTooClose { min_distance: f64, distance: f64 },
+    SlWrongSide { reference_price: f64, sl: f64 },
+    SlTooClose { min_distance: f64, distance: f64 },
+    TpWrongSide { reference_price: f64, tp: f64 },
+    TpTooClose { min_distance: f64, distance: f64 },
+}
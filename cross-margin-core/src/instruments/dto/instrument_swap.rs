@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::CrossMarginPositionSide;
+
+pub const SWAP_DAYS_IN_YEAR: f64 = 360.0;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CrossMarginSwapType {
+    // Points per lot, point is the instrument tick size.
+    Points = 0,
+    // Annual percent of the position notional.
+    Percentage = 1,
+}
+
+// Positive rates are paid to the trader, negative are charged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginInstrumentSwap {
+    pub instrument_id: String,
+    pub swap_type: CrossMarginSwapType,
+    pub long: f64,
+    pub short: f64,
+    // 0 - Monday, 6 - Sunday
+    pub triple_swap_day: Option<u32>,
+    pub charge_weekends: bool,
+}
+
+impl CrossMarginInstrumentSwap {
+    pub fn get_rate(&self, side: &CrossMarginPositionSide) -> f64 {
+        match side {
+            CrossMarginPositionSide::Buy => self.long,
+            CrossMarginPositionSide::Sell => self.short,
+        }
+    }
+
+    pub fn get_multiplier(&self, day_of_week: u32) -> f64 {
+        if !self.charge_weekends && day_of_week >= 5 {
+            return 0.0;
+        }
+
+        if self.triple_swap_day == Some(day_of_week) {
+            return 3.0;
+        }
+
+        return 1.0;
+    }
+}
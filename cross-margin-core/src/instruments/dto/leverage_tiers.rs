@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossMarginTierExposureMode {
+    // Tiers are applied to |buy - sell| notional of the instrument.
+    Net = 0,
+    // Tiers are applied to buy + sell notional of the instrument.
+    Gross = 1,
+}
+
+// Notional is in account currency, tier without upper bound covers everything above the previous one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginLeverageTier {
+    pub notional_to: Option<f64>,
+    pub leverage: f64,
+}
+
+// Tiers are sorted by notional_to, each tier leverage is applied only to the notional inside it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginLeverageTiers {
+    pub instrument_id: String,
+    pub exposure_mode: CrossMarginTierExposureMode,
+    pub tiers: Vec<CrossMarginLeverageTier>,
+}
+
+impl CrossMarginLeverageTiers {
+    pub fn get_exposure(&self, buy_notional: f64, sell_notional: f64) -> f64 {
+        match self.exposure_mode {
+            CrossMarginTierExposureMode::Net => (buy_notional - sell_notional).abs(),
+            CrossMarginTierExposureMode::Gross => buy_notional + sell_notional,
+        }
+    }
+
+    // Tier leverage is never higher than the account leverage.
+    pub fn get_margin(&self, notional: f64, max_leverage: f64) -> f64 {
+        let mut margin = 0.0;
+        let mut covered = 0.0;
+
+        for tier in &self.tiers {
+            if covered >= notional {
+                return margin;
+            }
+
+            let tier_to = tier.notional_to.unwrap_or(f64::MAX).min(notional);
+            if tier_to > covered {
+                margin += (tier_to - covered) / tier.leverage.min(max_leverage);
+                covered = tier_to;
+            }
+        }
+
+        if covered < notional {
+            let leverage = self
+                .tiers
+                .last()
+                .map(|x| x.leverage.min(max_leverage))
+                .unwrap_or(max_leverage);
+            margin += (notional - covered) / leverage;
+        }
+
+        return margin;
+    }
+
+    pub fn get_effective_leverage(&self, notional: f64, max_leverage: f64) -> f64 {
+        if notional <= 0.0 {
+            return max_leverage;
+        }
+
+        return notional / self.get_margin(notional, max_leverage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiers() -> CrossMarginLeverageTiers {
+        CrossMarginLeverageTiers {
+            instrument_id: "EURUSD".to_string(),
+            exposure_mode: CrossMarginTierExposureMode::Gross,
+            tiers: vec![
+                CrossMarginLeverageTier {
+                    notional_to: Some(1_000_000.0),
+                    leverage: 500.0,
+                },
+                CrossMarginLeverageTier {
+                    notional_to: Some(5_000_000.0),
+                    leverage: 100.0,
+                },
+                CrossMarginLeverageTier {
+                    notional_to: None,
+                    leverage: 20.0,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_tiered_margin() {
+        let tiers = tiers();
+
+        assert_eq!(tiers.get_margin(500_000.0, 1000.0), 1_000.0);
+        assert_eq!(tiers.get_margin(3_000_000.0, 1000.0), 2_000.0 + 20_000.0);
+        assert_eq!(
+            tiers.get_margin(6_000_000.0, 1000.0),
+            2_000.0 + 40_000.0 + 50_000.0
+        );
+        // account leverage caps every tier
+        assert_eq!(tiers.get_margin(2_000_000.0, 200.0), 5_000.0 + 10_000.0);
+    }
+}
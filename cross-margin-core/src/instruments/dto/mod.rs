@@ -0,0 +1,9 @@
+mod instrument_spec;
+mod instrument_swap;
+mod leverage_tiers;
+mod trading_schedule;
+
+pub use instrument_spec::*;
+pub use instrument_swap::*;
+pub use leverage_tiers::*;
+pub use trading_schedule::*;
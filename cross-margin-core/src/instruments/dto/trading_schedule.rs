@@ -0,0 +1,133 @@
+use chrono::{Datelike, NaiveDate, Timelike};
+use serde::{Deserialize, Serialize};
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+// Minutes are counted from the start of the UTC day, `to_minute` is exclusive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginTradingTimeRange {
+    pub from_minute: u32,
+    pub to_minute: u32,
+}
+
+impl CrossMarginTradingTimeRange {
+    pub fn contains(&self, minute: u32) -> bool {
+        return self.from_minute <= minute && minute < self.to_minute;
+    }
+}
+
+// 0 - Monday, 6 - Sunday
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginWeeklySession {
+    pub day_of_week: u32,
+    pub range: CrossMarginTradingTimeRange,
+}
+
+// Replaces weekly sessions for a specific date. Empty ranges means holiday, otherwise it is a half day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginTradingDayOverride {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub ranges: Vec<CrossMarginTradingTimeRange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginTradingSchedule {
+    pub instrument_id: String,
+    pub sessions: Vec<CrossMarginWeeklySession>,
+    pub overrides: Vec<CrossMarginTradingDayOverride>,
+}
+
+impl CrossMarginTradingSchedule {
+    pub fn is_open(&self, now: DateTimeAsMicroseconds) -> bool {
+        let now = now.to_chrono_utc();
+        let date = now.date_naive();
+        let minute = now.hour() * 60 + now.minute();
+
+        if let Some(day_override) = self.overrides.iter().find(|x| x.is_date(&date)) {
+            return day_override.ranges.iter().any(|x| x.contains(minute));
+        }
+
+        let day_of_week = now.weekday().num_days_from_monday();
+
+        return self
+            .sessions
+            .iter()
+            .any(|x| x.day_of_week == day_of_week && x.range.contains(minute));
+    }
+}
+
+impl CrossMarginTradingDayOverride {
+    fn is_date(&self, date: &NaiveDate) -> bool {
+        return date.year() == self.year && date.month() == self.month && date.day() == self.day;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-05 is Friday
+    fn date(day: u32, hour: u32, minute: u32) -> DateTimeAsMicroseconds {
+        let date = NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap();
+
+        DateTimeAsMicroseconds::new(date.and_utc().timestamp_micros())
+    }
+
+    fn create_schedule() -> CrossMarginTradingSchedule {
+        CrossMarginTradingSchedule {
+            instrument_id: "EURUSD".to_string(),
+            sessions: (0..5)
+                .map(|day_of_week| CrossMarginWeeklySession {
+                    day_of_week,
+                    range: CrossMarginTradingTimeRange {
+                        from_minute: 60,
+                        to_minute: 22 * 60,
+                    },
+                })
+                .collect(),
+            overrides: vec![
+                CrossMarginTradingDayOverride {
+                    year: 2024,
+                    month: 1,
+                    day: 1,
+                    ranges: vec![],
+                },
+                CrossMarginTradingDayOverride {
+                    year: 2024,
+                    month: 1,
+                    day: 2,
+                    ranges: vec![CrossMarginTradingTimeRange {
+                        from_minute: 60,
+                        to_minute: 12 * 60,
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_weekly_sessions() {
+        let schedule = create_schedule();
+
+        assert!(schedule.is_open(date(5, 10, 0)));
+        assert!(schedule.is_open(date(5, 1, 0)));
+        assert!(!schedule.is_open(date(5, 0, 59)));
+        assert!(!schedule.is_open(date(5, 22, 0)));
+        assert!(!schedule.is_open(date(6, 10, 0)));
+        assert!(!schedule.is_open(date(7, 10, 0)));
+    }
+
+    #[test]
+    fn test_holiday_and_half_day() {
+        let schedule = create_schedule();
+
+        assert!(!schedule.is_open(date(1, 10, 0)));
+        assert!(schedule.is_open(date(2, 11, 59)));
+        assert!(!schedule.is_open(date(2, 12, 0)));
+        assert!(schedule.is_open(date(3, 12, 0)));
+    }
+}
@@ -1,12 +1,25 @@
 mod prices;
 mod accounts;
+mod commissions;
+mod instruments;
 mod positions;
+mod trading_groups;
+mod exposure;
+mod events;
 mod cache_aggregate;
 mod flows;
+// Test accounts and positions, also used by the sdk tests.
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
 pub use accounts::*;
+pub use commissions::*;
+pub use instruments::*;
 pub use prices::*;
 pub use positions::*;
+pub use trading_groups::*;
+pub use exposure::*;
+pub use events::*;
 pub use cache_aggregate::*;
 pub use flows::*;
 
@@ -15,6 +28,12 @@ pub enum CrossMarginError{
     AccountNotFound,
     PositionNotFound,
     NotEnoughBalance,
+    TradingNotAllowed(CrossMarginAccountTradingState),
+    InvalidOrder(Vec<CrossMarginOrderViolation>),
+    MarketClosed(String),
     AssetNotFound(String),
+    TradingGroupNotFound(String),
     MultiError(Vec<String>),
+    VersionMismatch { expected: u64, actual: u64 },
+    ShardFailed(Vec<String>),
 }
\ No newline at end of file
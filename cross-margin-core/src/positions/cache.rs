@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use super::{
     CrossMarginPosition, CrossMarginPositionsCacheIndexes,
@@ -72,7 +72,7 @@ impl<T: CrossMarginPosition> PositionsCache<T> {
     pub fn query_and_select_remove<F>(
         &mut self,
         query: CrossMarginPositionsCacheQueryBuilder,
-        is_remove: impl Fn(&T) -> Option<F>,
+        mut is_remove: impl FnMut(&T) -> Option<F>,
     ) -> Vec<(T, F)> {
         let indexes = self.indexes.query(&query);
 
@@ -124,6 +124,37 @@ impl<T: CrossMarginPosition> PositionsCache<T> {
         return result;
     }
 
+    pub fn update_all_positions<F>(
+        &mut self,
+        update_command: impl Fn(&mut T) -> Option<F>,
+    ) -> Vec<F> {
+        let mut result = vec![];
+        for position in self.positions.values_mut() {
+            if let Some(update_result) = update_command(position) {
+                result.push(update_result);
+            };
+        }
+
+        return result;
+    }
+
+    pub fn update_positions_by_ids<F>(
+        &mut self,
+        ids: impl IntoIterator<Item = Arc<String>>,
+        update_command: impl Fn(&mut T) -> Option<F>,
+    ) -> Vec<F> {
+        let mut result = vec![];
+        for id in ids {
+            if let Some(position) = self.positions.get_mut(id.as_ref()) {
+                if let Some(update_result) = update_command(position) {
+                    result.push(update_result);
+                }
+            }
+        }
+
+        return result;
+    }
+
     pub fn bulk_update_positions<F>(
         &mut self,
         query: CrossMarginPositionsOneOfBulkQueryBuilder,
@@ -1,4 +1,5 @@
 use serde::{de::DeserializeOwned, Serialize};
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
 
 use crate::CrossMarginBidAsk;
 
@@ -9,10 +10,19 @@ pub trait CrossMarginActivePosition:
 {
     fn get_pl(&self) -> f64;
     fn update_pl(&mut self, pl: f64);
+    fn get_swaps(&self) -> f64;
+    fn add_swap(&mut self, amount: f64);
     fn get_open_price(&self) -> f64;
+    // Swaps are charged for rollovers after the open date only.
+    fn get_open_date(&self) -> DateTimeAsMicroseconds;
     fn get_active_price(&self) -> f64;
     fn get_profit_price(&self) -> f64;
     fn get_margin_price(&self) -> f64;
     fn update_profit_price(&mut self, bid_ask: CrossMarginBidAsk, price: f64);
     fn update_asset_price(&mut self, bid_ask: CrossMarginBidAsk, price: f64);
+    fn update_sl(&mut self, sl_price: Option<f64>, sl_profit: Option<f64>);
+    fn update_tp(&mut self, tp_price: Option<f64>, tp_profit: Option<f64>);
+    // Incremented on sl/tp and swap changes, price refreshes do not change it.
+    fn get_version(&self) -> u64;
+    fn set_version(&mut self, version: u64);
 }
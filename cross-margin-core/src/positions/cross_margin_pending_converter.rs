@@ -0,0 +1,181 @@
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use super::{CrossMarginActivePosition, CrossMarginPendingPosition};
+
+// Builds the active position for an executed pending order. Rates, SL and TP are set by the engine.
+pub trait CrossMarginPendingPositionConverter<PP, AP>: Send + Sync
+where
+    PP: CrossMarginPendingPosition,
+    AP: CrossMarginActivePosition,
+{
+    fn convert(&self, order: &PP, open_price: f64, open_date: DateTimeAsMicroseconds) -> AP;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{
+        test_utils::{
+            bid_ask, create_caches, eurusd, TestAccount, TestActivePosition, TestPendingPosition,
+        },
+        CrossMarginCacheInstrument, CrossMarginError, CrossMarginPendingPositionType,
+    };
+
+    use super::CrossMarginPendingPositionConverter;
+
+    struct TestConverter;
+
+    impl CrossMarginPendingPositionConverter<TestPendingPosition, TestActivePosition>
+        for TestConverter
+    {
+        fn convert(
+            &self,
+            order: &TestPendingPosition,
+            open_price: f64,
+            _: DateTimeAsMicroseconds,
+        ) -> TestActivePosition {
+            let instrument = CrossMarginCacheInstrument {
+                id: order.instrument_id.clone(),
+                base: order.base.clone(),
+                quote: order.quote.clone(),
+            };
+
+            return TestActivePosition::new(
+                &order.id,
+                &order.account_id,
+                &instrument,
+                order.side.clone(),
+                order.lots_amount,
+                open_price,
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_executed_order_is_opened_on_trigger_tick() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.pending_converter = Some(Arc::new(TestConverter));
+
+        let mut order = TestPendingPosition::new(
+            "order",
+            "acc",
+            &instrument,
+            CrossMarginPendingPositionType::BuyLimit,
+            100.0,
+            1.05,
+        );
+        order.sl_price = Some(0.9);
+        caches.add_pending_position(order, "place").await.unwrap();
+
+        let result = caches
+            .handle_bid_ask(bid_ask(&instrument, 1.0, 1.0002), "tick")
+            .await;
+
+        assert_eq!(result.executed_orders.len(), 1);
+        assert_eq!(result.opened_positions.len(), 1);
+
+        let position = caches.active_positions_cache.get_by_id("order").unwrap();
+        assert_eq!(position.open_price, 1.0002);
+        assert_eq!(position.active_price, 1.0);
+        assert_eq!(position.sl_price, Some(0.9));
+        assert!((position.pl - (1.0 - 1.0002) * 100.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_order_touched_mid_batch_opens_at_touching_price() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.pending_converter = Some(Arc::new(TestConverter));
+
+        let order = TestPendingPosition::new(
+            "order",
+            "acc",
+            &instrument,
+            CrossMarginPendingPositionType::BuyLimit,
+            100.0,
+            1.05,
+        );
+        caches.add_pending_position(order, "place").await.unwrap();
+
+        // only the skipped tick reaches the limit price
+        let result = caches
+            .handle_bid_ask_batch(
+                vec![
+                    bid_ask(&instrument, 1.04, 1.0402),
+                    bid_ask(&instrument, 1.1, 1.1002),
+                ],
+                "batch",
+            )
+            .await;
+
+        assert_eq!(result.executed_orders.len(), 1);
+        assert!(caches.pending_positions_cache.get_by_id("order").is_none());
+
+        let position = caches.active_positions_cache.get_by_id("order").unwrap();
+        assert_eq!(position.open_price, 1.0402);
+        assert_eq!(position.active_price, 1.1);
+    }
+
+    // Positions on an instrument without prices can't be rated.
+    struct UnpricedConverter;
+
+    impl CrossMarginPendingPositionConverter<TestPendingPosition, TestActivePosition>
+        for UnpricedConverter
+    {
+        fn convert(
+            &self,
+            order: &TestPendingPosition,
+            open_price: f64,
+            _: DateTimeAsMicroseconds,
+        ) -> TestActivePosition {
+            let instrument = CrossMarginCacheInstrument {
+                id: "XAUUSD".to_string(),
+                base: "XAU".to_string(),
+                quote: "USD".to_string(),
+            };
+
+            return TestActivePosition::new(
+                &order.id,
+                &order.account_id,
+                &instrument,
+                order.side.clone(),
+                order.lots_amount,
+                open_price,
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_open_keeps_order_pending() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.pending_converter = Some(Arc::new(UnpricedConverter));
+
+        let order = TestPendingPosition::new(
+            "order",
+            "acc",
+            &instrument,
+            CrossMarginPendingPositionType::BuyLimit,
+            100.0,
+            1.05,
+        );
+        caches.add_pending_position(order, "place").await.unwrap();
+
+        let result = caches
+            .handle_bid_ask(bid_ask(&instrument, 1.0, 1.0002), "tick")
+            .await;
+
+        assert!(result.executed_orders.is_empty());
+        assert!(result.failed_orders.is_empty());
+        assert!(matches!(
+            result.failed_opens.as_slice(),
+            [(_, CrossMarginError::AssetNotFound(_))]
+        ));
+        assert!(caches.pending_positions_cache.get_by_id("order").is_some());
+        assert!(caches.active_positions_cache.get_by_id("order").is_none());
+    }
+}
@@ -15,9 +15,34 @@ pub enum CrossMarginPendingPositionExecuteReason{
     Cancelled = 0,
     Rejected = 1,
     Executed = 2,
+    CloseOnly = 3,
+    TradingDisabled = 4,
+    NotEnoughMargin = 5,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CrossMarginPendingTriggerOutcome {
+    Kept = 0,
+    Rejected = 1,
+    PartiallyFilled = 2,
+}
+
+// Order which price condition was met without enough free margin to open it in full.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginPendingTriggerEvent {
+    pub order_id: String,
+    pub account_id: String,
+    pub instrument_id: String,
+    pub trigger_price: f64,
+    pub requested_lots: f64,
+    pub filled_lots: f64,
+    pub outcome: CrossMarginPendingTriggerOutcome,
 }
 
 pub trait CrossMarginPendingPosition: CrossMarginPosition + Serialize + DeserializeOwned + Clone{
     fn get_desired_price(&self) -> f64;
     fn get_order_type(&self) -> CrossMarginPendingPositionType;
+    // Resizes the order for a partial fill, returns false when the order can't be resized and
+    // the partial fill is rejected instead.
+    fn update_lots_amount(&mut self, lots_amount: f64) -> bool;
 }
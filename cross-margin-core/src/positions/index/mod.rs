@@ -1,7 +1,9 @@
 mod dto;
 mod positions_cache_index;
 mod positions_cache_query_builder;
+mod price_dependency_index;
 
 pub use dto::*;
 pub use positions_cache_index::*;
-pub use positions_cache_query_builder::*;
\ No newline at end of file
+pub use positions_cache_query_builder::*;
+pub use price_dependency_index::*;
\ No newline at end of file
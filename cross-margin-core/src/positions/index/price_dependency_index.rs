@@ -0,0 +1,120 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+#[derive(Debug, Clone)]
+struct CrossMarginPositionPriceDependencies {
+    account_id: String,
+    sources: Vec<String>,
+}
+
+// Maps source instruments (including cross legs) to the positions and accounts which prices depend on them.
+pub struct CrossMarginPriceDependencyIndex {
+    // SOURCE_ID - [POSITION_ID]
+    sources: HashMap<String, HashSet<Arc<String>>>,
+    // POSITION_ID - DEPENDENCIES
+    positions: HashMap<Arc<String>, CrossMarginPositionPriceDependencies>,
+}
+
+impl CrossMarginPriceDependencyIndex {
+    pub fn new() -> Self {
+        Self {
+            sources: HashMap::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn add_position(&mut self, position_id: &str, account_id: &str, sources: Vec<String>) {
+        self.remove_position(position_id);
+
+        let position_id = Arc::new(position_id.to_string());
+
+        for source in sources.iter() {
+            self.sources
+                .entry(source.clone())
+                .or_insert_with(HashSet::new)
+                .insert(position_id.clone());
+        }
+
+        self.positions.insert(
+            position_id,
+            CrossMarginPositionPriceDependencies {
+                account_id: account_id.to_string(),
+                sources,
+            },
+        );
+    }
+
+    pub fn remove_position(&mut self, position_id: &str) {
+        let Some(dependencies) = self.positions.remove(&position_id.to_string()) else {
+            return;
+        };
+
+        for source in dependencies.sources.iter() {
+            if let Some(positions) = self.sources.get_mut(source) {
+                positions.remove(&position_id.to_string());
+
+                if positions.is_empty() {
+                    self.sources.remove(source);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sources.clear();
+        self.positions.clear();
+    }
+
+    pub fn get_positions(&self, source_id: &str) -> HashSet<Arc<String>> {
+        return self.sources.get(source_id).cloned().unwrap_or_default();
+    }
+
+    pub fn get_accounts(&self, source_id: &str) -> HashSet<String> {
+        let Some(positions) = self.sources.get(source_id) else {
+            return HashSet::new();
+        };
+
+        return positions
+            .iter()
+            .filter_map(|x| self.positions.get(x))
+            .map(|x| x.account_id.clone())
+            .collect();
+    }
+
+    pub fn get_sources(&self, position_id: &str) -> Option<&Vec<String>> {
+        return self
+            .positions
+            .get(&position_id.to_string())
+            .map(|x| &x.sources);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies_are_replaced_and_removed() {
+        let mut index = CrossMarginPriceDependencyIndex::new();
+        index.add_position("1", "a", vec!["EURJPY".to_string(), "USDJPY".to_string()]);
+        index.add_position("2", "b", vec!["EURUSD".to_string()]);
+
+        assert_eq!(index.get_positions("USDJPY").len(), 1);
+        assert_eq!(
+            index.get_accounts("EURUSD"),
+            HashSet::from(["b".to_string()])
+        );
+
+        index.add_position("1", "a", vec!["EURJPY".to_string(), "EURUSD".to_string()]);
+        assert!(index.get_positions("USDJPY").is_empty());
+        assert_eq!(index.get_positions("EURUSD").len(), 2);
+
+        index.remove_position("2");
+        assert_eq!(
+            index.get_accounts("EURUSD"),
+            HashSet::from(["a".to_string()])
+        );
+    }
+}
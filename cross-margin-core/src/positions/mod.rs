@@ -4,11 +4,17 @@ mod index;
 mod cross_margin_active_position;
 mod cross_margin_position;
 mod cross_margin_pending_position;
+mod cross_margin_pending_converter;
 mod cross_margin_closed_position;
+mod position_settlement;
+mod slippage_stats;
 
 pub use cache::*;
 pub use index::*;
 pub use cross_margin_active_position::*;
 pub use cross_margin_position::*;
 pub use cross_margin_pending_position::*;
-pub use cross_margin_closed_position::*;
\ No newline at end of file
+pub use cross_margin_pending_converter::*;
+pub use cross_margin_closed_position::*;
+pub use position_settlement::*;
+pub use slippage_stats::*;
\ No newline at end of file
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+use crate::commissions::CrossMarginCommissionBreakdown;
+
+use super::CrossMarginActivePosition;
+
+// SL/TP fill, slippage is positive when the execution price is better than requested for the trader.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginStopExecution {
+    pub requested_price: f64,
+    pub market_price: f64,
+    pub execution_price: f64,
+    pub slippage: f64,
+    // PnL difference between the execution and the market price, in account currency.
+    pub pl_adjustment: f64,
+}
+
+// Amounts realized to the account balance when position is closed, in account currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginPositionSettlement {
+    // Price the position is closed at, the stop execution price when it is set.
+    pub close_price: f64,
+    pub pl: f64,
+    pub swaps: f64,
+    pub commission: Option<CrossMarginCommissionBreakdown>,
+    pub stop_execution: Option<CrossMarginStopExecution>,
+}
+
+impl CrossMarginPositionSettlement {
+    pub fn new(
+        position: &impl CrossMarginActivePosition,
+        commission: Option<CrossMarginCommissionBreakdown>,
+    ) -> Self {
+        Self {
+            close_price: position.get_active_price(),
+            pl: position.get_pl(),
+            swaps: position.get_swaps(),
+            commission,
+            stop_execution: None,
+        }
+    }
+
+    // The pl adjustment is applied to the position before the settlement is built.
+    pub fn with_stop_execution(mut self, stop_execution: Option<CrossMarginStopExecution>) -> Self {
+        if let Some(stop_execution) = &stop_execution {
+            self.close_price = stop_execution.execution_price;
+        }
+
+        self.stop_execution = stop_execution;
+        return self;
+    }
+
+    pub fn get_commission(&self) -> f64 {
+        return self
+            .commission
+            .as_ref()
+            .map(|x| x.charged_fee)
+            .unwrap_or(0.0);
+    }
+
+    pub fn get_total(&self) -> f64 {
+        return self.pl + self.swaps - self.get_commission();
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::CrossMarginCloseReason;
+
+use super::CrossMarginStopExecution;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrossMarginSlippageStat {
+    pub sl_count: u64,
+    pub tp_count: u64,
+    // Fills with a non zero slippage.
+    pub slipped_count: u64,
+    pub total_slippage: f64,
+    pub max_adverse_slippage: f64,
+}
+
+// SL/TP slippage by instrument, in price units.
+pub struct CrossMarginSlippageStats {
+    instruments: HashMap<String, CrossMarginSlippageStat>,
+}
+
+impl CrossMarginSlippageStats {
+    pub fn new() -> Self {
+        Self {
+            instruments: HashMap::new(),
+        }
+    }
+
+    pub fn from_stats(instruments: HashMap<String, CrossMarginSlippageStat>) -> Self {
+        Self { instruments }
+    }
+
+    pub fn record(
+        &mut self,
+        instrument_id: &str,
+        close_reason: &CrossMarginCloseReason,
+        execution: &CrossMarginStopExecution,
+    ) {
+        let stat = self
+            .instruments
+            .entry(instrument_id.to_string())
+            .or_default();
+
+        match close_reason {
+            CrossMarginCloseReason::Sl => stat.sl_count += 1,
+            CrossMarginCloseReason::Tp => stat.tp_count += 1,
+            _ => {}
+        }
+
+        if execution.slippage != 0.0 {
+            stat.slipped_count += 1;
+        }
+
+        stat.total_slippage += execution.slippage;
+        stat.max_adverse_slippage = stat.max_adverse_slippage.max(-execution.slippage);
+    }
+
+    pub fn get(&self, instrument_id: &str) -> Option<&CrossMarginSlippageStat> {
+        return self.instruments.get(instrument_id);
+    }
+
+    pub fn get_all(&self) -> &HashMap<String, CrossMarginSlippageStat> {
+        return &self.instruments;
+    }
+}
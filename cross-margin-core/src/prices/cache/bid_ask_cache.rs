@@ -1,15 +1,34 @@
-use std::{collections::HashMap, sync::Arc};
-
-use crate::prices::{cross::{CrossPriceEngine, SourceInstrument}, dto::CrossMarginBidAsk};
-
-
-pub struct CrossMarginBidAskCache {
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::prices::{
+    cross::{CrossPriceEngine, SourceInstrument},
+    dto::{CrossMarginBidAsk, CrossMarginCoalescedBidAsk},
+};
+
+#[derive(Clone)]
+struct CrossMarginBidAskCacheState {
     prices: HashMap<String, Arc<CrossMarginBidAsk>>,
     base_quote_index: HashMap<String, HashMap<String, Arc<CrossMarginBidAsk>>>,
     quote_base_index: HashMap<String, HashMap<String, Arc<CrossMarginBidAsk>>>,
     cross_ending: CrossPriceEngine,
 }
 
+// Cloning copies the prices, handles made by share see each other updates.
+pub struct CrossMarginBidAskCache {
+    state: Arc<RwLock<CrossMarginBidAskCacheState>>,
+}
+
+impl Clone for CrossMarginBidAskCache {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(self.read().clone())),
+        }
+    }
+}
+
 impl CrossMarginBidAskCache {
     pub fn new(
         request_crosses: impl IntoIterator<Item = (String, String)>,
@@ -38,47 +57,90 @@ impl CrossMarginBidAskCache {
         }
 
         return CrossMarginBidAskCache {
-            prices,
-            base_quote_index,
-            quote_base_index,
-            cross_ending: crosses,
+            state: Arc::new(RwLock::new(CrossMarginBidAskCacheState {
+                prices,
+                base_quote_index,
+                quote_base_index,
+                cross_ending: crosses,
+            })),
         };
     }
 
+    // Same prices for several engines, e.g. shards, updated once per tick.
+    pub fn share(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, CrossMarginBidAskCacheState> {
+        return self.state.read().unwrap();
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, CrossMarginBidAskCacheState> {
+        return self.state.write().unwrap();
+    }
+
     pub fn handle_new(&mut self, bid_ask: CrossMarginBidAsk) {
-        let bid_ask = Arc::new(bid_ask);
-        self.prices
-            .insert(bid_ask.asset_pair.clone(), bid_ask.clone());
+        self.write().handle_new(bid_ask);
+    }
 
-        let base_quote = self
-            .base_quote_index
-            .entry(bid_ask.base.clone())
-            .or_insert_with(HashMap::new);
-        base_quote.insert(bid_ask.quote.clone(), bid_ask.clone());
+    // Source price update which is also propagated to every cross built on it.
+    pub fn handle_new_with_crosses(&mut self, bid_ask: CrossMarginBidAsk) {
+        let mut state = self.write();
+        state.cross_ending.update_source(&bid_ask);
+        state.handle_new(bid_ask);
+    }
 
-        let quote_base = self
-            .quote_base_index
-            .entry(bid_ask.quote.clone())
-            .or_insert_with(HashMap::new);
-        quote_base.insert(bid_ask.base.clone(), bid_ask.clone());
+    // Applies the latest price of each pair, returns true when a new source pair appeared.
+    pub fn handle_coalesced(&mut self, prices: &[CrossMarginCoalescedBidAsk]) -> bool {
+        let mut state = self.write();
+        let mut is_new_source = false;
+
+        for coalesced in prices {
+            is_new_source |= !state.prices.contains_key(&coalesced.latest.asset_pair);
+            state.cross_ending.update_source(&coalesced.latest);
+            state.handle_new(coalesced.latest.clone());
+        }
+
+        return is_new_source;
+    }
+
+    pub fn get_all(&self) -> Vec<Arc<CrossMarginBidAsk>> {
+        return self.read().prices.values().cloned().collect();
     }
 
     pub fn get_by_id(&self, id: &str) -> Option<Arc<CrossMarginBidAsk>> {
-        self.prices.get(id).cloned()
+        self.read().prices.get(id).cloned()
     }
 
     pub fn get_base_quote(&self, base: &str, quote: &str) -> Option<Arc<CrossMarginBidAsk>> {
-        self.base_quote_index
-            .get(base)
-            .and_then(|x| x.get(quote))
-            .cloned()
+        self.read().get_base_quote(base, quote)
     }
 
     pub fn get_quote_base(&self, quote: &str, base: &str) -> Option<Arc<CrossMarginBidAsk>> {
-        self.quote_base_index
-            .get(quote)
-            .and_then(|x| x.get(base))
-            .cloned()
+        self.read().get_quote_base(quote, base)
+    }
+
+    // Source instruments ids which get_price is calculated from, empty when the price is not available.
+    pub fn get_price_sources(&self, base: &str, quote: &str) -> Vec<String> {
+        if base == quote {
+            return vec![];
+        }
+
+        let state = self.read();
+
+        if let Some(price) = state
+            .get_base_quote(base, quote)
+            .or_else(|| state.get_quote_base(base, quote))
+        {
+            return vec![price.asset_pair.clone()];
+        }
+
+        return state
+            .cross_ending
+            .get_cross_sources(base, quote)
+            .unwrap_or_default();
     }
 
     pub fn get_price(&self, base: &str, quote: &str) -> Option<Arc<CrossMarginBidAsk>> {
@@ -86,13 +148,16 @@ impl CrossMarginBidAskCache {
             return Some(Arc::new(CrossMarginBidAsk::create_blank(base)));
         }
 
-        let result = self.get_base_quote(base, quote).or_else(|| {
-            self.get_quote_base(base, quote)
+        let state = self.read();
+
+        let result = state.get_base_quote(base, quote).or_else(|| {
+            state
+                .get_quote_base(base, quote)
                 .map(|x| Arc::new(x.reverse()))
         });
 
         if let None = result {
-            let cross = self.cross_ending.get_cross(base, quote)?;
+            let cross = state.cross_ending.get_cross(base, quote)?;
             return Some(Arc::new(cross.get_bid_ask()));
         }
 
@@ -100,4 +165,36 @@ impl CrossMarginBidAskCache {
     }
 }
 
+impl CrossMarginBidAskCacheState {
+    fn handle_new(&mut self, bid_ask: CrossMarginBidAsk) {
+        let bid_ask = Arc::new(bid_ask);
+        self.prices
+            .insert(bid_ask.asset_pair.clone(), bid_ask.clone());
 
+        let base_quote = self
+            .base_quote_index
+            .entry(bid_ask.base.clone())
+            .or_insert_with(HashMap::new);
+        base_quote.insert(bid_ask.quote.clone(), bid_ask.clone());
+
+        let quote_base = self
+            .quote_base_index
+            .entry(bid_ask.quote.clone())
+            .or_insert_with(HashMap::new);
+        quote_base.insert(bid_ask.base.clone(), bid_ask.clone());
+    }
+
+    fn get_base_quote(&self, base: &str, quote: &str) -> Option<Arc<CrossMarginBidAsk>> {
+        self.base_quote_index
+            .get(base)
+            .and_then(|x| x.get(quote))
+            .cloned()
+    }
+
+    fn get_quote_base(&self, quote: &str, base: &str) -> Option<Arc<CrossMarginBidAsk>> {
+        self.quote_base_index
+            .get(quote)
+            .and_then(|x| x.get(base))
+            .cloned()
+    }
+}
@@ -7,6 +7,7 @@ use super::dto::{
     SourceInstrument,
 };
 
+#[derive(Clone)]
 pub struct CrossPriceEngine {
     //ID - [ENTITY]
     cross_matrix: HashMap<String, CrossInstrument>,
@@ -48,14 +49,26 @@ impl CrossPriceEngine {
                     left: left.active_price,
                     right: right.active_price,
                 }),
-                false => CrossPairType::DiffSide(CrossPairsDiffSide {
-                    right: if left.quote == right.base {
-                        BidAskReverseType::Direct(right.active_price)
-                    } else {
-                        BidAskReverseType::Reversed(right.active_price)
-                    },
-                    left: BidAskReverseType::Direct(left.active_price),
-                }),
+                // legs are chained as base - shared currency - quote, reversed where quoted the other way
+                false => {
+                    let shared = match left.base == base {
+                        true => left.quote.clone(),
+                        false => left.base.clone(),
+                    };
+
+                    CrossPairType::DiffSide(CrossPairsDiffSide {
+                        right: if shared == right.base {
+                            BidAskReverseType::Direct(right.active_price)
+                        } else {
+                            BidAskReverseType::Reversed(right.active_price)
+                        },
+                        left: if left.base == base {
+                            BidAskReverseType::Direct(left.active_price)
+                        } else {
+                            BidAskReverseType::Reversed(left.active_price)
+                        },
+                    })
+                }
             };
 
             let id = format!("{}{}", base, quote);
@@ -100,6 +113,12 @@ impl CrossPriceEngine {
         }
     }
 
+    pub fn update_source(&mut self, new_price: &CrossMarginBidAsk) {
+        for instrument in self.cross_matrix.values_mut() {
+            instrument.prices.update_source(new_price);
+        }
+    }
+
     pub fn get_cross(&self, base: &str, quote: &str) -> Option<&CrossInstrument> {
         let id = format!("{}-{}", base, quote);
         let id = self.mapping.get(&id)?;
@@ -107,6 +126,12 @@ impl CrossPriceEngine {
         self.cross_matrix.get(id.as_str())
     }
 
+    pub fn get_cross_sources(&self, base: &str, quote: &str) -> Option<Vec<String>> {
+        let cross = self.get_cross(base, quote)?;
+
+        return Some(cross.prices.get_sources());
+    }
+
     fn find_pair(
         base: &str,
         quote: &str,
@@ -234,4 +259,25 @@ mod tests {
         let usd_jpy = engine.get_cross("USD", "JPY");
         assert!(usd_jpy.is_none());
     }
+
+    #[test]
+    fn test_diff_side_cross_from_quote_currency() {
+        let request_crosses = vec![("JPY".to_string(), "EUR".to_string())];
+        let engine = CrossPriceEngine::new(request_crosses, create_test_instruments());
+
+        // JPY-USD is reversed USDJPY, USD-EUR is reversed EURUSD
+        let bid_ask = engine.get_cross("JPY", "EUR").unwrap().get_bid_ask();
+        assert!((bid_ask.bid - 1.0 / (111.0 * 1.2)).abs() < 1e-12);
+        assert!((bid_ask.ask - 1.0 / (110.0 * 1.1)).abs() < 1e-12);
+
+        let bid_ask = CrossPriceEngine::new(
+            vec![("EUR".to_string(), "JPY".to_string())],
+            create_test_instruments(),
+        )
+        .get_cross("EUR", "JPY")
+        .unwrap()
+        .get_bid_ask();
+        assert!((bid_ask.bid - 1.1 * 110.0).abs() < 1e-9);
+        assert!((bid_ask.ask - 1.2 * 111.0).abs() < 1e-9);
+    }
 }
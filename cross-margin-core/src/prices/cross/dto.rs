@@ -31,6 +31,38 @@ impl CrossPairType {
         }
     }
 
+    // Replaces the leg which is sourced from the same instrument.
+    pub fn update_source(&mut self, price: &CrossMarginBidAsk) {
+        match self {
+            CrossPairType::SameSide(x) => {
+                if x.left.asset_pair == price.asset_pair {
+                    x.left = price.clone();
+                }
+
+                if x.right.asset_pair == price.asset_pair {
+                    x.right = price.clone();
+                }
+            }
+            CrossPairType::DiffSide(x) => {
+                x.left.update_source(price);
+                x.right.update_source(price);
+            }
+        }
+    }
+
+    // Source instruments ids of both legs.
+    pub fn get_sources(&self) -> Vec<String> {
+        match self {
+            CrossPairType::SameSide(x) => {
+                vec![x.left.asset_pair.clone(), x.right.asset_pair.clone()]
+            }
+            CrossPairType::DiffSide(x) => vec![
+                x.left.get_source().asset_pair.clone(),
+                x.right.get_source().asset_pair.clone(),
+            ],
+        }
+    }
+
     pub fn calculate_cross(&self) -> (f64, f64) {
         match self {
             CrossPairType::SameSide(x) => x.calculate_cross(),
@@ -126,6 +158,16 @@ impl BidAskReverseType {
         }
     }
 
+    pub fn update_source(&mut self, price: &CrossMarginBidAsk) {
+        match self {
+            BidAskReverseType::Direct(mt) | BidAskReverseType::Reversed(mt) => {
+                if mt.asset_pair == price.asset_pair {
+                    *mt = price.clone();
+                }
+            }
+        }
+    }
+
     pub fn get_source(&self) -> &CrossMarginBidAsk {
         match self {
             BidAskReverseType::Direct(mt) => mt,
@@ -0,0 +1,99 @@
+use crate::CrossMarginPositionSide;
+
+use super::CrossMarginBidAsk;
+
+// Latest price of an asset pair within a batch with the extremes of the skipped ticks.
+#[derive(Debug, Clone)]
+pub struct CrossMarginCoalescedBidAsk {
+    pub latest: CrossMarginBidAsk,
+    pub min_bid: f64,
+    pub max_bid: f64,
+    pub min_ask: f64,
+    pub max_ask: f64,
+    pub ticks_count: usize,
+}
+
+impl CrossMarginCoalescedBidAsk {
+    pub fn new(bid_ask: CrossMarginBidAsk) -> Self {
+        Self {
+            min_bid: bid_ask.bid,
+            max_bid: bid_ask.bid,
+            min_ask: bid_ask.ask,
+            max_ask: bid_ask.ask,
+            ticks_count: 1,
+            latest: bid_ask,
+        }
+    }
+
+    // Keeps the order in which asset pairs first appear in the batch.
+    pub fn coalesce(prices: Vec<CrossMarginBidAsk>) -> Vec<Self> {
+        let mut result: Vec<Self> = vec![];
+
+        for bid_ask in prices {
+            match result
+                .iter_mut()
+                .find(|x| x.latest.asset_pair == bid_ask.asset_pair)
+            {
+                Some(coalesced) => coalesced.update(bid_ask),
+                None => result.push(Self::new(bid_ask)),
+            }
+        }
+
+        return result;
+    }
+
+    pub fn update(&mut self, bid_ask: CrossMarginBidAsk) {
+        self.min_bid = self.min_bid.min(bid_ask.bid);
+        self.max_bid = self.max_bid.max(bid_ask.bid);
+        self.min_ask = self.min_ask.min(bid_ask.ask);
+        self.max_ask = self.max_ask.max(bid_ask.ask);
+        self.ticks_count += 1;
+        self.latest = bid_ask;
+    }
+
+    // Lowest and highest close price seen in the batch.
+    pub fn get_close_price_range(&self, side: &CrossMarginPositionSide) -> (f64, f64) {
+        match side {
+            CrossMarginPositionSide::Buy => (self.min_bid, self.max_bid),
+            CrossMarginPositionSide::Sell => (self.min_ask, self.max_ask),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use super::*;
+
+    fn tick(asset_pair: &str, bid: f64) -> CrossMarginBidAsk {
+        return CrossMarginBidAsk {
+            asset_pair: asset_pair.to_string(),
+            bid,
+            ask: bid + 0.0002,
+            base: asset_pair[..3].to_string(),
+            quote: asset_pair[3..].to_string(),
+            date: DateTimeAsMicroseconds::now(),
+        };
+    }
+
+    #[test]
+    fn test_keeps_latest_price_and_extremes() {
+        let coalesced = CrossMarginCoalescedBidAsk::coalesce(vec![
+            tick("EURUSD", 1.1),
+            tick("GBPUSD", 1.3),
+            tick("EURUSD", 1.05),
+            tick("EURUSD", 1.12),
+            tick("EURUSD", 1.08),
+        ]);
+
+        assert_eq!(coalesced.len(), 2);
+        assert_eq!(coalesced[0].latest.bid, 1.08);
+        assert_eq!(coalesced[0].ticks_count, 4);
+        assert_eq!(
+            coalesced[0].get_close_price_range(&CrossMarginPositionSide::Buy),
+            (1.05, 1.12)
+        );
+        assert_eq!(coalesced[1].ticks_count, 1);
+    }
+}
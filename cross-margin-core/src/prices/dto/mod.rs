@@ -1,7 +1,9 @@
 mod bid_ask;
 mod position_side;
 mod close_reason;
+mod coalesced_bid_ask;
 
 pub use bid_ask::*;
 pub use position_side::*;
-pub use close_reason::*;
\ No newline at end of file
+pub use close_reason::*;
+pub use coalesced_bid_ask::*;
\ No newline at end of file
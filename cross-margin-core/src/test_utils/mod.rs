@@ -0,0 +1,83 @@
+#![allow(dead_code)]
+
+mod test_account;
+mod test_active_position;
+mod test_pending_position;
+
+pub use test_account::*;
+pub use test_active_position::*;
+pub use test_pending_position::*;
+
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{
+    CrossMarginBidAsk, CrossMarginCacheInstrument, CrossMarginCaches, CrossMarginCachesBuilder,
+    CrossMarginHedgeMode, CrossMarginPositionSide, CrossMarginTradingGroup,
+    CrossMarginTradingGroupsCache,
+};
+
+pub type TestCaches = CrossMarginCaches<TestAccount, TestActivePosition, TestPendingPosition>;
+
+pub fn eurusd() -> CrossMarginCacheInstrument {
+    CrossMarginCacheInstrument {
+        id: "EURUSD".to_string(),
+        base: "EUR".to_string(),
+        quote: "USD".to_string(),
+    }
+}
+
+pub fn bid_ask(instrument: &CrossMarginCacheInstrument, bid: f64, ask: f64) -> CrossMarginBidAsk {
+    CrossMarginBidAsk {
+        asset_pair: instrument.id.clone(),
+        bid,
+        ask,
+        base: instrument.base.clone(),
+        quote: instrument.quote.clone(),
+        date: DateTimeAsMicroseconds::from(1_700_000_000_000_000 as i64),
+    }
+}
+
+pub fn default_trading_groups() -> CrossMarginTradingGroupsCache {
+    CrossMarginTradingGroupsCache::new(vec![CrossMarginTradingGroup::new("default")])
+}
+
+pub async fn create_caches(accounts: Vec<TestAccount>) -> TestCaches {
+    let instrument = eurusd();
+
+    CrossMarginCachesBuilder::new(
+        accounts,
+        vec![],
+        vec![],
+        vec![instrument.clone()],
+        vec!["USD".to_string()],
+        vec![bid_ask(&instrument, 1.1, 1.1002)],
+    )
+    .with_trading_groups_cache(default_trading_groups())
+    .build()
+    .await
+    .unwrap()
+}
+
+// Netting account "acc" with a fully offset EURUSD book, its margin is zero at any price.
+pub async fn create_netted_caches(balance: f64) -> TestCaches {
+    let instrument = eurusd();
+    let mut caches = create_caches(vec![TestAccount::new("acc", balance, 100.0)]).await;
+    let mut group = CrossMarginTradingGroup::new("default");
+    group.hedge_mode = CrossMarginHedgeMode::Netting;
+    caches.trading_groups_cache.update_group(group);
+
+    for (id, side) in [
+        ("buy", CrossMarginPositionSide::Buy),
+        ("sell", CrossMarginPositionSide::Sell),
+    ] {
+        caches
+            .add_active_position(
+                TestActivePosition::new(id, "acc", &instrument, side, 100_000.0, 1.1),
+                "open",
+            )
+            .await
+            .unwrap();
+    }
+
+    caches
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{CrossMarginAccount, CrossMarginAccountTradingState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestAccount {
+    pub id: String,
+    pub trader_id: String,
+    pub balance: f64,
+    pub currency: String,
+    pub leverage: Option<f64>,
+    pub stop_out: Option<f64>,
+    pub margin_call: Option<f64>,
+    pub trading_group: String,
+    pub instruments_leverages: HashMap<String, f64>,
+    pub trading_state: CrossMarginAccountTradingState,
+    pub last_process_id: Option<String>,
+    pub version: u64,
+}
+
+impl TestAccount {
+    pub fn new(id: &str, balance: f64, leverage: f64) -> Self {
+        Self {
+            id: id.to_string(),
+            trader_id: format!("trader-{}", id),
+            balance,
+            currency: "USD".to_string(),
+            leverage: Some(leverage),
+            stop_out: Some(50.0),
+            margin_call: None,
+            trading_group: "default".to_string(),
+            instruments_leverages: HashMap::new(),
+            trading_state: CrossMarginAccountTradingState::Active,
+            last_process_id: None,
+            version: 0,
+        }
+    }
+}
+
+impl CrossMarginAccount for TestAccount {
+    fn get_trader_id(&self) -> &str {
+        &self.trader_id
+    }
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn get_stop_out(&self) -> Option<f64> {
+        self.stop_out
+    }
+
+    fn get_margin_call(&self) -> Option<f64> {
+        self.margin_call
+    }
+
+    fn get_balance(&self) -> f64 {
+        self.balance
+    }
+
+    fn get_currency(&self) -> &str {
+        &self.currency
+    }
+
+    fn get_leverage(&self) -> Option<f64> {
+        self.leverage
+    }
+
+    fn get_instruments_leverages(&self) -> &HashMap<String, f64> {
+        &self.instruments_leverages
+    }
+
+    fn update_balance(&mut self, delta: f64) {
+        self.balance += delta;
+    }
+
+    fn get_trading_group(&self) -> &str {
+        &self.trading_group
+    }
+
+    fn update_trading_group(&mut self, new_group: String) {
+        self.trading_group = new_group;
+    }
+
+    fn update_leverage(&mut self, leverage: Option<f64>) {
+        self.leverage = leverage;
+    }
+
+    fn get_trading_state(&self) -> CrossMarginAccountTradingState {
+        self.trading_state
+    }
+
+    fn set_trading_state(&mut self, state: CrossMarginAccountTradingState) {
+        self.trading_state = state;
+    }
+
+    fn track_update(&mut self, process_id: &str, _: DateTimeAsMicroseconds) {
+        self.last_process_id = Some(process_id.to_string());
+    }
+
+    fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use service_sdk::rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{
+    CrossMarginActivePosition, CrossMarginBidAsk, CrossMarginCacheIndexGenerator,
+    CrossMarginCacheInstrument, CrossMarginPosition, CrossMarginPositionSide,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestActivePosition {
+    pub id: String,
+    pub trader_id: String,
+    pub account_id: String,
+    pub instrument_id: String,
+    pub base: String,
+    pub quote: String,
+    pub collateral: String,
+    pub side: CrossMarginPositionSide,
+    pub lots_size: f64,
+    pub lots_amount: f64,
+    pub open_price: f64,
+    pub open_date: DateTimeAsMicroseconds,
+    pub active_price: f64,
+    pub profit_price: f64,
+    pub margin_price: f64,
+    pub pl: f64,
+    pub swaps: f64,
+    pub sl_price: Option<f64>,
+    pub sl_profit: Option<f64>,
+    pub tp_price: Option<f64>,
+    pub tp_profit: Option<f64>,
+    pub version: u64,
+}
+
+impl TestActivePosition {
+    pub fn new(
+        id: &str,
+        account_id: &str,
+        instrument: &CrossMarginCacheInstrument,
+        side: CrossMarginPositionSide,
+        lots_amount: f64,
+        open_price: f64,
+    ) -> Self {
+        Self {
+            id: id.to_string(),
+            trader_id: format!("trader-{}", account_id),
+            account_id: account_id.to_string(),
+            instrument_id: instrument.id.clone(),
+            base: instrument.base.clone(),
+            quote: instrument.quote.clone(),
+            collateral: "USD".to_string(),
+            side,
+            lots_size: 1.0,
+            lots_amount,
+            open_price,
+            open_date: DateTimeAsMicroseconds::new(0),
+            active_price: open_price,
+            profit_price: 1.0,
+            margin_price: 1.0,
+            pl: 0.0,
+            swaps: 0.0,
+            sl_price: None,
+            sl_profit: None,
+            tp_price: None,
+            tp_profit: None,
+            version: 0,
+        }
+    }
+}
+
+impl CrossMarginCacheIndexGenerator for TestActivePosition {
+    fn get_id_index(&self) -> String {
+        self.id.clone()
+    }
+
+    fn get_base_index(&self) -> Option<String> {
+        Some(self.base.clone())
+    }
+
+    fn get_quote_index(&self) -> Option<String> {
+        Some(self.quote.clone())
+    }
+
+    fn get_collateral_index(&self) -> Option<String> {
+        Some(self.collateral.clone())
+    }
+
+    fn get_client_identification_index(&self) -> Option<String> {
+        Some(self.trader_id.clone())
+    }
+
+    fn get_account_identification_index(&self) -> Option<String> {
+        Some(self.account_id.clone())
+    }
+}
+
+impl CrossMarginPosition for TestActivePosition {
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn get_trader_id(&self) -> &str {
+        &self.trader_id
+    }
+
+    fn get_account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    fn get_base(&self) -> &str {
+        &self.base
+    }
+
+    fn get_quote(&self) -> &str {
+        &self.quote
+    }
+
+    fn get_instrument_id(&self) -> &str {
+        &self.instrument_id
+    }
+
+    fn get_collateral(&self) -> &str {
+        &self.collateral
+    }
+
+    fn get_side(&self) -> &CrossMarginPositionSide {
+        &self.side
+    }
+
+    fn get_lots_size(&self) -> f64 {
+        self.lots_size
+    }
+
+    fn get_lots_amount(&self) -> f64 {
+        self.lots_amount
+    }
+
+    fn get_sl_price(&self) -> Option<f64> {
+        self.sl_price
+    }
+
+    fn get_sl_profit(&self) -> Option<f64> {
+        self.sl_profit
+    }
+
+    fn get_tp_price(&self) -> Option<f64> {
+        self.tp_price
+    }
+
+    fn get_tp_profit(&self) -> Option<f64> {
+        self.tp_profit
+    }
+}
+
+impl CrossMarginActivePosition for TestActivePosition {
+    fn get_pl(&self) -> f64 {
+        self.pl
+    }
+
+    fn update_pl(&mut self, pl: f64) {
+        self.pl = pl;
+    }
+
+    fn get_swaps(&self) -> f64 {
+        self.swaps
+    }
+
+    fn add_swap(&mut self, amount: f64) {
+        self.swaps += amount;
+    }
+
+    fn get_open_price(&self) -> f64 {
+        self.open_price
+    }
+
+    fn get_open_date(&self) -> DateTimeAsMicroseconds {
+        self.open_date
+    }
+
+    fn get_active_price(&self) -> f64 {
+        self.active_price
+    }
+
+    fn get_profit_price(&self) -> f64 {
+        self.profit_price
+    }
+
+    fn get_margin_price(&self) -> f64 {
+        self.margin_price
+    }
+
+    fn update_profit_price(&mut self, _: CrossMarginBidAsk, price: f64) {
+        self.profit_price = price;
+    }
+
+    fn update_asset_price(&mut self, _: CrossMarginBidAsk, price: f64) {
+        self.active_price = price;
+    }
+
+    fn update_sl(&mut self, sl_price: Option<f64>, sl_profit: Option<f64>) {
+        self.sl_price = sl_price;
+        self.sl_profit = sl_profit;
+    }
+
+    fn update_tp(&mut self, tp_price: Option<f64>, tp_profit: Option<f64>) {
+        self.tp_price = tp_price;
+        self.tp_profit = tp_profit;
+    }
+
+    fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CrossMarginCacheIndexGenerator, CrossMarginCacheInstrument, CrossMarginPendingPosition,
+    CrossMarginPendingPositionType, CrossMarginPosition, CrossMarginPositionSide,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestPendingPosition {
+    pub id: String,
+    pub trader_id: String,
+    pub account_id: String,
+    pub instrument_id: String,
+    pub base: String,
+    pub quote: String,
+    pub collateral: String,
+    pub side: CrossMarginPositionSide,
+    pub order_type: CrossMarginPendingPositionType,
+    pub lots_size: f64,
+    pub lots_amount: f64,
+    pub desired_price: f64,
+    pub sl_price: Option<f64>,
+    pub tp_price: Option<f64>,
+}
+
+impl TestPendingPosition {
+    pub fn new(
+        id: &str,
+        account_id: &str,
+        instrument: &CrossMarginCacheInstrument,
+        order_type: CrossMarginPendingPositionType,
+        lots_amount: f64,
+        desired_price: f64,
+    ) -> Self {
+        let side = match order_type {
+            CrossMarginPendingPositionType::BuyStop | CrossMarginPendingPositionType::BuyLimit => {
+                CrossMarginPositionSide::Buy
+            }
+            CrossMarginPendingPositionType::SellStop
+            | CrossMarginPendingPositionType::SellLimit => CrossMarginPositionSide::Sell,
+        };
+
+        Self {
+            id: id.to_string(),
+            trader_id: format!("trader-{}", account_id),
+            account_id: account_id.to_string(),
+            instrument_id: instrument.id.clone(),
+            base: instrument.base.clone(),
+            quote: instrument.quote.clone(),
+            collateral: "USD".to_string(),
+            side,
+            order_type,
+            lots_size: 1.0,
+            lots_amount,
+            desired_price,
+            sl_price: None,
+            tp_price: None,
+        }
+    }
+}
+
+impl CrossMarginCacheIndexGenerator for TestPendingPosition {
+    fn get_id_index(&self) -> String {
+        self.id.clone()
+    }
+
+    fn get_base_index(&self) -> Option<String> {
+        Some(self.base.clone())
+    }
+
+    fn get_quote_index(&self) -> Option<String> {
+        Some(self.quote.clone())
+    }
+
+    fn get_collateral_index(&self) -> Option<String> {
+        Some(self.collateral.clone())
+    }
+
+    fn get_client_identification_index(&self) -> Option<String> {
+        Some(self.trader_id.clone())
+    }
+
+    fn get_account_identification_index(&self) -> Option<String> {
+        Some(self.account_id.clone())
+    }
+}
+
+impl CrossMarginPosition for TestPendingPosition {
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn get_trader_id(&self) -> &str {
+        &self.trader_id
+    }
+
+    fn get_account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    fn get_base(&self) -> &str {
+        &self.base
+    }
+
+    fn get_quote(&self) -> &str {
+        &self.quote
+    }
+
+    fn get_instrument_id(&self) -> &str {
+        &self.instrument_id
+    }
+
+    fn get_collateral(&self) -> &str {
+        &self.collateral
+    }
+
+    fn get_side(&self) -> &CrossMarginPositionSide {
+        &self.side
+    }
+
+    fn get_lots_size(&self) -> f64 {
+        self.lots_size
+    }
+
+    fn get_lots_amount(&self) -> f64 {
+        self.lots_amount
+    }
+
+    fn get_sl_price(&self) -> Option<f64> {
+        self.sl_price
+    }
+
+    fn get_sl_profit(&self) -> Option<f64> {
+        None
+    }
+
+    fn get_tp_price(&self) -> Option<f64> {
+        self.tp_price
+    }
+
+    fn get_tp_profit(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl CrossMarginPendingPosition for TestPendingPosition {
+    fn get_desired_price(&self) -> f64 {
+        self.desired_price
+    }
+
+    fn get_order_type(&self) -> CrossMarginPendingPositionType {
+        self.order_type.clone()
+    }
+
+    fn update_lots_amount(&mut self, lots_amount: f64) -> bool {
+        self.lots_amount = lots_amount;
+        return true;
+    }
+}
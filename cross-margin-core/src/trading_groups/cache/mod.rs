@@ -0,0 +1,3 @@
+mod trading_groups_cache;
+
+pub use trading_groups_cache::*;
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use crate::{
+    trading_groups::{CrossMarginHedgeMode, CrossMarginTradingGroup},
+    CrossMarginAccount, CrossMarginError,
+};
+
+// Account values override group values, every account must belong to a registered group.
+#[derive(Clone)]
+pub struct CrossMarginTradingGroupsCache {
+    groups: HashMap<String, CrossMarginTradingGroup>,
+    // Bumped on every change, lets dependent caches detect stale values.
+    revision: u64,
+}
+
+impl CrossMarginTradingGroupsCache {
+    pub fn new(groups: Vec<CrossMarginTradingGroup>) -> Self {
+        Self {
+            groups: groups.into_iter().map(|x| (x.id.clone(), x)).collect(),
+            revision: 0,
+        }
+    }
+
+    pub fn get_group(&self, group_id: &str) -> Option<&CrossMarginTradingGroup> {
+        return self.groups.get(group_id);
+    }
+
+    pub fn update_group(&mut self, group: CrossMarginTradingGroup) {
+        self.revision += 1;
+        self.groups.insert(group.id.clone(), group);
+    }
+
+    pub fn remove_group(&mut self, group_id: &str) -> Option<CrossMarginTradingGroup> {
+        self.revision += 1;
+        return self.groups.remove(group_id);
+    }
+
+    pub fn get_revision(&self) -> u64 {
+        return self.revision;
+    }
+
+    pub fn get_all(&self) -> Vec<&CrossMarginTradingGroup> {
+        return self.groups.values().collect();
+    }
+
+    pub fn get_account_group(
+        &self,
+        account: &impl CrossMarginAccount,
+    ) -> Result<&CrossMarginTradingGroup, CrossMarginError> {
+        return self.groups.get(account.get_trading_group()).ok_or(
+            CrossMarginError::TradingGroupNotFound(account.get_trading_group().to_string()),
+        );
+    }
+
+    pub fn get_leverage(&self, account: &impl CrossMarginAccount) -> Result<f64, CrossMarginError> {
+        let group = self.get_account_group(account)?;
+
+        return Ok(account.get_leverage().unwrap_or(group.leverage));
+    }
+
+    pub fn get_instrument_leverage(
+        &self,
+        account: &impl CrossMarginAccount,
+        instrument_id: &str,
+    ) -> Result<Option<f64>, CrossMarginError> {
+        let group = self.get_account_group(account)?;
+
+        if let Some(leverage) = account.get_instruments_leverages().get(instrument_id) {
+            return Ok(Some(*leverage));
+        }
+
+        return Ok(group.instruments_leverages.get(instrument_id).cloned());
+    }
+
+    pub fn get_stop_out(&self, account: &impl CrossMarginAccount) -> Result<f64, CrossMarginError> {
+        let group = self.get_account_group(account)?;
+
+        return Ok(account.get_stop_out().unwrap_or(group.stop_out));
+    }
+
+    pub fn get_margin_call(
+        &self,
+        account: &impl CrossMarginAccount,
+    ) -> Result<f64, CrossMarginError> {
+        let group = self.get_account_group(account)?;
+
+        return Ok(account.get_margin_call().unwrap_or(group.margin_call));
+    }
+
+    pub fn get_hedge_mode(
+        &self,
+        account: &impl CrossMarginAccount,
+    ) -> Result<CrossMarginHedgeMode, CrossMarginError> {
+        return Ok(self.get_account_group(account)?.hedge_mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::TestAccount, trading_groups::CrossMarginTradingGroup, CrossMarginError,
+    };
+
+    use super::CrossMarginTradingGroupsCache;
+
+    #[test]
+    fn test_account_values_override_group() {
+        let mut group = CrossMarginTradingGroup::new("default");
+        group.leverage = 50.0;
+        group.stop_out = 30.0;
+        group
+            .instruments_leverages
+            .insert("EURUSD".to_string(), 20.0);
+        let mut cache = CrossMarginTradingGroupsCache::new(vec![group.clone()]);
+
+        let mut account = TestAccount::new("acc", 1000.0, 100.0);
+        account.leverage = None;
+        account.stop_out = None;
+
+        assert_eq!(cache.get_leverage(&account).unwrap(), 50.0);
+        assert_eq!(cache.get_stop_out(&account).unwrap(), 30.0);
+        assert_eq!(
+            cache.get_instrument_leverage(&account, "EURUSD").unwrap(),
+            Some(20.0)
+        );
+
+        account.leverage = Some(100.0);
+        account
+            .instruments_leverages
+            .insert("EURUSD".to_string(), 10.0);
+        assert_eq!(cache.get_leverage(&account).unwrap(), 100.0);
+        assert_eq!(
+            cache.get_instrument_leverage(&account, "EURUSD").unwrap(),
+            Some(10.0)
+        );
+
+        group.stop_out = 40.0;
+        cache.update_group(group);
+        assert_eq!(cache.get_stop_out(&account).unwrap(), 40.0);
+
+        account.trading_group = "unknown".to_string();
+        assert!(matches!(
+            cache.get_leverage(&account),
+            Err(CrossMarginError::TradingGroupNotFound(group)) if group == "unknown"
+        ));
+    }
+}
@@ -0,0 +1,5 @@
+mod spread_markup;
+mod trading_group;
+
+pub use spread_markup::*;
+pub use trading_group::*;
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+use crate::CrossMarginBidAsk;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CrossMarginSpreadMarkupType {
+    // Markup is a number of instrument ticks.
+    Points = 0,
+    // Markup is a percent of the raw price.
+    Percentage = 1,
+}
+
+// Bid is lowered by bid markup and ask is raised by ask markup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginSpreadMarkup {
+    pub markup_type: CrossMarginSpreadMarkupType,
+    pub bid: f64,
+    pub ask: f64,
+}
+
+impl CrossMarginSpreadMarkup {
+    // Points markup needs the instrument tick size, it is skipped for instruments without spec.
+    pub fn apply(&self, bid_ask: &CrossMarginBidAsk, tick_size: Option<f64>) -> CrossMarginBidAsk {
+        let (bid_markup, ask_markup) = match self.markup_type {
+            CrossMarginSpreadMarkupType::Points => match tick_size {
+                Some(tick_size) => (self.bid * tick_size, self.ask * tick_size),
+                None => (0.0, 0.0),
+            },
+            CrossMarginSpreadMarkupType::Percentage => (
+                bid_ask.bid * self.bid / 100.0,
+                bid_ask.ask * self.ask / 100.0,
+            ),
+        };
+
+        let mut result = bid_ask.clone();
+        result.bid -= bid_markup;
+        result.ask += ask_markup;
+
+        return result;
+    }
+}
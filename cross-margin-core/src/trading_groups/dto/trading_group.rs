@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::commissions::CrossMarginCommissionRule;
+
+use super::CrossMarginSpreadMarkup;
+
+pub const DEFAULT_TRADING_GROUP_LEVERAGE: f64 = 1.0;
+pub const DEFAULT_TRADING_GROUP_STOP_OUT: f64 = 50.0;
+pub const DEFAULT_TRADING_GROUP_MARGIN_CALL: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossMarginHedgeMode {
+    // Opposite positions are margined with the averaged margin rate.
+    Hedging = 0,
+    // Only the net volume of the instrument is margined.
+    Netting = 1,
+    // Every position is margined in full.
+    Gross = 2,
+}
+
+// Execution price of SL/TP when the market price has gapped through the level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossMarginGapFillPolicy {
+    Market = 0,
+    // Guaranteed fill at the requested level.
+    Level = 1,
+    // Better of the level and the market price for the trader.
+    BestPrice = 2,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossMarginTradingGroup {
+    pub id: String,
+    pub leverage: f64,
+    pub instruments_leverages: HashMap<String, f64>,
+    pub stop_out: f64,
+    pub margin_call: f64,
+    pub spread_markup: Option<CrossMarginSpreadMarkup>,
+    pub commissions: Vec<CrossMarginCommissionRule>,
+    pub hedge_mode: CrossMarginHedgeMode,
+    pub sl_gap_policy: CrossMarginGapFillPolicy,
+    pub tp_gap_policy: CrossMarginGapFillPolicy,
+}
+
+impl CrossMarginTradingGroup {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            leverage: DEFAULT_TRADING_GROUP_LEVERAGE,
+            instruments_leverages: HashMap::new(),
+            stop_out: DEFAULT_TRADING_GROUP_STOP_OUT,
+            margin_call: DEFAULT_TRADING_GROUP_MARGIN_CALL,
+            spread_markup: None,
+            commissions: vec![],
+            hedge_mode: CrossMarginHedgeMode::Hedging,
+            sl_gap_policy: CrossMarginGapFillPolicy::Market,
+            tp_gap_policy: CrossMarginGapFillPolicy::Market,
+        }
+    }
+
+    // Instrument rule has priority over the group default.
+    pub fn get_commission_rule(&self, instrument_id: &str) -> Option<&CrossMarginCommissionRule> {
+        return self
+            .commissions
+            .iter()
+            .find(|x| x.instrument_id.as_deref() == Some(instrument_id))
+            .or_else(|| self.commissions.iter().find(|x| x.instrument_id.is_none()));
+    }
+}
@@ -0,0 +1,5 @@
+mod dto;
+mod cache;
+
+pub use dto::*;
+pub use cache::*;
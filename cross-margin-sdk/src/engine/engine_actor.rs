@@ -0,0 +1,615 @@
+use cross_margin_core::{
+    CrossMarginAccount, CrossMarginActivePosition, CrossMarginCacheHandleBidAskResult,
+    CrossMarginCaches, CrossMarginCachesBuilder, CrossMarginError, CrossMarginPendingPosition,
+    CrossMarginPositionsCacheQueryBuilder,
+};
+use tokio::{
+    sync::{
+        mpsc::{
+            self,
+            error::{TryRecvError, TrySendError},
+        },
+        oneshot,
+    },
+    task::JoinHandle,
+};
+
+use super::{
+    CrossMarginEngineCommand, CrossMarginEngineHandle, CrossMarginEngineSnapshot,
+    CrossMarginEngineTick,
+};
+
+#[derive(Debug, Clone)]
+pub struct CrossMarginEngineServiceSettings {
+    pub ticks_queue_capacity: usize,
+    pub commands_queue_capacity: usize,
+    pub max_ticks_per_batch: usize,
+    // Commands served after every ticks batch, so a ticks flood doesn't starve them.
+    pub max_commands_per_batch: usize,
+}
+
+impl Default for CrossMarginEngineServiceSettings {
+    fn default() -> Self {
+        Self {
+            ticks_queue_capacity: 10_000,
+            commands_queue_capacity: 1_000,
+            max_ticks_per_batch: 1_000,
+            max_commands_per_batch: 100,
+        }
+    }
+}
+
+// Owns the caches, queued ticks are handled as one batch followed by the queued commands.
+pub struct CrossMarginEngine<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    caches: CrossMarginCaches<A, AP, PP>,
+    ticks: mpsc::Receiver<CrossMarginEngineTick>,
+    commands: mpsc::Receiver<CrossMarginEngineCommand<A, AP, PP>>,
+    tick_results: Option<mpsc::Sender<CrossMarginCacheHandleBidAskResult<AP, PP>>>,
+    // Results produced while tick_results was full, merged and sent once it has capacity.
+    unsent_tick_result: Option<CrossMarginCacheHandleBidAskResult<AP, PP>>,
+    settings: CrossMarginEngineServiceSettings,
+    shutdown_replies: Vec<oneshot::Sender<CrossMarginEngineSnapshot<A, AP, PP>>>,
+}
+
+impl<A, AP, PP> CrossMarginEngine<A, AP, PP>
+where
+    A: CrossMarginAccount + Send + Sync + 'static,
+    AP: CrossMarginActivePosition + Send + Sync + 'static,
+    PP: CrossMarginPendingPosition + Send + Sync + 'static,
+{
+    // Tick results are sent to tick_results when it is set, results are never dropped: while it is
+    // full they are merged into one result sent when the consumer catches up.
+    pub fn spawn(
+        caches: CrossMarginCaches<A, AP, PP>,
+        settings: CrossMarginEngineServiceSettings,
+        tick_results: Option<mpsc::Sender<CrossMarginCacheHandleBidAskResult<AP, PP>>>,
+    ) -> (
+        CrossMarginEngineHandle<A, AP, PP>,
+        JoinHandle<CrossMarginEngineSnapshot<A, AP, PP>>,
+    ) {
+        let (ticks_sender, ticks) = mpsc::channel(settings.ticks_queue_capacity);
+        let (commands_sender, commands) = mpsc::channel(settings.commands_queue_capacity);
+
+        let engine = Self {
+            caches,
+            ticks,
+            commands,
+            tick_results,
+            unsent_tick_result: None,
+            settings,
+            shutdown_replies: vec![],
+        };
+
+        let join_handle = tokio::spawn(engine.run());
+
+        return (
+            CrossMarginEngineHandle::new(ticks_sender, commands_sender),
+            join_handle,
+        );
+    }
+
+    // Registries and settings are set on the builder, the rest is restored from the snapshot.
+    pub async fn from_snapshot(
+        snapshot: CrossMarginEngineSnapshot<A, AP, PP>,
+        builder: CrossMarginCachesBuilder<A, AP, PP>,
+        settings: CrossMarginEngineServiceSettings,
+        tick_results: Option<mpsc::Sender<CrossMarginCacheHandleBidAskResult<AP, PP>>>,
+    ) -> Result<
+        (
+            CrossMarginEngineHandle<A, AP, PP>,
+            JoinHandle<CrossMarginEngineSnapshot<A, AP, PP>>,
+        ),
+        CrossMarginError,
+    > {
+        let caches = builder.with_snapshot(snapshot).build().await?;
+        return Ok(Self::spawn(caches, settings, tick_results));
+    }
+
+    async fn run(mut self) -> CrossMarginEngineSnapshot<A, AP, PP> {
+        loop {
+            let is_running = tokio::select! {
+                biased;
+                Some(permit) = reserve_tick_result(
+                    self.tick_results.clone(),
+                    self.unsent_tick_result.is_some(),
+                ) => {
+                    permit.send(self.unsent_tick_result.take().unwrap());
+                    true
+                }
+                Some(tick) = self.ticks.recv() => {
+                    self.handle_ticks(tick).await;
+                    self.handle_queued_commands().await
+                }
+                command = self.commands.recv() => self.dispatch(command).await,
+            };
+
+            self.report_queue_depth();
+
+            if !is_running {
+                break;
+            }
+        }
+
+        // already queued ticks and commands are completed before the snapshot
+        self.ticks.close();
+        self.commands.close();
+
+        while let Ok(tick) = self.ticks.try_recv() {
+            self.handle_ticks(tick).await;
+        }
+
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                CrossMarginEngineCommand::Shutdown { reply } => self.shutdown_replies.push(reply),
+                command => self.handle_command(command).await,
+            }
+        }
+
+        self.report_queue_depth();
+
+        // the consumer gets every result before the engine stops
+        if let (Some(tick_results), Some(result)) =
+            (&self.tick_results, self.unsent_tick_result.take())
+        {
+            let _ = tick_results.send(result).await;
+        }
+
+        let snapshot = CrossMarginEngineSnapshot::new(&self.caches).await;
+
+        for reply in self.shutdown_replies.drain(..) {
+            let _ = reply.send(snapshot.clone());
+        }
+
+        return snapshot;
+    }
+
+    // Returns false when the engine is shut down or all handles are dropped.
+    async fn dispatch(&mut self, command: Option<CrossMarginEngineCommand<A, AP, PP>>) -> bool {
+        match command {
+            Some(CrossMarginEngineCommand::Shutdown { reply }) => {
+                self.shutdown_replies.push(reply);
+                return false;
+            }
+            Some(command) => {
+                self.handle_command(command).await;
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    async fn handle_queued_commands(&mut self) -> bool {
+        for _ in 0..self.settings.max_commands_per_batch {
+            let command = match self.commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => None,
+            };
+
+            if !self.dispatch(command).await {
+                return false;
+            }
+        }
+
+        return true;
+    }
+
+    fn report_queue_depth(&self) {
+        metrics::gauge!("cross_margin_engine_queue_depth", "queue" => "ticks")
+            .set(self.ticks.len() as f64);
+        metrics::gauge!("cross_margin_engine_queue_depth", "queue" => "commands")
+            .set(self.commands.len() as f64);
+    }
+
+    async fn handle_ticks(&mut self, tick: CrossMarginEngineTick) {
+        let first_process_id = tick.process_id;
+        let mut last_process_id = None;
+        let mut prices = vec![tick.bid_ask];
+
+        while prices.len() < self.settings.max_ticks_per_batch {
+            let Ok(tick) = self.ticks.try_recv() else {
+                break;
+            };
+
+            prices.push(tick.bid_ask);
+            last_process_id = Some(tick.process_id);
+        }
+
+        // ticks are queued in order, so the first and the last process ids identify the batch
+        let process_id = match last_process_id {
+            Some(last_process_id) => format!("{}..{}", first_process_id, last_process_id),
+            None => first_process_id,
+        };
+
+        let result = self.caches.handle_bid_ask_batch(prices, &process_id).await;
+        self.send_tick_result(result);
+    }
+
+    // The engine never waits for a lagging consumer, results it can't take yet are merged.
+    fn send_tick_result(&mut self, result: CrossMarginCacheHandleBidAskResult<AP, PP>) {
+        let Some(tick_results) = &self.tick_results else {
+            return;
+        };
+
+        let result = match self.unsent_tick_result.take() {
+            Some(mut unsent) => {
+                unsent.merge(result);
+                unsent
+            }
+            None => result,
+        };
+
+        match tick_results.try_send(result) {
+            Ok(()) => {}
+            Err(TrySendError::Full(result)) => {
+                metrics::counter!("cross_margin_engine_merged_tick_results").increment(1);
+                self.unsent_tick_result = Some(result);
+            }
+            Err(TrySendError::Closed(_)) => self.tick_results = None,
+        }
+    }
+
+    async fn handle_command(&mut self, command: CrossMarginEngineCommand<A, AP, PP>) {
+        // a dropped reply receiver means the caller is not interested in the result
+        match command {
+            CrossMarginEngineCommand::OpenPosition {
+                position,
+                process_id,
+                reply,
+            } => {
+                let result = self.caches.add_active_position(position, &process_id).await;
+                let _ = reply.send(result);
+            }
+            CrossMarginEngineCommand::ClosePosition {
+                position_id,
+                process_id,
+                expected_version,
+                reply,
+            } => {
+                let result = self
+                    .caches
+                    .remove_active_position(&position_id, &process_id, expected_version)
+                    .await;
+                let _ = reply.send(result);
+            }
+            CrossMarginEngineCommand::ModifyPosition {
+                position_id,
+                sl_price,
+                sl_profit,
+                tp_price,
+                tp_profit,
+                process_id,
+                expected_version,
+                reply,
+            } => {
+                let result = self
+                    .caches
+                    .update_active_position_sl_tp(
+                        &position_id,
+                        sl_price,
+                        sl_profit,
+                        tp_price,
+                        tp_profit,
+                        &process_id,
+                        expected_version,
+                    )
+                    .await;
+                let _ = reply.send(result);
+            }
+            CrossMarginEngineCommand::PlacePendingPosition {
+                position,
+                process_id,
+                reply,
+            } => {
+                let result = self
+                    .caches
+                    .add_pending_position(position, &process_id)
+                    .await;
+                let _ = reply.send(result);
+            }
+            CrossMarginEngineCommand::CancelPendingPosition {
+                position_id,
+                process_id,
+                reply,
+            } => {
+                let result = self
+                    .caches
+                    .remove_pending_position(&position_id, &process_id)
+                    .await;
+                let _ = reply.send(result);
+            }
+            CrossMarginEngineCommand::UpdateBalance {
+                account_id,
+                delta,
+                process_id,
+                expected_version,
+                reply,
+            } => {
+                let result = self
+                    .caches
+                    .update_account_balance(
+                        &account_id,
+                        delta,
+                        &process_id,
+                        false,
+                        expected_version,
+                    )
+                    .await;
+                let _ = reply.send(result);
+            }
+            CrossMarginEngineCommand::GetAccount { account_id, reply } => {
+                let account = self.caches.accounts_cache.get_account(&account_id).cloned();
+                let _ = reply.send(account);
+            }
+            CrossMarginEngineCommand::GetAccountSummary { account_id, reply } => {
+                let _ = reply.send(self.caches.get_account_summary(&account_id));
+            }
+            CrossMarginEngineCommand::GetAccountPositions { account_id, reply } => {
+                let positions = self
+                    .caches
+                    .active_positions_cache
+                    .query_positions(
+                        CrossMarginPositionsCacheQueryBuilder::new().with_account(&account_id),
+                    )
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                let _ = reply.send(positions);
+            }
+            CrossMarginEngineCommand::Shutdown { reply } => {
+                self.shutdown_replies.push(reply);
+            }
+        }
+    }
+}
+
+// Resolves when there is an unsent result and the consumer has capacity for it.
+async fn reserve_tick_result<T>(
+    tick_results: Option<mpsc::Sender<T>>,
+    has_unsent_result: bool,
+) -> Option<mpsc::OwnedPermit<T>> {
+    if !has_unsent_result {
+        return None;
+    }
+
+    return tick_results?.reserve_owned().await.ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use cross_margin_core::{
+        test_utils::{
+            bid_ask, create_caches, default_trading_groups, eurusd, TestAccount,
+            TestActivePosition, TestPendingPosition,
+        },
+        CrossMarginCachesBuilder, CrossMarginPendingPositionType, CrossMarginPositionSide,
+    };
+
+    use crate::CrossMarginEngineError;
+
+    use super::*;
+
+    type TestEngine = CrossMarginEngine<TestAccount, TestActivePosition, TestPendingPosition>;
+
+    fn create_position(id: &str) -> TestActivePosition {
+        let mut position = TestActivePosition::new(
+            id,
+            "acc",
+            &eurusd(),
+            CrossMarginPositionSide::Buy,
+            100.0,
+            1.1002,
+        );
+        position.sl_price = Some(1.05);
+        return position;
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_executed_in_order() {
+        let caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        let (handle, _) = TestEngine::spawn(caches, Default::default(), None);
+
+        // both commands are queued before the engine runs, the second expects the first version
+        let (first, second) = tokio::join!(
+            handle.update_balance("acc", 100.0, "first", Some(0)),
+            handle.update_balance("acc", -50.0, "second", Some(1)),
+        );
+
+        assert_eq!(first.unwrap().balance, 1100.0);
+        assert_eq!(second.unwrap().balance, 1050.0);
+    }
+
+    #[tokio::test]
+    async fn test_queued_ticks_are_handled_as_one_batch() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches
+            .add_active_position(create_position("position"), "open")
+            .await
+            .unwrap();
+
+        let (results_sender, mut results) = mpsc::channel(10);
+        let (handle, _) = TestEngine::spawn(caches, Default::default(), Some(results_sender));
+
+        // the engine task runs when the test awaits the result, so the ticks are queued together
+        for (index, bid) in [1.04, 1.1].into_iter().enumerate() {
+            handle
+                .handle_bid_ask(
+                    bid_ask(&instrument, bid, bid + 0.0002),
+                    &format!("tick-{}", index),
+                )
+                .await
+                .unwrap();
+        }
+
+        // sl is touched by the skipped tick
+        let result = results.recv().await.unwrap();
+        assert_eq!(result.closed_positions.len(), 1);
+
+        let account = handle.get_account("acc").await.unwrap().unwrap();
+        assert_eq!(account.last_process_id.as_deref(), Some("tick-0..tick-1"));
+
+        handle.shutdown().await.unwrap();
+        assert!(results.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_served_between_tick_batches() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches
+            .add_active_position(create_position("position"), "open")
+            .await
+            .unwrap();
+
+        let settings = CrossMarginEngineServiceSettings {
+            max_ticks_per_batch: 1,
+            ..Default::default()
+        };
+        let (handle, _) = TestEngine::spawn(caches, settings, None);
+
+        for bid in [1.1, 1.04, 1.04] {
+            handle
+                .handle_bid_ask(bid_ask(&instrument, bid, bid + 0.0002), "tick")
+                .await
+                .unwrap();
+        }
+
+        // queued after all ticks, the query is served after the first batch
+        let positions = handle.get_account_positions("acc").await.unwrap();
+        assert_eq!(positions.len(), 1);
+
+        let snapshot = handle.shutdown().await.unwrap();
+        assert!(snapshot.active_positions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tick_results_are_not_dropped_for_lagging_consumer() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+
+        for (id, sl) in [("first", 1.05), ("second", 1.04), ("third", 1.03)] {
+            let mut position = create_position(id);
+            position.sl_price = Some(sl);
+            caches.add_active_position(position, "open").await.unwrap();
+        }
+
+        let settings = CrossMarginEngineServiceSettings {
+            max_ticks_per_batch: 1,
+            ..Default::default()
+        };
+        let (results_sender, mut results) = mpsc::channel(1);
+        let (handle, _) = TestEngine::spawn(caches, settings, Some(results_sender));
+
+        // every tick closes one position, the consumer doesn't read until the shutdown
+        for bid in [1.049, 1.039, 1.029] {
+            handle
+                .handle_bid_ask(bid_ask(&instrument, bid, bid + 0.0002), "tick")
+                .await
+                .unwrap();
+        }
+
+        let (snapshot, closed) = tokio::join!(handle.shutdown(), async {
+            let mut closed = vec![];
+
+            while let Some(result) = results.recv().await {
+                closed.extend(result.closed_positions.into_iter().map(|x| x.0.id));
+            }
+
+            closed
+        });
+
+        assert!(snapshot.unwrap().active_positions.is_empty());
+        assert_eq!(closed, vec!["first", "second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_completes_queued_commands() {
+        let caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        let (handle, join_handle) = TestEngine::spawn(caches, Default::default(), None);
+
+        let (account, snapshot) = tokio::join!(
+            handle.update_balance("acc", 100.0, "deposit", None),
+            handle.shutdown(),
+        );
+
+        assert_eq!(account.unwrap().balance, 1100.0);
+        assert_eq!(snapshot.unwrap().accounts[0].balance, 1100.0);
+        assert_eq!(join_handle.await.unwrap().accounts[0].balance, 1100.0);
+        assert!(matches!(
+            handle.get_account("acc").await,
+            Err(CrossMarginEngineError::Stopped)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_engine_is_restored_from_snapshot() {
+        let instrument = eurusd();
+        let mut caches = create_caches(vec![TestAccount::new("acc", 1000.0, 100.0)]).await;
+        caches.settings.reserve_pending_margin = true;
+        caches.settings.idempotency_window = 100;
+        let settings = caches.settings.clone();
+        let (handle, _) = TestEngine::spawn(caches, Default::default(), None);
+
+        handle
+            .open_position(create_position("position"), "open")
+            .await
+            .unwrap();
+        handle
+            .place_pending_position(
+                TestPendingPosition::new(
+                    "order",
+                    "acc",
+                    &instrument,
+                    CrossMarginPendingPositionType::BuyLimit,
+                    100.0,
+                    1.05,
+                ),
+                "place",
+            )
+            .await
+            .unwrap();
+        handle
+            .update_balance("acc", 100.0, "deposit", None)
+            .await
+            .unwrap();
+        let snapshot = handle.shutdown().await.unwrap();
+        assert_eq!(snapshot.margin_reservations.len(), 1);
+
+        let builder = CrossMarginCachesBuilder::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![instrument.clone()],
+            vec!["USD".to_string()],
+            vec![],
+        )
+        .with_trading_groups_cache(default_trading_groups())
+        .with_settings(settings);
+        let (handle, _) =
+            TestEngine::from_snapshot(snapshot.clone(), builder, Default::default(), None)
+                .await
+                .unwrap();
+
+        // the retried deposit returns the result applied before the snapshot
+        let account = handle
+            .update_balance("acc", 100.0, "deposit", None)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, 1100.0);
+
+        let restored = handle.shutdown().await.unwrap();
+        assert_eq!(restored.accounts[0].balance, 1100.0);
+        assert_eq!(restored.active_positions.len(), 1);
+        assert_eq!(restored.pending_positions.len(), 1);
+        assert_eq!(
+            restored.margin_reservations[0].margin,
+            snapshot.margin_reservations[0].margin
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use cross_margin_core::{
+    AccountCalculationResult, CrossMarginAccount, CrossMarginActivePosition, CrossMarginBidAsk,
+    CrossMarginCommissionBreakdown, CrossMarginError, CrossMarginPendingPosition,
+    CrossMarginPositionSettlement,
+};
+use tokio::sync::oneshot;
+
+use super::CrossMarginEngineSnapshot;
+
+pub type CrossMarginEngineReply<T> = oneshot::Sender<Result<T, CrossMarginError>>;
+
+#[derive(Debug, Clone)]
+pub struct CrossMarginEngineTick {
+    pub bid_ask: CrossMarginBidAsk,
+    pub process_id: String,
+}
+
+pub enum CrossMarginEngineCommand<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    OpenPosition {
+        position: AP,
+        process_id: String,
+        reply: CrossMarginEngineReply<Option<CrossMarginCommissionBreakdown>>,
+    },
+    ClosePosition {
+        position_id: String,
+        process_id: String,
+        expected_version: Option<u64>,
+        reply: CrossMarginEngineReply<(AP, A, CrossMarginPositionSettlement)>,
+    },
+    ModifyPosition {
+        position_id: String,
+        sl_price: Option<f64>,
+        sl_profit: Option<f64>,
+        tp_price: Option<f64>,
+        tp_profit: Option<f64>,
+        process_id: String,
+        expected_version: Option<u64>,
+        reply: CrossMarginEngineReply<AP>,
+    },
+    PlacePendingPosition {
+        position: PP,
+        process_id: String,
+        reply: CrossMarginEngineReply<()>,
+    },
+    CancelPendingPosition {
+        position_id: String,
+        process_id: String,
+        reply: CrossMarginEngineReply<PP>,
+    },
+    // Deposits and withdrawals.
+    UpdateBalance {
+        account_id: String,
+        delta: f64,
+        process_id: String,
+        expected_version: Option<u64>,
+        reply: CrossMarginEngineReply<A>,
+    },
+    GetAccount {
+        account_id: String,
+        reply: oneshot::Sender<Option<A>>,
+    },
+    GetAccountSummary {
+        account_id: String,
+        reply: CrossMarginEngineReply<AccountCalculationResult>,
+    },
+    GetAccountPositions {
+        account_id: String,
+        reply: oneshot::Sender<Vec<AP>>,
+    },
+    Shutdown {
+        reply: oneshot::Sender<CrossMarginEngineSnapshot<A, AP, PP>>,
+    },
+}
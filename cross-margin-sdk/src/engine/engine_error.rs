@@ -0,0 +1,14 @@
+use cross_margin_core::CrossMarginError;
+
+#[derive(Debug)]
+pub enum CrossMarginEngineError {
+    // Engine was shut down, the command was not executed.
+    Stopped,
+    Engine(CrossMarginError),
+}
+
+impl From<CrossMarginError> for CrossMarginEngineError {
+    fn from(value: CrossMarginError) -> Self {
+        return CrossMarginEngineError::Engine(value);
+    }
+}
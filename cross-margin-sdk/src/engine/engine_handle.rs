@@ -0,0 +1,238 @@
+use cross_margin_core::{
+    AccountCalculationResult, CrossMarginAccount, CrossMarginActivePosition, CrossMarginBidAsk,
+    CrossMarginCommissionBreakdown, CrossMarginError, CrossMarginPendingPosition,
+    CrossMarginPositionSettlement,
+};
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    CrossMarginEngineCommand, CrossMarginEngineError, CrossMarginEngineSnapshot,
+    CrossMarginEngineTick,
+};
+
+pub struct CrossMarginEngineHandle<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    ticks: mpsc::Sender<CrossMarginEngineTick>,
+    commands: mpsc::Sender<CrossMarginEngineCommand<A, AP, PP>>,
+}
+
+impl<A, AP, PP> Clone for CrossMarginEngineHandle<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    fn clone(&self) -> Self {
+        Self {
+            ticks: self.ticks.clone(),
+            commands: self.commands.clone(),
+        }
+    }
+}
+
+impl<A, AP, PP> CrossMarginEngineHandle<A, AP, PP>
+where
+    A: CrossMarginAccount,
+    AP: CrossMarginActivePosition,
+    PP: CrossMarginPendingPosition,
+{
+    pub fn new(
+        ticks: mpsc::Sender<CrossMarginEngineTick>,
+        commands: mpsc::Sender<CrossMarginEngineCommand<A, AP, PP>>,
+    ) -> Self {
+        Self { ticks, commands }
+    }
+
+    pub fn get_ticks_queue_depth(&self) -> usize {
+        return self.ticks.max_capacity() - self.ticks.capacity();
+    }
+
+    pub fn get_commands_queue_depth(&self) -> usize {
+        return self.commands.max_capacity() - self.commands.capacity();
+    }
+
+    // Waits for queue space, results are delivered to the tick results channel.
+    pub async fn handle_bid_ask(
+        &self,
+        bid_ask: CrossMarginBidAsk,
+        process_id: &str,
+    ) -> Result<(), CrossMarginEngineError> {
+        return self
+            .ticks
+            .send(CrossMarginEngineTick {
+                bid_ask,
+                process_id: process_id.to_string(),
+            })
+            .await
+            .map_err(|_| CrossMarginEngineError::Stopped);
+    }
+
+    pub async fn open_position(
+        &self,
+        position: AP,
+        process_id: &str,
+    ) -> Result<Option<CrossMarginCommissionBreakdown>, CrossMarginEngineError> {
+        return self
+            .execute(|reply| CrossMarginEngineCommand::OpenPosition {
+                position,
+                process_id: process_id.to_string(),
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn close_position(
+        &self,
+        position_id: &str,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<(AP, A, CrossMarginPositionSettlement), CrossMarginEngineError> {
+        return self
+            .execute(|reply| CrossMarginEngineCommand::ClosePosition {
+                position_id: position_id.to_string(),
+                process_id: process_id.to_string(),
+                expected_version,
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn modify_position(
+        &self,
+        position_id: &str,
+        sl_price: Option<f64>,
+        sl_profit: Option<f64>,
+        tp_price: Option<f64>,
+        tp_profit: Option<f64>,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<AP, CrossMarginEngineError> {
+        return self
+            .execute(|reply| CrossMarginEngineCommand::ModifyPosition {
+                position_id: position_id.to_string(),
+                sl_price,
+                sl_profit,
+                tp_price,
+                tp_profit,
+                process_id: process_id.to_string(),
+                expected_version,
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn place_pending_position(
+        &self,
+        position: PP,
+        process_id: &str,
+    ) -> Result<(), CrossMarginEngineError> {
+        return self
+            .execute(|reply| CrossMarginEngineCommand::PlacePendingPosition {
+                position,
+                process_id: process_id.to_string(),
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn cancel_pending_position(
+        &self,
+        position_id: &str,
+        process_id: &str,
+    ) -> Result<PP, CrossMarginEngineError> {
+        return self
+            .execute(|reply| CrossMarginEngineCommand::CancelPendingPosition {
+                position_id: position_id.to_string(),
+                process_id: process_id.to_string(),
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn update_balance(
+        &self,
+        account_id: &str,
+        delta: f64,
+        process_id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<A, CrossMarginEngineError> {
+        return self
+            .execute(|reply| CrossMarginEngineCommand::UpdateBalance {
+                account_id: account_id.to_string(),
+                delta,
+                process_id: process_id.to_string(),
+                expected_version,
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn get_account(&self, account_id: &str) -> Result<Option<A>, CrossMarginEngineError> {
+        return self
+            .request(|reply| CrossMarginEngineCommand::GetAccount {
+                account_id: account_id.to_string(),
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn get_account_summary(
+        &self,
+        account_id: &str,
+    ) -> Result<AccountCalculationResult, CrossMarginEngineError> {
+        return self
+            .execute(|reply| CrossMarginEngineCommand::GetAccountSummary {
+                account_id: account_id.to_string(),
+                reply,
+            })
+            .await;
+    }
+
+    pub async fn get_account_positions(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<AP>, CrossMarginEngineError> {
+        return self
+            .request(|reply| CrossMarginEngineCommand::GetAccountPositions {
+                account_id: account_id.to_string(),
+                reply,
+            })
+            .await;
+    }
+
+    // Already queued ticks and commands are completed before the snapshot is taken.
+    pub async fn shutdown(
+        &self,
+    ) -> Result<CrossMarginEngineSnapshot<A, AP, PP>, CrossMarginEngineError> {
+        return self
+            .request(|reply| CrossMarginEngineCommand::Shutdown { reply })
+            .await;
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> CrossMarginEngineCommand<A, AP, PP>,
+    ) -> Result<T, CrossMarginEngineError> {
+        let (reply, receiver) = oneshot::channel();
+
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| CrossMarginEngineError::Stopped)?;
+
+        return receiver.await.map_err(|_| CrossMarginEngineError::Stopped);
+    }
+
+    async fn execute<T>(
+        &self,
+        command: impl FnOnce(
+            oneshot::Sender<Result<T, CrossMarginError>>,
+        ) -> CrossMarginEngineCommand<A, AP, PP>,
+    ) -> Result<T, CrossMarginEngineError> {
+        return Ok(self.request(command).await??);
+    }
+}
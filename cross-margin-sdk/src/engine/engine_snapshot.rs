@@ -0,0 +1,4 @@
+use cross_margin_core::CrossMarginCachesSnapshot;
+
+// State required to start the engine again, taken on shutdown.
+pub type CrossMarginEngineSnapshot<A, AP, PP> = CrossMarginCachesSnapshot<A, AP, PP>;
@@ -0,0 +1,11 @@
+mod engine_actor;
+mod engine_command;
+mod engine_error;
+mod engine_handle;
+mod engine_snapshot;
+
+pub use engine_actor::*;
+pub use engine_command::*;
+pub use engine_error::*;
+pub use engine_handle::*;
+pub use engine_snapshot::*;
@@ -1 +1,6 @@
-pub extern crate cross_margin_core as core;
\ No newline at end of file
+// Re-exported with `use` as an `extern crate` alias named core shadows `::core` in macros.
+pub use cross_margin_core as core;
+
+mod engine;
+
+pub use engine::*;